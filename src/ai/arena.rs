@@ -0,0 +1,433 @@
+// 两个 AI 配置之间的自对弈比赛：交换先后手，可以从随机开局开始，统计胜负、估算 Elo 差并用 SPRT 提前结束
+use std::{path::PathBuf, str::FromStr};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{ai::{mcts::MCTSAI, mctsv2::{MCTSConfig, MCTSv2}, Heuristic, SearchLimit, SearchSignal, AI}, general::*, hequn::general::HequnBoard, tree::game_tree::GameTree, xingxiang::general::XingxiangBoard, zhandi::general::ZhandiBoard};
+
+const USAGE: &str = "\
+usage: xinqi --arena <hequn|zhandi|xingxiang> --a <player> --b <player> [options]
+  <player>           mcts or mctsv2, followed by comma separated settings:
+                       time=<ms>     thinking time per move, default 1000
+                       sims=<n>      count simulations per move instead of time, for reproducible games
+                       c=<f>         exploration constant, default 1.414
+                       seed=<n>      random seed of the search, game i uses seed + i
+                     mctsv2 only:
+                       threads=<n>   search threads, default 1
+                       w=<f>         weight of the evaluation, default 5
+                       eval_temp=<f> temperature of the evaluation, default depends on the game
+                       depth=<n>     stop rollouts after n moves and use the evaluation
+                       select=<visits|winrate|t>  pick the final move by visits, win rate or sampling with temperature t
+                     e.g. --a mctsv2,time=500,w=2 --b mcts,time=500
+  --games <n>        number of games, default 100
+  --opening <n>      play n random moves before each pair of games, default 0
+  --max-moves <n>    count the game as a draw after this many moves
+  --sprt <elo0,elo1> stop early once the Elo difference is known to be below elo0 or above elo1
+  --alpha <f>        SPRT false positive rate, default 0.05
+  --beta <f>         SPRT false negative rate, default 0.05
+  --seed <n>         seed of the random openings
+  --out <path>       save all games to this .pgn file";
+
+// 生成随机开局时最多重试的次数
+const OPENING_TRIES: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AIKind {
+    Mcts,
+    Mctsv2,
+}
+
+// 参加比赛的一方
+#[derive(Clone)]
+pub struct PlayerConfig {
+    pub kind: AIKind,
+    pub limit: SearchLimit,
+    pub threads: usize,
+    pub config: MCTSConfig,
+    pub name: String,
+}
+
+fn parse_number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value \"{}\" for {}", value, option))
+}
+
+impl PlayerConfig {
+    // "mctsv2,time=500,c=1.0,w=5,threads=2"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(',').map(str::trim);
+        let kind = match parts.next().unwrap_or_default() {
+            "mcts" => AIKind::Mcts,
+            "mctsv2" => AIKind::Mctsv2,
+            kind => return Err(format!("unknown AI \"{}\", expected mcts or mctsv2", kind)),
+        };
+        let mut player = Self {
+            kind,
+            limit: SearchLimit::Time(1000),
+            threads: 1,
+            config: MCTSConfig::default(),
+            name: spec.to_string(),
+        };
+        for part in parts {
+            let Some((key, value)) = part.split_once('=') else {
+                return Err(format!("expected <key>=<value>, found \"{}\"", part));
+            };
+            match (key, kind) {
+                ("time", _) => player.limit = SearchLimit::Time(parse_number(key, value)?),
+                ("sims", _) => player.limit = SearchLimit::Simulations(parse_number(key, value)?),
+                ("threads", AIKind::Mctsv2) => player.threads = parse_number(key, value)?,
+                (_, AIKind::Mctsv2) | ("c" | "seed", AIKind::Mcts) => player.config.set(key, value)?,
+                _ => return Err(format!("unknown setting \"{}\" for {}", key, spec)),
+            }
+        }
+        Ok(player)
+    }
+
+    // 每盘棋使用新的 AI，避免把上一盘的搜索树带进来。固定种子时第 round 盘使用 seed + round，
+    // 每盘棋不同，但整场比赛可以复现
+    fn build<B: Heuristic>(&self, round: u32) -> Box<dyn AI<B = B>> {
+        let seed = self.config.seed.map(|seed| seed.wrapping_add(round as u64));
+        match self.kind {
+            AIKind::Mcts => Box::new(MCTSAI::<B>::new()
+                .with_exploration(self.config.exploration)
+                .with_seed(seed)),
+            AIKind::Mctsv2 => Box::new(MCTSv2::<B>::default()
+                .with_config(MCTSConfig { seed, ..self.config })
+                .with_threads(self.threads)),
+        }
+    }
+}
+
+// SPRT 检验 H0: Elo 差为 elo0 与 H1: Elo 差为 elo1
+#[derive(Clone, Copy)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    // 对数似然比的下界和上界，越过下界接受 H0，越过上界接受 H1
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+}
+
+// 期望得分和 Elo 差的换算
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn score_to_elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+// 从 A 方看的比赛结果
+#[derive(Clone, Copy, Default)]
+pub struct ArenaResult {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl ArenaResult {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // 平均每盘得分，和棋计 0.5
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games() as f64
+    }
+
+    // 每盘得分的方差
+    fn variance(&self) -> f64 {
+        let n = self.games() as f64;
+        let s = self.score();
+        (self.wins as f64 * (1.0 - s).powi(2) + self.draws as f64 * (0.5 - s).powi(2) + self.losses as f64 * s.powi(2)) / n
+    }
+
+    // Elo 差的估计值和 95% 置信区间的半宽，全胜或全负时无法估计
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        if self.games() == 0 {
+            return None;
+        }
+        let s = self.score();
+        if s <= 0.0 || s >= 1.0 {
+            return None;
+        }
+        let margin = 1.96 * (self.variance() / self.games() as f64).sqrt();
+        let low = score_to_elo((s - margin).max(1e-6));
+        let high = score_to_elo((s + margin).min(1.0 - 1e-6));
+        Some((score_to_elo(s), (high - low) / 2.0))
+    }
+
+    // 正态近似下的对数似然比
+    pub fn llr(&self, sprt: &Sprt) -> f64 {
+        let variance = if self.games() == 0 { 0.0 } else { self.variance() };
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let s0 = elo_to_score(sprt.elo0);
+        let s1 = elo_to_score(sprt.elo1);
+        self.games() as f64 * (s1 - s0) * (2.0 * self.score() - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn summary(&self) -> String {
+        let elo = match self.elo() {
+            Some((elo, margin)) => format!("{:+.1} +/- {:.1}", elo, margin),
+            None => "n/a".to_string(),
+        };
+        format!(
+            "+{} ={} -{} ({} games), score {:.1}%, Elo {}",
+            self.wins, self.draws, self.losses, self.games(), self.score() * 100.0, elo,
+        )
+    }
+}
+
+struct Options {
+    game: String,
+    a: Option<PlayerConfig>,
+    b: Option<PlayerConfig>,
+    games: u32,
+    opening_plies: usize,
+    max_moves: Option<usize>,
+    sprt: Option<Sprt>,
+    seed: Option<u64>,
+    out: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter();
+        let game = args.next().ok_or("missing game name")?;
+        let mut options = Self {
+            game: game.clone(),
+            a: None,
+            b: None,
+            games: 100,
+            opening_plies: 0,
+            max_moves: None,
+            sprt: None,
+            seed: None,
+            out: None,
+        };
+        let mut alpha = 0.05;
+        let mut beta = 0.05;
+        while let Some(option) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", option));
+            match option.as_str() {
+                "--a" => options.a = Some(PlayerConfig::parse(value()?)?),
+                "--b" => options.b = Some(PlayerConfig::parse(value()?)?),
+                "--games" => options.games = parse_number(option, value()?)?,
+                "--opening" => options.opening_plies = parse_number(option, value()?)?,
+                "--max-moves" => options.max_moves = Some(parse_number(option, value()?)?),
+                "--sprt" => {
+                    let bounds = value()?;
+                    let Some((elo0, elo1)) = bounds.split_once(',') else {
+                        return Err(format!("expected <elo0>,<elo1> for --sprt, found \"{}\"", bounds));
+                    };
+                    options.sprt = Some(Sprt {
+                        elo0: parse_number(option, elo0)?,
+                        elo1: parse_number(option, elo1)?,
+                        alpha: 0.0,
+                        beta: 0.0,
+                    });
+                },
+                "--alpha" => alpha = parse_number(option, value()?)?,
+                "--beta" => beta = parse_number(option, value()?)?,
+                "--seed" => options.seed = Some(parse_number(option, value()?)?),
+                "--out" => options.out = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("unknown option {}", option)),
+            }
+        }
+        if let Some(sprt) = &mut options.sprt {
+            if sprt.elo0 >= sprt.elo1 {
+                return Err("--sprt needs elo0 < elo1".to_string());
+            }
+            if !(0.0 < alpha && alpha < 0.5 && 0.0 < beta && beta < 0.5) {
+                return Err("--alpha and --beta must be between 0 and 0.5".to_string());
+            }
+            sprt.alpha = alpha;
+            sprt.beta = beta;
+        }
+        Ok(options)
+    }
+}
+
+// cargo run --release -- --arena <game> --a <player> --b <player> [options]
+pub fn run(args: &[String]) {
+    let result = Options::parse(args).and_then(|options| match options.game.as_str() {
+        HequnBoard::NAME => run_arena::<HequnBoard>(&options),
+        ZhandiBoard::NAME => run_arena::<ZhandiBoard>(&options),
+        XingxiangBoard::NAME => run_arena::<XingxiangBoard>(&options),
+        game => Err(format!("unknown game \"{}\"", game)),
+    });
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+}
+
+fn run_arena<B: Heuristic>(options: &Options) -> Result<(), String> {
+    let (Some(a), Some(b)) = (&options.a, &options.b) else {
+        return Err("both --a and --b are required".to_string());
+    };
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_rng(&mut rand::rng()),
+    };
+    println!("A: {}", a.name);
+    println!("B: {}", b.name);
+
+    let mut result = ArenaResult::default();
+    let mut pgn = Vec::new();
+    let mut opening = Vec::new();
+    for round in 0..options.games {
+        // 每两盘使用同一个开局，A 先在第一盘执先手，第二盘交换
+        if round % 2 == 0 {
+            opening = random_opening::<B>(&mut rng, options.opening_plies)?;
+        }
+        let a_first = round % 2 == 0;
+        let (first, second) = if a_first { (a, b) } else { (b, a) };
+        let tree = play_game::<B>(first, second, &opening, options.max_moves, round + 1);
+        let outcome = tree.result();
+        let a_wins = if a_first { "1-0" } else { "0-1" };
+        match outcome.as_str() {
+            "1/2-1/2" => result.draws += 1,
+            r if r == a_wins => result.wins += 1,
+            _ => result.losses += 1,
+        }
+        pgn.push(tree.to_pgn());
+        println!(
+            "game {:>4}: {} vs {}: {:<7} {}",
+            round + 1, if a_first { "A" } else { "B" }, if a_first { "B" } else { "A" }, outcome, result.summary(),
+        );
+
+        if let Some(sprt) = &options.sprt {
+            let llr = result.llr(sprt);
+            let (lower, upper) = sprt.bounds();
+            if llr <= lower || llr >= upper {
+                println!(
+                    "SPRT: LLR {:.2} ({:.2}, {:.2}), {} accepted",
+                    llr, lower, upper, if llr >= upper { "H1 (A is stronger)" } else { "H0 (A is not stronger)" },
+                );
+                break;
+            }
+        }
+    }
+
+    println!();
+    println!("A vs B: {}", result.summary());
+    if let Some(sprt) = &options.sprt {
+        let (lower, upper) = sprt.bounds();
+        println!("SPRT [{}, {}]: LLR {:.2} ({:.2}, {:.2})", sprt.elo0, sprt.elo1, result.llr(sprt), lower, upper);
+    }
+    if let Some(path) = &options.out {
+        std::fs::write(path, pgn.join("\n\n") + "\n").map_err(|e| e.to_string())?;
+        println!("saved {} games to {}", pgn.len(), path.display());
+    }
+    Ok(())
+}
+
+// 从初始局面随机走 plies 步，对局结束时重新开始
+fn random_opening<B: Board>(rng: &mut StdRng, plies: usize) -> Result<Vec<B::S>, String> {
+    // 随机走子很难走到 plies 步还没结束时，不能一直重试下去
+    for _ in 0..OPENING_TRIES {
+        let mut board = B::default();
+        let mut steps = Vec::new();
+        while steps.len() < plies && !board.end_game() {
+            let all_move = board.all_move();
+            let step = all_move[rng.random_range(0..all_move.len())];
+            board = board.try_move(step).unwrap();
+            steps.push(step);
+        }
+        if !board.end_game() {
+            return Ok(steps);
+        }
+    }
+    Err(format!("no random opening of {plies} plies found in {OPENING_TRIES} tries, use a smaller --opening"))
+}
+
+fn play_game<B: Heuristic>(first: &PlayerConfig, second: &PlayerConfig, opening: &[B::S], max_moves: Option<usize>, round: u32) -> GameTree<B> {
+    let mut tree = GameTree::new(B::default());
+    tree.set_tag("Event", "xinqi arena".to_string());
+    tree.set_tag("Round", round.to_string());
+    tree.set_tag("First", first.name.clone());
+    tree.set_tag("Second", second.name.clone());
+    for step in opening {
+        tree.try_move(*step);
+    }
+
+    let ais = [first.build::<B>(round), second.build::<B>(round)];
+    let mut moves = opening.len();
+    while !tree.board().end_game() {
+        if max_moves.is_some_and(|max| moves >= max) {
+            tree.set_tag("Result", "1/2-1/2".to_string());
+            tree.set_tag("Termination", "move limit".to_string());
+            break;
+        }
+        let board = tree.board();
+        let (ai, player) = match board.get_active_player() {
+            PlayerOrder::First => (&ais[0], first),
+            PlayerOrder::Second => (&ais[1], second),
+        };
+        let step = ai.search(board, player.limit, &SearchSignal::default());
+        tree.try_move(step);
+        moves += 1;
+    }
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(wins: u32, draws: u32, losses: u32) -> ArenaResult {
+        ArenaResult { wins, draws, losses }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn elo_of_known_scores() {
+        // 得分 60% 约为 +70 Elo，和棋只影响置信区间
+        let (elo, margin) = result(60, 0, 40).elo().unwrap();
+        assert_close(elo, 70.44);
+        assert_close(margin, 70.57);
+        let (elo, margin_with_draws) = result(50, 20, 30).elo().unwrap();
+        assert_close(elo, 70.44);
+        assert!(margin_with_draws < margin);
+        let (elo, _) = result(40, 0, 60).elo().unwrap();
+        assert_close(elo, -70.44);
+        assert_close(result(5, 10, 5).elo().unwrap().0, 0.0);
+
+        assert!(result(0, 0, 0).elo().is_none());
+        assert!(result(10, 0, 0).elo().is_none());
+        assert!(result(0, 0, 10).elo().is_none());
+    }
+
+    #[test]
+    fn llr_of_known_results() {
+        let sprt = Sprt { elo0: 0.0, elo1: 50.0, alpha: 0.05, beta: 0.05 };
+        let (lower, upper) = sprt.bounds();
+        assert_close(lower, -2.944);
+        assert_close(upper, 2.944);
+
+        assert_close(result(60, 0, 40).llr(&sprt), 1.914);
+        assert!(result(40, 0, 60).llr(&sprt) < lower);
+        assert!(result(120, 0, 80).llr(&sprt) > upper);
+        assert_eq!(result(0, 0, 0).llr(&sprt), 0.0);
+        assert_eq!(result(0, 7, 0).llr(&sprt), 0.0);
+    }
+
+    #[test]
+    fn unreachable_opening_is_an_error() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(random_opening::<HequnBoard>(&mut rng, 4).unwrap().len(), 4);
+        assert!(random_opening::<HequnBoard>(&mut rng, 500).is_err());
+    }
+}
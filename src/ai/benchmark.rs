@@ -0,0 +1,23 @@
+// 测量 MCTSv2 在不同线程数下每秒的模拟次数
+use std::time::Instant;
+use crate::ai::{mctsv2::MCTSv2, Heuristic, SearchLimit, SearchSignal, AI};
+
+pub fn benchmark<B: Heuristic>(
+    name: &str,
+    time_limit_ms: u32,
+    max_threads: usize,
+) {
+    let mut threads = 1;
+    while threads <= max_threads {
+        let ai = MCTSv2::<B>::default().with_threads(threads);
+        let signal = SearchSignal::default();
+        let start_time = Instant::now();
+        ai.search(B::default(), SearchLimit::Time(time_limit_ms), &signal);
+        let per_second = signal.simulations() as f64 / start_time.elapsed().as_secs_f64();
+        println!(
+            "{:<10} {:>3} threads: {:>10.0} simulations/s, {:>10.0} per thread",
+            name, threads, per_second, per_second / threads as f64,
+        );
+        threads *= 2;
+    }
+}
@@ -1,9 +1,9 @@
-use std::{marker::PhantomData, time::{Duration, Instant}};
+use std::{marker::PhantomData, time::Instant};
 
 // Monte-Carlo Tree Search
-use bevy::{prelude::*};
-use crate::{ai::AI, general::*};
-use rand::Rng;
+use tracing::{error, info};
+use crate::{ai::{SearchLimit, SearchSignal, AI}, general::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 struct MCTSNode<B: Board> {
     visit_count: u32,
@@ -36,20 +36,24 @@ struct MCTS<B: Board> {
     nodes: Vec<MCTSNode<B>>,
     root: usize,
     exploration_param: f32,
-    rng: rand::rngs::ThreadRng,
+    rng: StdRng,
 }
 
 impl<B> MCTS<B> 
 where B: Board 
 {
-    fn new(board: B, p: f32) -> Self {
+    fn new(board: B, p: f32, seed: Option<u64>) -> Self {
         let nodes = vec![MCTSNode::new(&board)];
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
         Self { 
             board,
             nodes, 
             root: 0, 
             exploration_param: p,  
-            rng: rand::rng(),
+            rng,
         }
     }
 
@@ -159,15 +163,31 @@ where B: Board
 }
 
 pub struct MCTSAI<B: Board> {
+    exploration: f32,
+    seed: Option<u64>,
     _marker: PhantomData<B>,
 }
 
 impl<B: Board> MCTSAI<B> {
     pub fn new() -> Self {
         Self {
+            exploration: std::f32::consts::SQRT_2,
+            seed: None,
             _marker: PhantomData,
         }
     }
+
+    // UCB1 公式中的参数 c，默认为常见的 √2
+    pub fn with_exploration(mut self, exploration: f32) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    // 固定随机数种子后，同一局面、同样的模拟次数总是得到同一步棋
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
 }
 
 impl<B> AI for MCTSAI<B>
@@ -175,16 +195,15 @@ where B: Board
 {
     type B = B;
 
-    fn play(&self, board: Self::B, time_limit_ms: u32) -> <Self::B as Board>::S {
+    fn search(&self, board: Self::B, limit: SearchLimit, signal: &SearchSignal) -> <Self::B as Board>::S {
         let start_time = Instant::now();
-        let time_limit = Duration::from_millis(time_limit_ms as u64);
         
-        // 创建MCTS实例，使用常见的探索参数√2
-        let mut mcts = MCTS::new(board, std::f32::consts::SQRT_2);
+        let mut mcts = MCTS::new(board, self.exploration, self.seed);
         
-        // 在时限内尽可能多地搜索
-        while start_time.elapsed() < time_limit {
+        // 在预算内尽可能多地搜索
+        while !limit.reached(start_time, mcts.nodes[mcts.root].visit_count) && !signal.is_stopped() {
             mcts.search();
+            signal.set_simulations(mcts.nodes[mcts.root].visit_count);
         }
         
         info!("MCTS completed {} simulations", mcts.nodes[mcts.root].visit_count);
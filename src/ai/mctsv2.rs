@@ -1,9 +1,9 @@
-use std::{time::{Duration, Instant}};
+use std::{cmp::Reverse, collections::VecDeque, sync::Mutex, time::{Duration, Instant}};
 
 // Monte-Carlo Tree Search
-use bevy::{prelude::*};
-use crate::{ai::AI, general::*};
-use rand::Rng;
+use tracing::{error, info};
+use crate::{ai::{Heuristic, MoveStat, SearchLimit, SearchSignal, AI}, general::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 struct MCTSNode<B: Board> {
     visit_count: u32,
@@ -33,27 +33,86 @@ where B: Board
     }
 }
 
+// 最终着法的选择方式
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveSelection {
+    MaxVisits,
+    MaxWinRate,        // 只在访问次数不少于最多者 1/10 的着法中选择，避免选中偶然赢了几次的着法
+    Temperature(f32),  // 按访问次数的 1/t 次方为权重随机选择，t 趋于 0 时等同于 MaxVisits
+}
+
+// MCTSv2 的可调参数，见 ai.md
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MCTSConfig {
+    pub exploration: f32,                // UCB1 公式中的参数 c
+    pub prior_weight: f32,               // 估值相当于多少次模拟，为 0 时只使用模拟结果
+    pub eval_temperature: Option<f32>,   // 估值把分差转换为胜率时的温度，None 时使用每种棋的默认值
+    pub max_rollout_depth: Option<u32>,  // 模拟阶段最多走的步数，达到后以局面估值作为结果
+    pub move_selection: MoveSelection,
+    pub seed: Option<u64>,               // 随机数种子，None 时每次随机
+}
+
+impl Default for MCTSConfig {
+    fn default() -> Self {
+        Self {
+            exploration: std::f32::consts::SQRT_2,
+            prior_weight: 5.0,
+            eval_temperature: None,
+            max_rollout_depth: None,
+            move_selection: MoveSelection::MaxVisits,
+            seed: None,
+        }
+    }
+}
+
+impl MCTSConfig {
+    // 命令行中的一项设置，如 "c=1.0"、"select=winrate"
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let number = |value: &str| value.parse::<f32>().map_err(|_| format!("invalid value \"{}\" for {}", value, key));
+        match key {
+            "c" => self.exploration = number(value)?,
+            "w" => self.prior_weight = number(value)?.max(0.0),
+            "eval_temp" => self.eval_temperature = Some(number(value)?).filter(|t| *t > 0.0),
+            "depth" => self.max_rollout_depth = Some(value.parse().map_err(|_| format!("invalid value \"{}\" for {}", value, key))?),
+            "select" => self.move_selection = match value {
+                "visits" => MoveSelection::MaxVisits,
+                "winrate" => MoveSelection::MaxWinRate,
+                t => MoveSelection::Temperature(number(t)?),
+            },
+            "seed" => self.seed = Some(value.parse().map_err(|_| format!("invalid value \"{}\" for {}", value, key))?),
+            _ => return Err(format!("unknown MCTS setting \"{}\"", key)),
+        }
+        Ok(())
+    }
+}
+
 struct MCTS<B: Board> {
     board: B,
     nodes: Vec<MCTSNode<B>>,
     root: usize,
-    exploration_param: f32,
-    rng: rand::rngs::ThreadRng,
-    evaluate: fn(&B) -> f32,
+    config: MCTSConfig,
+    temperature: f32,
+    rng: StdRng,
+    evaluate: fn(&B, f32) -> f32,
     quick_move: fn(&B) -> Vec<B::S>,
 }
 
 impl<B> MCTS<B> 
 where B: Board 
 {
-    fn new(board: B, p: f32, evaluate: fn(&B) -> f32, quick_move: fn(&B) -> Vec<B::S>,) -> Self {
-        let nodes = vec![MCTSNode::new(&board, evaluate(&board))];
+    fn new(board: B, config: MCTSConfig, temperature: f32, evaluate: fn(&B, f32) -> f32, quick_move: fn(&B) -> Vec<B::S>,) -> Self {
+        let nodes = vec![MCTSNode::new(&board, evaluate(&board, temperature))];
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
         Self { 
             board,
             nodes, 
             root: 0, 
-            exploration_param: p,  
-            rng: rand::rng(),
+            config,
+            temperature,
+            rng,
             evaluate,
             quick_move,
         }
@@ -84,7 +143,7 @@ where B: Board
                 if self.nodes[current].sons[i] == None {
                     self.nodes[current].unselected_son_num -= 1;
                     board = board.try_move(self.nodes[current].all_move[i]).unwrap();
-                    self.nodes.push(MCTSNode::new(&board, (self.evaluate)(&board)));
+                    self.nodes.push(MCTSNode::new(&board, (self.evaluate)(&board, self.temperature)));
                     let new_node = self.nodes.len() - 1;
                     self.nodes[current].sons[i] = Some(new_node);
                     current = new_node;
@@ -99,20 +158,26 @@ where B: Board
             }
 
             // 模拟阶段，使用快速走子策略
-            while !board.end_game() {
+            let mut depth = 0;
+            while !board.end_game() && self.config.max_rollout_depth.is_none_or(|max| depth < max) {
                 let all_move = (self.quick_move)(&board);
                 let num = self.rng.random_range(0..all_move.len());
                 board = board.try_move(all_move[num]).unwrap();
+                depth += 1;
             }
         }
 
-        // 回溯阶段
-        let win_count = match board.get_winner() {
-            Some(winner) => match winner {
-                PlayerOrder::First => 1.0,
-                PlayerOrder::Second => 0.0,
-            },
-            None => 0.5,
+        // 回溯阶段，模拟被截断时以局面估值作为结果
+        let win_count = if !board.end_game() {
+            (self.evaluate)(&board, self.temperature)
+        } else {
+            match board.get_winner() {
+                Some(winner) => match winner {
+                    PlayerOrder::First => 1.0,
+                    PlayerOrder::Second => 0.0,
+                },
+                None => 0.5,
+            }
         };
         for node in node_path {
             self.nodes[node].visit_count += 1;
@@ -124,7 +189,7 @@ where B: Board
      * 从当前节点的所有儿子中选择一个本步行动，返回选择的儿子的排名。
      * 使用 UCB1 公式 score = Q + c * sqrt(ln(N) / n)
      * 其中 Q 为估测胜率, c 为参数, N 为父节点访问次数, n 为子节点访问次数。
-     * 通过估值策略进行改进, 令 Q = 0.7Q1 + 0.3Q2, Q1, Q2 分别是通过模拟和通过估值得到的胜率。
+     * 通过估值策略进行改进, 令 Q = (nQ1 + wQ2) / (n + w), Q1, Q2 分别是通过模拟和通过估值得到的胜率，w 为估值的权重。
      */ 
     fn select(&self, current: usize, active_player: PlayerOrder) -> usize {
         let son_num = self.nodes[current].son_num;
@@ -136,12 +201,12 @@ where B: Board
             let n = self.nodes[nd].visit_count as f32;
             let q1 = self.nodes[nd].win_count / self.nodes[nd].visit_count as f32;
             let q2 = self.nodes[nd].evaluation;
-            let q = (n * q1 + 5.0 * q2) / (n + 5.0);
+            let q = (n * q1 + self.config.prior_weight * q2) / (n + self.config.prior_weight);
             let q = match active_player {
                 PlayerOrder::First => q,
                 PlayerOrder::Second => 1.0 - q,
             };
-            let score = q + self.exploration_param * (nn.ln() / n).sqrt();
+            let score = q + self.config.exploration * (nn.ln() / n).sqrt();
             if score > best_score {
                 best_score = score;
                 best_index = i;
@@ -150,38 +215,263 @@ where B: Board
         best_index
     }
 
-    fn get_best_move(&self) -> B::S {
-        let mut best_visit_count = 0;
-        let mut best_index = 0;
+    /**
+     * 在当前根节点两层以内寻找与 board 相同的局面（即我方一步加对方一步之内），
+     * 找到则以其为新的根节点并丢弃其余节点，返回是否找到。
+     */
+    fn reroot(&mut self, board: &B) -> bool {
+        let fen = board.write_fen();
+        let mut queue = VecDeque::from([(self.root, self.board.clone(), 0)]);
+        while let Some((current, current_board, depth)) = queue.pop_front() {
+            if current_board.write_fen() == fen {
+                self.compact(current);
+                self.board = board.clone();
+                return true;
+            }
+            if depth == 2 {
+                continue;
+            }
+            for i in 0..self.nodes[current].son_num {
+                if let Some(son) = self.nodes[current].sons[i] {
+                    let son_board = current_board.try_move(self.nodes[current].all_move[i]).unwrap();
+                    queue.push_back((son, son_board, depth + 1));
+                }
+            }
+        }
+        false
+    }
+
+    // 只保留以 new_root 为根的子树，重新编号后 new_root 为 0 号节点
+    fn compact(&mut self, new_root: usize) {
+        let mut old_nodes: Vec<Option<MCTSNode<B>>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        let mut nodes = vec![old_nodes[new_root].take().unwrap()];
+        let mut i = 0;
+        while i < nodes.len() {
+            for j in 0..nodes[i].son_num {
+                if let Some(old_index) = nodes[i].sons[j] {
+                    let new_index = nodes.len();
+                    nodes[i].sons[j] = Some(new_index);
+                    nodes.push(old_nodes[old_index].take().unwrap());
+                }
+            }
+            i += 1;
+        }
+        self.nodes = nodes;
+        self.root = 0;
+    }
+
+    // 根节点每个儿子的访问次数和先手方获胜次数，未扩展的儿子计 0
+    fn root_counts(&self) -> Vec<(u32, f32)> {
+        self.nodes[self.root].sons
+            .iter()
+            .map(|son| son.map_or((0, 0.0), |nd| (self.nodes[nd].visit_count, self.nodes[nd].win_count)))
+            .collect()
+    }
 
-        for i in 0..self.nodes[self.root].son_num {
-            if let Some(node_index) = self.nodes[self.root].sons[i] {
-                let visit_count = self.nodes[node_index].visit_count;
-                if visit_count > best_visit_count {
-                    best_visit_count = visit_count;
-                    best_index = i;
+    // 按 config.move_selection 从根节点的各个儿子中选出最终着法，返回其排名
+    fn choose_move(&mut self, counts: &[(u32, f32)]) -> usize {
+        let max_visits = counts.iter().map(|(visits, _)| *visits).max().unwrap_or(0);
+        let most_visited = most_visited(&counts.iter().map(|(visits, _)| *visits).collect::<Vec<_>>());
+        match self.config.move_selection {
+            MoveSelection::MaxVisits => most_visited,
+            MoveSelection::MaxWinRate => {
+                let active_player = self.board.get_active_player();
+                let win_rate = |(visits, wins): (u32, f32)| match active_player {
+                    PlayerOrder::First => wins / visits as f32,
+                    PlayerOrder::Second => 1.0 - wins / visits as f32,
+                };
+                (0..counts.len())
+                    .filter(|&i| counts[i].0 > 0 && counts[i].0 * 10 >= max_visits)
+                    .max_by(|&i, &j| win_rate(counts[i]).total_cmp(&win_rate(counts[j])))
+                    .unwrap_or(most_visited)
+            },
+            MoveSelection::Temperature(t) => {
+                if t <= 0.0 || max_visits == 0 {
+                    return most_visited;
                 }
+                let weights: Vec<f64> = counts.iter()
+                    .map(|(visits, _)| (*visits as f64 / max_visits as f64).powf(1.0 / t as f64))
+                    .collect();
+                let mut x = self.rng.random::<f64>() * weights.iter().sum::<f64>();
+                for (i, w) in weights.iter().enumerate() {
+                    if x < *w {
+                        return i;
+                    }
+                    x -= w;
+                }
+                most_visited
+            },
+        }
+    }
+
+    // 根节点各个已扩展儿子的统计信息，按访问次数从多到少排序
+    fn root_stats(&self) -> Vec<MoveStat<B::S>> {
+        let active_player = self.board.get_active_player();
+        let to_active = |q: f32| match active_player {
+            PlayerOrder::First => q,
+            PlayerOrder::Second => 1.0 - q,
+        };
+        let root = &self.nodes[self.root];
+        let mut stats: Vec<MoveStat<B::S>> = (0..root.son_num)
+            .filter_map(|i| {
+                let node = &self.nodes[root.sons[i]?];
+                Some(MoveStat {
+                    step: root.all_move[i],
+                    san: self.board.write_step(root.all_move[i]).unwrap_or_default(),
+                    visits: node.visit_count,
+                    win_rate: to_active(node.win_count / node.visit_count as f32),
+                    evaluation: to_active(node.evaluation),
+                })
+            })
+            .collect();
+        stats.sort_by_key(|s| Reverse(s.visits));
+        stats
+    }
+
+    // 在预算用完或被停止前反复搜索。report 为真时每隔 REPORT_INTERVAL 通过 signal 报告当前最好的着法
+    fn search_until(&mut self, start_time: Instant, limit: SearchLimit, signal: &SearchSignal, report: bool) {
+        let mut simulations = 0;
+        let mut last_report = start_time;
+        while !limit.reached(start_time, simulations) && !signal.is_stopped() {
+            // 节点数达到上限后不再扩展。按时间计算时仍然等到时间用完或被停止，"go infinite" 只能由 stop 结束
+            if self.nodes.len() >= MAX_NODES {
+                match limit {
+                    SearchLimit::Time(_) => std::thread::sleep(FULL_TREE_POLL),
+                    SearchLimit::Simulations(_) => break,
+                }
+                continue;
+            }
+            self.search();
+            simulations += 1;
+            signal.add_simulations(1);
+            if report && last_report.elapsed() >= REPORT_INTERVAL {
+                self.report_best(signal);
+                last_report = Instant::now();
             }
         }
+        if report {
+            self.report_best(signal);
+        }
+    }
 
-        self.nodes[self.root].all_move[best_index].clone()
+    fn report_best(&self, signal: &SearchSignal) {
+        if let Some(best) = self.root_stats().into_iter().next() {
+            signal.set_best(best.san, best.win_rate);
+        }
     }
 }
 
+// 搜索中报告当前最好着法的间隔
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+// 一棵搜索树最多扩展的节点数，避免长时间思考、对手回合思考和分析局面时耗尽内存
+const MAX_NODES: usize = 200_000;
+
+// 搜索树已满时检查停止信号的间隔
+const FULL_TREE_POLL: Duration = Duration::from_millis(10);
+
+// 访问次数最多的儿子。访问次数相同时取排在前面的儿子，和 root_stats 的顺序一致
+fn most_visited(visits: &[u32]) -> usize {
+    (0..visits.len()).min_by_key(|&i| Reverse(visits[i])).unwrap_or(0)
+}
+
 pub struct MCTSv2<B: Board> {
-    evaluate: fn(&B) -> f32,
+    evaluate: fn(&B, f32) -> f32,
     quick_move: fn(&B) -> Vec<B::S>,
+    tree: Mutex<Option<MCTS<B>>>, // 上一次搜索留下的搜索树，下一次搜索时尽量复用
+    ponder: Mutex<Option<SearchSignal>>,
+    threads: usize,
+    config: MCTSConfig,
+    default_temperature: f32,
 }
 
 impl<B: Board> MCTSv2<B> {
+    // default_temperature 是 config.eval_temperature 为 None 时估值使用的温度
     pub fn new(
-        evaluate: fn(&B) -> f32,
+        evaluate: fn(&B, f32) -> f32,
+        default_temperature: f32,
         quick_move: fn(&B) -> Vec<B::S>,
     ) -> Self {
         Self {
             evaluate,
             quick_move,
+            tree: Mutex::new(None),
+            ponder: Mutex::new(None),
+            threads: 1,
+            config: MCTSConfig::default(),
+            default_temperature,
+        }
+    }
+
+    // 根并行：每个线程独立建树，最后按根节点各儿子的访问次数之和选择着法
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn with_config(mut self, config: MCTSConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &MCTSConfig {
+        &self.config
+    }
+
+    // 新建搜索树，第 index 个线程的随机数种子在 config.seed 的基础上加 index
+    fn new_tree(&self, board: B, index: usize) -> MCTS<B> {
+        let config = MCTSConfig {
+            seed: self.config.seed.map(|seed| seed.wrapping_add(index as u64)),
+            ..self.config
+        };
+        let temperature = self.config.eval_temperature.unwrap_or(self.default_temperature);
+        MCTS::new(board, config, temperature, self.evaluate, self.quick_move)
+    }
+
+    // 取出上一次的搜索树并移动到 board 对应的节点，无法复用时新建搜索树
+    fn take_tree(&self, tree: &mut Option<MCTS<B>>, board: &B) -> MCTS<B> {
+        if let Some(mut mcts) = tree.take() && mcts.reroot(board) {
+            return mcts;
         }
+        self.new_tree(board.clone(), 0)
+    }
+
+    // 登记一次对手回合的思考，返回用于停止它的信号，之后在后台线程中调用 ponder
+    pub fn start_ponder(&self) -> SearchSignal {
+        let signal = SearchSignal::default();
+        if let Some(old) = self.ponder.lock().unwrap().replace(signal.clone()) {
+            old.stop();
+        }
+        signal
+    }
+
+    // 在对手回合继续搜索 board，直到 signal 被停止、下一次搜索开始或节点数达到上限
+    pub fn ponder(&self, board: B, signal: &SearchSignal) {
+        let mut tree = self.tree.lock().unwrap();
+        if signal.is_stopped() || board.end_game() {
+            return;
+        }
+        let mut mcts = self.take_tree(&mut tree, &board);
+        let reused = mcts.nodes[mcts.root].visit_count;
+
+        signal.set_simulations(reused);
+        while !signal.is_stopped() && mcts.nodes.len() < MAX_NODES {
+            mcts.search();
+            signal.add_simulations(1);
+        }
+
+        info!("MCTS pondered {} simulations ({} reused)", mcts.nodes[mcts.root].visit_count - reused, reused);
+        *tree = Some(mcts);
+    }
+}
+
+impl<B: Heuristic> Default for MCTSv2<B> {
+    fn default() -> Self {
+        Self::new(B::evaluate_with, B::TEMPERATURE, B::quick_move)
     }
 }
 
@@ -190,21 +480,155 @@ where B: Board
 {
     type B = B;
 
-    fn play(&self, board: Self::B, time_limit_ms: u32) -> <Self::B as Board>::S {
+    fn search(&self, board: Self::B, limit: SearchLimit, signal: &SearchSignal) -> <Self::B as Board>::S {
         let start_time = Instant::now();
-        let time_limit = Duration::from_millis(time_limit_ms as u64);
+
+        // 停止对手回合的思考，取回搜索树
+        if let Some(ponder) = self.ponder.lock().unwrap().take() {
+            ponder.stop();
+        }
+        let mut tree = self.tree.lock().unwrap();
+
+        // 复用上一次搜索中对应局面的子树，否则创建MCTS实例
+        let mut mcts = self.take_tree(&mut tree, &board);
+        let reused = mcts.nodes[mcts.root].visit_count;
+        signal.set_simulations(reused);
         
-        // 创建MCTS实例，使用常见的探索参数√2
-        let mut mcts = MCTS::new(board, std::f32::consts::SQRT_2, self.evaluate, self.quick_move);
+        // 在预算内尽可能多地搜索，其余线程各自从头建树。按模拟次数计算时由各线程平分
+        let helper_counts: Vec<Vec<(u32, f32)>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (1..self.threads).map(|index| {
+                let board = board.clone();
+                scope.spawn(move || {
+                    let mut helper = self.new_tree(board, index);
+                    helper.search_until(start_time, limit.share(index, self.threads), signal, false);
+                    helper.root_counts()
+                })
+            }).collect();
+            // 只由主线程报告当前最好的着法
+            mcts.search_until(start_time, limit.share(0, self.threads), signal, true);
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
         
-        // 在时限内尽可能多地搜索
-        while start_time.elapsed() < time_limit {
+        info!("MCTS completed {} simulations on {} threads ({} reused)", signal.simulations() - reused, self.threads, reused);
+        
+        // 合并所有线程的统计后按 config.move_selection 选择着法
+        let mut counts = mcts.root_counts();
+        for helper in helper_counts {
+            for ((visits, wins), (hv, hw)) in counts.iter_mut().zip(helper) {
+                *visits += hv;
+                *wins += hw;
+            }
+        }
+        let best_index = mcts.choose_move(&counts);
+        let best_move = mcts.nodes[mcts.root].all_move[best_index];
+        *tree = Some(mcts);
+        best_move
+    }
+    fn analyze(&self, board: Self::B, time_limit_ms: u32, signal: &SearchSignal) -> Vec<MoveStat<<Self::B as Board>::S>> {
+        let start_time = Instant::now();
+        let time_limit = Duration::from_millis(time_limit_ms as u64);
+
+        if let Some(ponder) = self.ponder.lock().unwrap().take() {
+            ponder.stop();
+        }
+        let mut tree = self.tree.lock().unwrap();
+        if board.end_game() {
+            return Vec::new();
+        }
+
+        // 多次分析同一局面时在同一棵搜索树上继续搜索
+        let mut mcts = self.take_tree(&mut tree, &board);
+        while start_time.elapsed() < time_limit && !signal.is_stopped() && mcts.nodes.len() < MAX_NODES {
             mcts.search();
         }
-        
-        info!("MCTS completed {} simulations", mcts.nodes[mcts.root].visit_count);
-        
-        // 返回访问次数最多的着法
-        mcts.get_best_move()
+        signal.set_simulations(mcts.nodes[mcts.root].visit_count);
+
+        let stats = mcts.root_stats();
+        *tree = Some(mcts);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zhandi::general::ZhandiBoard;
+
+    // 固定种子、按模拟次数搜索时得到的着法和根节点统计
+    fn seeded_search(threads: usize) -> (String, Vec<(String, u32, f32)>) {
+        let config = MCTSConfig { seed: Some(7), ..MCTSConfig::default() };
+        let ai = MCTSv2::<ZhandiBoard>::default().with_threads(threads).with_config(config);
+        let board = ZhandiBoard::default();
+        let step = ai.search(board.clone(), SearchLimit::Simulations(200), &SearchSignal::default());
+        // 时限为 0 时只取出刚才那棵搜索树的统计
+        let stats = ai.analyze(board.clone(), 0, &SearchSignal::default())
+            .into_iter()
+            .map(|stat| (stat.san, stat.visits, stat.win_rate))
+            .collect();
+        (board.write_step(step).unwrap(), stats)
+    }
+
+    #[test]
+    fn same_seed_and_simulations_give_the_same_search() {
+        for threads in [1, 3] {
+            let (step, stats) = seeded_search(threads);
+            assert_eq!(seeded_search(threads), (step, stats.clone()), "threads = {}", threads);
+            assert!(!stats.is_empty());
+        }
     }
-}
\ No newline at end of file
+
+    // 以 node 为根的子树中的节点数
+    fn subtree_size(mcts: &MCTS<ZhandiBoard>, node: usize) -> usize {
+        1 + mcts.nodes[node].sons.iter().flatten().map(|&son| subtree_size(mcts, son)).sum::<usize>()
+    }
+
+    #[test]
+    fn reroot_keeps_the_subtree_of_the_played_moves() {
+        let ai = MCTSv2::<ZhandiBoard>::default().with_config(MCTSConfig { seed: Some(5), ..MCTSConfig::default() });
+        // 选一个着法不多的局面，让两步之后的子树也有足够的访问
+        let mut board = ZhandiBoard::default();
+        while board.all_move().len() > 10 {
+            board = board.try_move(board.all_move()[0]).unwrap();
+        }
+        let mut mcts = ai.new_tree(board.clone(), 0);
+        for _ in 0..300 {
+            mcts.search();
+        }
+
+        // 走出访问最多的一步，再走出对方在这棵子树中访问最多的一步
+        let most_visited = |mcts: &MCTS<ZhandiBoard>, node: usize| {
+            (0..mcts.nodes[node].son_num)
+                .filter_map(|i| mcts.nodes[node].sons[i].map(|son| (i, son)))
+                .max_by_key(|&(_, son)| mcts.nodes[son].visit_count)
+                .unwrap()
+        };
+        let (i, son) = most_visited(&mcts, mcts.root);
+        let (j, grandson) = most_visited(&mcts, son);
+        let after = board.try_move(mcts.nodes[mcts.root].all_move[i]).unwrap();
+        let after = after.try_move(mcts.nodes[son].all_move[j]).unwrap();
+        let visits = mcts.nodes[grandson].visit_count;
+        let size = subtree_size(&mcts, grandson);
+        assert!(visits > 1);
+
+        assert!(mcts.reroot(&after));
+        assert_eq!(mcts.root, 0);
+        assert_eq!(mcts.nodes[0].visit_count, visits);
+        assert_eq!(mcts.nodes.len(), size);
+        assert_eq!(mcts.board.write_fen(), after.write_fen());
+
+        // 三步之外的局面无法复用
+        let (k, _) = most_visited(&mcts, 0);
+        let far = after.try_move(mcts.nodes[0].all_move[k]).unwrap();
+        let far = far.try_move(far.all_move()[0]).unwrap();
+        let far = far.try_move(far.all_move()[0]).unwrap();
+        assert!(!mcts.reroot(&far));
+    }
+
+    #[test]
+    fn visit_ties_go_to_the_first_son() {
+        // 和 root_stats 的稳定排序一致，访问次数相同时选排在前面的儿子
+        assert_eq!(most_visited(&[0, 3, 1, 3]), 1);
+        assert_eq!(most_visited(&[2, 2, 2]), 0);
+        assert_eq!(most_visited(&[]), 0);
+    }
+}
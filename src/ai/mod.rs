@@ -1,10 +1,131 @@
+use std::{fmt, sync::{atomic::{AtomicBool, AtomicU32, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+
 use crate::general::*;
 
+pub mod arena;
+pub mod benchmark;
 pub mod mcts;
 pub mod mctsv2;
+#[cfg(feature = "gui")]
+pub mod task;
 
 pub trait AI {
     type B: Board;
 
-    fn play(&self, board: Self::B, time_limit_ms: u32) -> <Self::B as Board>::S;
-}
\ No newline at end of file
+    // 可以被中途停止的搜索，通过 signal 报告已完成的模拟次数
+    fn search(&self, board: Self::B, limit: SearchLimit, signal: &SearchSignal) -> <Self::B as Board>::S;
+
+    // 分析局面，返回根节点各儿子的统计信息，按访问次数从多到少排序。不支持分析的 AI 返回空列表
+    fn analyze(&self, _board: Self::B, _time_limit_ms: u32, _signal: &SearchSignal) -> Vec<MoveStat<<Self::B as Board>::S>> {
+        Vec::new()
+    }
+}
+
+// 每种棋的局面估值和快速走子，见 ai.md
+pub trait Heuristic: Board {
+    // 估值把分差转换为胜率时默认的温度
+    const TEMPERATURE: f32;
+
+    // 先手方胜率估计，温度越高越接近 0.5
+    fn evaluate_with(&self, temperature: f32) -> f32;
+    fn quick_move(&self) -> Vec<Self::S>;
+}
+
+// 分析结果中的一个候选着法，胜率和估值都是从行动方的角度计算的
+#[derive(Clone)]
+pub struct MoveStat<S: Step> {
+    pub step: S,
+    pub san: String,
+    pub visits: u32,
+    pub win_rate: f32,
+    pub evaluation: f32,
+}
+
+impl<T: AI + ?Sized> AI for Arc<T> {
+    type B = T::B;
+
+    fn search(&self, board: Self::B, limit: SearchLimit, signal: &SearchSignal) -> <Self::B as Board>::S {
+        (**self).search(board, limit, signal)
+    }
+
+    fn analyze(&self, board: Self::B, time_limit_ms: u32, signal: &SearchSignal) -> Vec<MoveStat<<Self::B as Board>::S>> {
+        (**self).analyze(board, time_limit_ms, signal)
+    }
+}
+
+// 一次搜索的预算。按模拟次数计算时结果与机器快慢无关，配合固定的随机数种子可以复现同一步棋
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchLimit {
+    Time(u32),        // 毫秒
+    Simulations(u32), // 本次搜索新增的模拟次数，不包括复用的搜索树中已有的
+}
+
+impl SearchLimit {
+    // 从 start_time 开始、已经完成 simulations 次模拟后是否用完了预算
+    pub fn reached(&self, start_time: Instant, simulations: u32) -> bool {
+        match self {
+            SearchLimit::Time(ms) => start_time.elapsed() >= Duration::from_millis(*ms as u64),
+            SearchLimit::Simulations(n) => simulations >= *n,
+        }
+    }
+
+    // 多线程搜索时第 index 个线程（共 threads 个）的预算，模拟次数尽量平均分配
+    pub fn share(&self, index: usize, threads: usize) -> SearchLimit {
+        match self {
+            SearchLimit::Time(ms) => SearchLimit::Time(*ms),
+            SearchLimit::Simulations(n) => {
+                let threads = threads as u32;
+                let index = index as u32;
+                SearchLimit::Simulations(n / threads + if index < n % threads { 1 } else { 0 })
+            },
+        }
+    }
+}
+
+impl fmt::Display for SearchLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchLimit::Time(ms) => write!(f, "{} ms", ms),
+            SearchLimit::Simulations(n) => write!(f, "{} simulations", n),
+        }
+    }
+}
+
+// 在搜索线程和界面之间共享的搜索状态
+#[derive(Clone, Default)]
+pub struct SearchSignal {
+    stop: Arc<AtomicBool>,
+    simulations: Arc<AtomicU32>,
+    best: Arc<Mutex<Option<(String, f32)>>>, // 搜索中当前最好的着法和它对行动方的胜率
+}
+
+impl SearchSignal {
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn simulations(&self) -> u32 {
+        self.simulations.load(Ordering::Relaxed)
+    }
+
+    pub fn set_simulations(&self, n: u32) {
+        self.simulations.store(n, Ordering::Relaxed);
+    }
+
+    // 多个线程同时搜索时累加模拟次数
+    pub fn add_simulations(&self, n: u32) {
+        self.simulations.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn best(&self) -> Option<(String, f32)> {
+        self.best.lock().unwrap().clone()
+    }
+
+    pub fn set_best(&self, san: String, win_rate: f32) {
+        *self.best.lock().unwrap() = Some((san, win_rate));
+    }
+}
@@ -0,0 +1,148 @@
+// 在后台线程中运行的 AI 搜索任务
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot::{self, error::TryRecvError};
+use crate::{ai::{MoveStat, SearchLimit, SearchSignal, AI}, engine::client::ExternalEngine, general::*};
+
+pub enum AITaskPoll<S: Step> {
+    Running,
+    Done(S),
+    Failed(String),
+}
+
+pub struct AITask<B: Board> {
+    pub node: usize, // 发起搜索时游戏树的焦点
+    pub fen: String, // 被搜索的局面
+    signal: SearchSignal,
+    rx: oneshot::Receiver<Result<StepType<B>, String>>,
+}
+
+impl<B: Board> AITask<B> {
+    pub fn spawn<A>(
+        runtime: &tokio::runtime::Runtime,
+        ai: A,
+        board: B,
+        node: usize,
+        limit: SearchLimit,
+    ) -> Self
+    where
+        A: AI<B = B> + Send + 'static,
+    {
+        Self::spawn_with(runtime, board, node, move |board, signal| Ok(ai.search(board, limit, signal)))
+    }
+
+    // 由外部引擎搜索，引擎出错时任务失败
+    pub fn spawn_engine(
+        runtime: &tokio::runtime::Runtime,
+        engine: Arc<ExternalEngine<B>>,
+        board: B,
+        node: usize,
+        limit: SearchLimit,
+    ) -> Self {
+        Self::spawn_with(runtime, board, node, move |board, signal| engine.try_search(&board, limit, signal))
+    }
+
+    fn spawn_with<F>(
+        runtime: &tokio::runtime::Runtime,
+        board: B,
+        node: usize,
+        search: F,
+    ) -> Self
+    where
+        F: FnOnce(B, &SearchSignal) -> Result<StepType<B>, String> + Send + 'static,
+    {
+        let signal = SearchSignal::default();
+        let task_signal = signal.clone();
+        let fen = board.write_fen();
+        let (tx, rx) = oneshot::channel();
+        runtime.spawn_blocking(move || {
+            let _ = tx.send(search(board, &task_signal));
+        });
+        Self {
+            node,
+            fen,
+            signal,
+            rx,
+        }
+    }
+
+    pub fn simulations(&self) -> u32 {
+        self.signal.simulations()
+    }
+
+    pub fn poll(&mut self) -> AITaskPoll<StepType<B>> {
+        match self.rx.try_recv() {
+            Ok(Ok(step)) => AITaskPoll::Done(step),
+            Ok(Err(e)) => AITaskPoll::Failed(e),
+            Err(TryRecvError::Empty) => AITaskPoll::Running,
+            Err(TryRecvError::Closed) => AITaskPoll::Failed("search task stopped unexpectedly".to_string()),
+        }
+    }
+}
+
+// 丢弃任务即取消搜索
+impl<B: Board> Drop for AITask<B> {
+    fn drop(&mut self) {
+        self.signal.stop();
+    }
+}
+
+// 每次分析的时长，之后把结果发布出去
+const ANALYSIS_INTERVAL_MS: u32 = 200;
+
+// 在后台持续分析一个局面，直到被停止或者搜索不再有进展
+pub struct AnalysisTask<B: Board> {
+    pub node: usize, // 被分析的游戏树节点
+    pub fen: String,
+    signal: SearchSignal,
+    stats: Arc<Mutex<Vec<MoveStat<StepType<B>>>>>,
+}
+
+impl<B: Board> AnalysisTask<B> {
+    pub fn spawn<A>(
+        runtime: &tokio::runtime::Runtime,
+        ai: A,
+        board: B,
+        node: usize,
+    ) -> Self
+    where
+        A: AI<B = B> + Send + 'static,
+    {
+        let signal = SearchSignal::default();
+        let task_signal = signal.clone();
+        let fen = board.write_fen();
+        let stats = Arc::new(Mutex::new(Vec::new()));
+        let task_stats = stats.clone();
+        runtime.spawn_blocking(move || {
+            let mut last_simulations = 0;
+            while !task_signal.is_stopped() {
+                let result = ai.analyze(board.clone(), ANALYSIS_INTERVAL_MS, &task_signal);
+                *task_stats.lock().unwrap() = result;
+                // 搜索树达到上限或者局面已经结束
+                if task_signal.simulations() == last_simulations {
+                    break;
+                }
+                last_simulations = task_signal.simulations();
+            }
+        });
+        Self {
+            node,
+            fen,
+            signal,
+            stats,
+        }
+    }
+
+    pub fn simulations(&self) -> u32 {
+        self.signal.simulations()
+    }
+
+    pub fn stats(&self) -> Vec<MoveStat<StepType<B>>> {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+impl<B: Board> Drop for AnalysisTask<B> {
+    fn drop(&mut self) {
+        self.signal.stop();
+    }
+}
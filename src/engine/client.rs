@@ -0,0 +1,167 @@
+// 启动外部引擎进程，通过引擎协议让它为一方走子
+use std::{io::{BufRead, BufReader, Write}, marker::PhantomData, process::{Child, ChildStdin, Command, Stdio}, sync::{mpsc::{self, Receiver, RecvTimeoutError}, Mutex}, time::{Duration, Instant}};
+
+use crate::{ai::{SearchLimit, SearchSignal}, engine::{parse_info, split_command, HANDSHAKE, HANDSHAKE_OK}, general::*};
+
+// 等待引擎回复的时间，搜索时在思考时间之外再等待这么久，按模拟次数搜索时引擎这么久没有输出则认为出错
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+// 搜索中检查是否被取消的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+struct EngineProcess {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl EngineProcess {
+    // command 按空白拆成程序名和参数
+    fn start(command: &str) -> Result<Self, String> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().ok_or("empty engine command")?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("cannot start \"{}\": {}", command, e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut process = Self { child, stdin, lines };
+        process.send(HANDSHAKE)?;
+        process.wait_for(|line| line == HANDSHAKE_OK)?;
+        Ok(process)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", line)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("cannot write to engine: {}", e))
+    }
+
+    // 丢弃其它行，直到收到满足条件的一行
+    fn wait_for(&mut self, done: impl Fn(&str) -> bool) -> Result<String, String> {
+        loop {
+            match self.lines.recv_timeout(REPLY_TIMEOUT) {
+                Ok(line) if done(line.trim()) => return Ok(line),
+                Ok(_) => {},
+                Err(RecvTimeoutError::Timeout) => return Err("engine did not reply".to_string()),
+                Err(RecvTimeoutError::Disconnected) => return Err("engine exited".to_string()),
+            }
+        }
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // 给引擎一点时间自行退出
+        for _ in 0..10 {
+            if self.child.try_wait().is_ok_and(|status| status.is_some()) {
+                return;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// 外部引擎，进程在第一次搜索时启动，之后一直保留，出错后下一次搜索时重新启动
+pub struct ExternalEngine<B: Board> {
+    pub command: String,
+    process: Mutex<Option<EngineProcess>>,
+    _marker: PhantomData<B>,
+}
+
+impl<B: Board> ExternalEngine<B> {
+    pub fn new(command: String) -> Self {
+        Self {
+            command,
+            process: Mutex::new(None),
+            _marker: PhantomData,
+        }
+    }
+
+    // 让引擎搜索 board，signal 被停止时发送 stop 并使用引擎随后给出的着法
+    pub fn try_search(&self, board: &B, limit: SearchLimit, signal: &SearchSignal) -> Result<B::S, String> {
+        let mut process = self.process.lock().unwrap();
+        if process.is_none() {
+            let mut started = EngineProcess::start(&self.command)?;
+            started.send(&format!("newgame {}", B::NAME))?;
+            started.send("isready")?;
+            started.wait_for(|line| line == "readyok")?;
+            *process = Some(started);
+        }
+        let result = Self::search_with(process.as_mut().unwrap(), board, limit, signal);
+        if result.is_err() {
+            *process = None;
+        }
+        result
+    }
+
+    fn search_with(process: &mut EngineProcess, board: &B, limit: SearchLimit, signal: &SearchSignal) -> Result<B::S, String> {
+        process.send(&format!("position fen {}", board.write_fen()))?;
+        let deadline = match limit {
+            SearchLimit::Time(ms) => {
+                process.send(&format!("go movetime {}", ms))?;
+                Some(Instant::now() + Duration::from_millis(ms as u64) + REPLY_TIMEOUT)
+            },
+            SearchLimit::Simulations(n) => {
+                process.send(&format!("go nodes {}", n))?;
+                None
+            },
+        };
+
+        let mut last_reply = Instant::now();
+        let mut stopped = false;
+        loop {
+            match process.lines.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => match split_command(&line) {
+                    ("info", args) => {
+                        // 搜索进度，"info move ..." 是单个着法的统计
+                        last_reply = Instant::now();
+                        let info = parse_info(args);
+                        if !info.iter().any(|(key, _)| *key == "move")
+                            && let Some(visits) = info.iter().find(|(key, _)| *key == "visits").and_then(|(_, v)| v.parse().ok()) {
+                            signal.set_simulations(visits);
+                        }
+                    },
+                    ("bestmove", "none") => return Err("engine found no move".to_string()),
+                    ("bestmove", san) => {
+                        return board.read_step(san.to_string()).map_err(|e| format!("engine played an invalid move: {}", e));
+                    },
+                    _ => {},
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    if signal.is_stopped() && !stopped {
+                        process.send("stop")?;
+                        stopped = true;
+                    }
+                    let timed_out = match deadline {
+                        Some(deadline) => Instant::now() > deadline,
+                        None => last_reply.elapsed() > REPLY_TIMEOUT,
+                    };
+                    if timed_out {
+                        return Err("engine did not answer in time".to_string());
+                    }
+                },
+                Err(RecvTimeoutError::Disconnected) => return Err("engine exited".to_string()),
+            }
+        }
+    }
+}
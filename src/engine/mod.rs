@@ -0,0 +1,48 @@
+// 基于文本行的引擎协议（类似 UCI/GTP），协议见 ai.md。
+// Xinqi 可以作为引擎被外部图形界面和比赛管理程序调用（server），也可以启动外部引擎进程为任意一方走子（client）
+use crate::{general::Board, hequn::general::HequnBoard, xingxiang::general::XingxiangBoard, zhandi::general::ZhandiBoard};
+
+pub mod server;
+pub mod client;
+
+// 握手命令和引擎的回复
+pub const HANDSHAKE: &str = "xinqi";
+pub const HANDSHAKE_OK: &str = "xinqiok";
+
+// go 没有指定时间时每步的思考时间
+pub const DEFAULT_MOVETIME_MS: u32 = 2000;
+
+pub const GAMES: [&str; 3] = [HequnBoard::NAME, ZhandiBoard::NAME, XingxiangBoard::NAME];
+
+// 拆出命令名和其余部分
+pub fn split_command(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.split_once(char::is_whitespace) {
+        Some((command, args)) => (command, args.trim()),
+        None => (line, ""),
+    }
+}
+
+// "startpos [moves ...]" 或 "fen <fen> [moves ...]"，返回 FEN（startpos 时为 None）和着法
+pub fn parse_position(args: &str) -> Result<(Option<String>, Vec<String>), String> {
+    let (position, moves) = match args.split_once(" moves") {
+        Some((position, moves)) => (position.trim(), moves.split_whitespace().map(String::from).collect()),
+        None => (args.trim(), Vec::new()),
+    };
+    match split_command(position) {
+        ("startpos", "") => Ok((None, moves)),
+        ("fen", fen) if !fen.is_empty() => Ok((Some(fen.to_string()), moves)),
+        _ => Err(format!("expected \"startpos\" or \"fen <fen>\", found \"{}\"", position)),
+    }
+}
+
+// "info" 之后的 "key value" 对
+pub fn parse_info(args: &str) -> Vec<(&str, &str)> {
+    let tokens: Vec<&str> = args.split_whitespace().collect();
+    tokens.chunks(2)
+        .filter_map(|pair| match pair {
+            [key, value] => Some((*key, *value)),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,266 @@
+// xinqi --engine：从标准输入读取命令，在标准输出回复，用 MCTSv2 搜索
+use std::{io::BufRead, sync::{mpsc::{self, Receiver, RecvTimeoutError, Sender}, Arc}, time::{Duration, Instant}};
+
+use crate::{ai::{mctsv2::{MCTSConfig, MCTSv2}, Heuristic, SearchLimit, SearchSignal, AI}, engine::{parse_position, split_command, DEFAULT_MOVETIME_MS, GAMES, HANDSHAKE, HANDSHAKE_OK}, general::*, hequn::general::HequnBoard, xingxiang::general::XingxiangBoard, zhandi::general::ZhandiBoard};
+
+// 搜索中输出进度的间隔
+const INFO_INTERVAL: Duration = Duration::from_millis(500);
+// 搜索结束时输出统计信息的候选着法数
+const INFO_MOVES: usize = 5;
+const MAX_THREADS: usize = 64;
+
+enum EngineEvent {
+    Line(String),             // 标准输入的一行
+    SearchDone(Vec<String>),  // 搜索结束后要输出的 info 和 bestmove
+}
+
+enum Next {
+    NewGame(String),
+    Quit,
+}
+
+fn reply_error(msg: impl std::fmt::Display) {
+    println!("info string error: {}", msg);
+}
+
+pub fn run() {
+    let (tx, rx) = mpsc::channel();
+    let stdin_tx = tx.clone();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if stdin_tx.send(EngineEvent::Line(line)).is_err() {
+                return;
+            }
+        }
+        // 输入结束时退出
+        let _ = stdin_tx.send(EngineEvent::Line("quit".to_string()));
+    });
+
+    let mut threads = 1;
+    let mut config = MCTSConfig::default();
+    let mut game = HequnBoard::NAME.to_string();
+    loop {
+        let next = match game.as_str() {
+            HequnBoard::NAME => run_session::<HequnBoard>(&mut threads, &mut config, &rx, &tx),
+            ZhandiBoard::NAME => run_session::<ZhandiBoard>(&mut threads, &mut config, &rx, &tx),
+            XingxiangBoard::NAME => run_session::<XingxiangBoard>(&mut threads, &mut config, &rx, &tx),
+            _ => unreachable!(),
+        };
+        match next {
+            Next::NewGame(name) => game = name,
+            Next::Quit => break,
+        }
+    }
+}
+
+fn run_session<B: Heuristic>(threads: &mut usize, config: &mut MCTSConfig, events: &Receiver<EngineEvent>, tx: &Sender<EngineEvent>) -> Next {
+    let mut session = Session::<B>::new(*threads, *config);
+    let next = session.run(events, tx);
+    *threads = session.threads;
+    *config = session.config;
+    next
+}
+
+// 一种棋的对局，换成另一种棋时结束
+struct Session<B: Heuristic> {
+    ai: Arc<MCTSv2<B>>,
+    threads: usize,
+    config: MCTSConfig,
+    board: B,
+    search: Option<(SearchSignal, Instant)>,
+}
+
+impl<B: Heuristic> Session<B> {
+    fn new(threads: usize, config: MCTSConfig) -> Self {
+        Self {
+            ai: Arc::new(MCTSv2::default().with_config(config).with_threads(threads)),
+            threads,
+            config,
+            board: B::default(),
+            search: None,
+        }
+    }
+
+    fn run(&mut self, events: &Receiver<EngineEvent>, tx: &Sender<EngineEvent>) -> Next {
+        loop {
+            let event = match &self.search {
+                Some((signal, start_time)) => match events.recv_timeout(INFO_INTERVAL) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => {
+                        println!("{}", progress(signal.simulations(), *start_time, signal.best()));
+                        continue;
+                    },
+                    Err(RecvTimeoutError::Disconnected) => return Next::Quit,
+                },
+                None => match events.recv() {
+                    Ok(event) => event,
+                    Err(_) => return Next::Quit,
+                },
+            };
+            match event {
+                EngineEvent::SearchDone(lines) => {
+                    self.search = None;
+                    for line in lines {
+                        println!("{}", line);
+                    }
+                },
+                EngineEvent::Line(line) => {
+                    if let Some(next) = self.command(&line, tx) {
+                        return next;
+                    }
+                },
+            }
+        }
+    }
+
+    fn command(&mut self, line: &str, tx: &Sender<EngineEvent>) -> Option<Next> {
+        let (command, args) = split_command(line);
+        // 和 UCI 一样，搜索中只接受 stop、isready 和 quit
+        if self.search.is_some() && !matches!(command, "stop" | "isready" | "quit") {
+            reply_error("search in progress, send stop first");
+            return None;
+        }
+        match command {
+            "" => {},
+            HANDSHAKE => {
+                println!("id name Xinqi {}", env!("CARGO_PKG_VERSION"));
+                println!("id games {}", GAMES.join(" "));
+                println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+                println!("option name MCTS type string default <empty>");
+                println!("{}", HANDSHAKE_OK);
+            },
+            "isready" => println!("readyok"),
+            "newgame" => {
+                let name = if args.is_empty() { B::NAME } else { args };
+                if name == B::NAME {
+                    *self = Self::new(self.threads, self.config);
+                } else if GAMES.contains(&name) {
+                    return Some(Next::NewGame(name.to_string()));
+                } else {
+                    reply_error(format!("unknown game \"{}\"", name));
+                }
+            },
+            "position" => {
+                if let Err(e) = self.set_position(args) {
+                    reply_error(e);
+                }
+            },
+            "go" => {
+                if let Err(e) = self.go(args, tx) {
+                    reply_error(e);
+                }
+            },
+            "stop" => {
+                if let Some((signal, _)) = &self.search {
+                    signal.stop();
+                }
+            },
+            "setoption" => {
+                if let Err(e) = self.set_option(args) {
+                    reply_error(e);
+                }
+            },
+            "quit" => {
+                if let Some((signal, _)) = &self.search {
+                    signal.stop();
+                }
+                return Some(Next::Quit);
+            },
+            _ => reply_error(format!("unknown command \"{}\"", command)),
+        }
+        None
+    }
+
+    fn set_position(&mut self, args: &str) -> Result<(), String> {
+        let (fen, moves) = parse_position(args)?;
+        let mut board = match fen {
+            Some(fen) => B::read_fen(fen).map_err(|e| e.to_string())?,
+            None => B::default(),
+        };
+        for san in moves {
+            let step = board.read_step(san.clone()).map_err(|e| e.to_string())?;
+            board = board.try_move(step).ok_or_else(|| format!("illegal move \"{}\"", san))?;
+        }
+        self.board = board;
+        Ok(())
+    }
+
+    // "name Threads value 4"，"name MCTS value c=1.0,w=5"
+    fn set_option(&mut self, args: &str) -> Result<(), String> {
+        let Some((name, value)) = args.strip_prefix("name ").and_then(|s| s.split_once(" value ")) else {
+            return Err(format!("expected \"name <name> value <value>\", found \"{}\"", args));
+        };
+        match name.trim() {
+            "Threads" => {
+                let threads: usize = value.trim().parse().map_err(|_| format!("invalid value \"{}\" for Threads", value))?;
+                self.threads = threads.clamp(1, MAX_THREADS);
+                self.ai = Arc::new(MCTSv2::default().with_config(self.config).with_threads(self.threads));
+                Ok(())
+            },
+            "MCTS" => {
+                let mut config = self.config;
+                for setting in value.split(',').filter(|s| !s.trim().is_empty()) {
+                    let Some((key, value)) = setting.split_once('=') else {
+                        return Err(format!("expected <key>=<value>, found \"{}\"", setting));
+                    };
+                    config.set(key.trim(), value.trim())?;
+                }
+                self.config = config;
+                self.ai = Arc::new(MCTSv2::default().with_config(self.config).with_threads(self.threads));
+                Ok(())
+            },
+            name => Err(format!("unknown option \"{}\"", name)),
+        }
+    }
+
+    // "movetime <ms>"、"nodes <n>" 或 "infinite"，在后台线程中搜索，结束后输出统计信息和 bestmove
+    fn go(&mut self, args: &str, tx: &Sender<EngineEvent>) -> Result<(), String> {
+        let limit = match split_command(args) {
+            ("", _) => SearchLimit::Time(DEFAULT_MOVETIME_MS),
+            ("movetime", ms) => SearchLimit::Time(ms.parse().map_err(|_| format!("invalid movetime \"{}\"", ms))?),
+            ("nodes", n) => SearchLimit::Simulations(n.parse().map_err(|_| format!("invalid nodes \"{}\"", n))?),
+            ("infinite", _) => SearchLimit::Time(u32::MAX),
+            (param, _) => return Err(format!("unknown go parameter \"{}\"", param)),
+        };
+        if self.board.end_game() {
+            println!("bestmove none");
+            return Ok(());
+        }
+
+        let signal = SearchSignal::default();
+        let task_signal = signal.clone();
+        let ai = self.ai.clone();
+        let board = self.board.clone();
+        let tx = tx.clone();
+        let start_time = Instant::now();
+        std::thread::spawn(move || {
+            let step = ai.search(board.clone(), limit, &task_signal);
+            // 时限为 0 时不再搜索，只取出刚才那棵搜索树根节点的统计
+            let stats = ai.analyze(board.clone(), 0, &SearchSignal::default());
+            let best = stats.first().map(|stat| (stat.san.clone(), stat.win_rate));
+            let mut lines = vec![progress(task_signal.simulations(), start_time, best)];
+            lines.extend(stats.iter()
+                .take(INFO_MOVES)
+                .map(|stat| format!(
+                    "info move {} visits {} winrate {:.4} eval {:.4}",
+                    stat.san, stat.visits, stat.win_rate, stat.evaluation,
+                )));
+            lines.push(format!("bestmove {}", board.write_step(step).unwrap_or_default()));
+            let _ = tx.send(EngineEvent::SearchDone(lines));
+        });
+        self.search = Some((signal, start_time));
+        Ok(())
+    }
+}
+
+// 搜索进度，已经有最好的着法时附上它和它对行动方的胜率
+fn progress(visits: u32, start_time: Instant, best: Option<(String, f32)>) -> String {
+    let mut line = format!("info visits {} time {}", visits, start_time.elapsed().as_millis());
+    if let Some((san, win_rate)) = best {
+        line += &format!(" best {} winrate {:.4}", san, win_rate);
+    }
+    line
+}
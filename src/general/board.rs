@@ -1,3 +1,4 @@
+#[cfg(feature = "gui")]
 use bevy::prelude::*;
 
 use crate::general::*;
@@ -5,6 +6,9 @@ use crate::general::*;
 pub trait Board: Default + Clone + Send + Sync + 'static {
     type S: Step;
 
+    // 游戏名，用于文件名等场合
+    const NAME: &'static str;
+
     // 尝试进行一步移动，如果成功，返回移动后的棋盘
     fn try_move(&self, step: Self::S) -> Option<Self> where Self: Sized;
 
@@ -26,22 +30,24 @@ pub trait Board: Default + Clone + Send + Sync + 'static {
     // 获取当前的行动方
     fn get_active_player(&self) -> PlayerOrder;
 
-    // 在一步移动和它的字符串表示间进行转换
-    fn read_step(&self, s: String) -> Option<Self::S>;
+    // 在一步移动和它的字符串表示间进行转换，读取时会检查这一步是否合法
+    fn read_step(&self, s: String) -> Result<Self::S, XinqiParseError>;
     fn write_step(&self, step: Self::S) -> Option<String>;
 
     // 在棋盘状态与字符串表示间进行转换
-    fn read_fen(s: String) -> Option<Self> where Self: Sized;
+    fn read_fen(s: String) -> Result<Self, XinqiParseError> where Self: Sized;
     fn write_fen(&self) -> String;
 }
 
 pub type StepType<B> = <B as Board>::S;
 
+#[cfg(feature = "gui")]
 #[derive(Event)]
 pub struct UpdateBoard<B: Board> {
     pub new_board: B,
 }
 
+#[cfg(feature = "gui")]
 impl<B: Board> UpdateBoard<B> {
     pub fn new(board: B) -> Self {
         Self {
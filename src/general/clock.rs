@@ -0,0 +1,287 @@
+use std::{fmt, time::Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::general::PlayerOrder;
+
+// 计时规则，时间都以毫秒为单位
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum TimeControl {
+    SuddenDeath { main_ms: u32 },                               // 包干
+    Fischer { main_ms: u32, increment_ms: u32 },                // 每走一步加时
+    Byoyomi { main_ms: u32, period_ms: u32, periods: u32 },     // 基本用时之后进入读秒，一步之内用完一次读秒则减少一次
+    PerMove { move_ms: u32 },                                   // 每步限时
+}
+
+// PGN 的 TimeControl 标签使用的格式，以秒为单位：包干 "300"，加时 "300+5"，读秒 "300+3x30"，每步限时 "/30"
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = |ms: u32| ms as f64 / 1000.0;
+        match *self {
+            TimeControl::SuddenDeath { main_ms } => write!(f, "{}", s(main_ms)),
+            TimeControl::Fischer { main_ms, increment_ms } => write!(f, "{}+{}", s(main_ms), s(increment_ms)),
+            TimeControl::Byoyomi { main_ms, period_ms, periods } => write!(f, "{}+{}x{}", s(main_ms), periods, s(period_ms)),
+            TimeControl::PerMove { move_ms } => write!(f, "/{}", s(move_ms)),
+        }
+    }
+}
+
+impl TimeControl {
+    fn initial(&self) -> ClockTime {
+        match *self {
+            TimeControl::SuddenDeath { main_ms } | TimeControl::Fischer { main_ms, .. } => ClockTime { ms: main_ms, periods: 0, overtime: false },
+            TimeControl::Byoyomi { main_ms: 0, period_ms, periods } => ClockTime { ms: period_ms, periods, overtime: true },
+            TimeControl::Byoyomi { main_ms, periods, .. } => ClockTime { ms: main_ms, periods, overtime: false },
+            TimeControl::PerMove { move_ms } => ClockTime { ms: move_ms, periods: 0, overtime: false },
+        }
+    }
+
+    // 用掉 elapsed 毫秒之后剩下的时间，超时返回 None
+    fn spend(&self, mut time: ClockTime, mut elapsed: u32) -> Option<ClockTime> {
+        loop {
+            if elapsed < time.ms {
+                time.ms -= elapsed;
+                return Some(time);
+            }
+            elapsed -= time.ms;
+            // 基本用时用完后进入读秒，此后每用完一次读秒减少一次
+            match *self {
+                TimeControl::Byoyomi { period_ms, .. } if period_ms > 0 => {
+                    if time.overtime {
+                        time.periods = time.periods.saturating_sub(1);
+                    }
+                    if time.periods == 0 {
+                        return None;
+                    }
+                    time.overtime = true;
+                    time.ms = period_ms;
+                },
+                _ => return None,
+            }
+        }
+    }
+
+    // 这一步最多还能用的时间
+    fn available(&self, time: ClockTime) -> u32 {
+        match *self {
+            TimeControl::Byoyomi { period_ms, .. } => {
+                let periods = if time.overtime { time.periods.saturating_sub(1) } else { time.periods };
+                time.ms.saturating_add(period_ms.saturating_mul(periods))
+            },
+            _ => time.ms,
+        }
+    }
+
+    // 走完一步之后的时间
+    fn after_move(&self, mut time: ClockTime) -> ClockTime {
+        match *self {
+            TimeControl::SuddenDeath { .. } => {},
+            TimeControl::Fischer { increment_ms, .. } => time.ms = time.ms.saturating_add(increment_ms),
+            TimeControl::Byoyomi { period_ms, .. } => {
+                if time.overtime {
+                    time.ms = period_ms;
+                }
+            },
+            TimeControl::PerMove { move_ms } => time.ms = move_ms,
+        }
+        time
+    }
+}
+
+// 一方剩余的时间。读秒阶段 ms 是本次读秒剩余的时间，periods 包括正在使用的这一次
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ClockTime {
+    pub ms: u32,
+    pub periods: u32,
+    pub overtime: bool,
+}
+
+impl fmt::Display for ClockTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.overtime {
+            write!(f, "{} ({} left)", format_clock(self.ms), self.periods)
+        } else {
+            write!(f, "{}", format_clock(self.ms))
+        }
+    }
+}
+
+// 显示用的时间，不到 10 秒时显示十分之一秒
+pub fn format_clock(ms: u32) -> String {
+    let seconds = ms / 1000;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else if seconds >= 10 {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format!("0:{:02}.{}", seconds, ms % 1000 / 100)
+    }
+}
+
+// 一盘棋双方的棋钟。棋钟跟随游戏树的主线：主线增加一步时按钟，轮到的一方开始计时
+pub struct Clock {
+    pub control: TimeControl,
+    times: [ClockTime; 2],
+    running: Option<(PlayerOrder, Instant)>,
+    flagged: Option<PlayerOrder>,
+    pub plies: usize, // 已经计时的主线步数
+}
+
+fn index(player: PlayerOrder) -> usize {
+    match player {
+        PlayerOrder::First => 0,
+        PlayerOrder::Second => 1,
+    }
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            times: [control.initial(); 2],
+            running: None,
+            flagged: None,
+            plies: 0,
+        }
+    }
+
+    pub fn running(&self) -> Option<PlayerOrder> {
+        self.running.map(|(player, _)| player)
+    }
+
+    fn elapsed(&self, now: Instant) -> u32 {
+        self.running.map_or(0, |(_, start)| now.saturating_duration_since(start).as_millis().min(u32::MAX as u128) as u32)
+    }
+
+    // player 此刻剩余的时间，超时返回 None
+    pub fn time(&self, player: PlayerOrder, now: Instant) -> Option<ClockTime> {
+        if self.flagged == Some(player) {
+            return None;
+        }
+        let time = self.times[index(player)];
+        match self.running {
+            Some((running, _)) if running == player => self.control.spend(time, self.elapsed(now)),
+            _ => Some(time),
+        }
+    }
+
+    // 正在计时的一方超时多久了
+    pub fn overdue(&self, now: Instant) -> Option<(PlayerOrder, u32)> {
+        let (player, _) = self.running?;
+        let available = self.control.available(self.times[index(player)]);
+        let elapsed = self.elapsed(now);
+        (elapsed >= available).then(|| (player, elapsed - available))
+    }
+
+    pub fn start(&mut self, player: PlayerOrder, now: Instant) {
+        if self.flagged.is_none() {
+            self.running = Some((player, now));
+        }
+    }
+
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((player, _)) = self.running
+            && let Some(time) = self.control.spend(self.times[index(player)], self.elapsed(now)) {
+            self.times[index(player)] = time;
+        }
+        self.running = None;
+    }
+
+    // 正在计时的一方走完一步，返回这一方剩余的时间，超时返回 None
+    pub fn press(&mut self, next: PlayerOrder, now: Instant) -> Option<ClockTime> {
+        let (player, _) = self.running?;
+        let time = self.control.spend(self.times[index(player)], self.elapsed(now))?;
+        let time = self.control.after_move(time);
+        self.times[index(player)] = time;
+        self.start(next, now);
+        Some(time)
+    }
+
+    pub fn flag(&mut self, player: PlayerOrder) {
+        self.flagged = Some(player);
+        self.running = None;
+    }
+
+    pub fn times(&self) -> (ClockTime, ClockTime) {
+        (self.times[0], self.times[1])
+    }
+
+    // 网络对局中以走子一方的时间为准
+    pub fn sync(&mut self, first: ClockTime, second: ClockTime, now: Instant) {
+        self.times = [first, second];
+        if let Some((player, _)) = self.running {
+            self.running = Some((player, now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    fn clock_time(ms: u32, periods: u32, overtime: bool) -> ClockTime {
+        ClockTime { ms, periods, overtime }
+    }
+
+    #[test]
+    fn fischer_adds_the_increment_after_each_move() {
+        let mut clock = Clock::new(TimeControl::Fischer { main_ms: 10_000, increment_ms: 2_000 });
+        let t0 = Instant::now();
+        clock.start(PlayerOrder::First, t0);
+        assert_eq!(clock.time(PlayerOrder::First, at(t0, 3_000)), Some(clock_time(7_000, 0, false)));
+        assert_eq!(clock.press(PlayerOrder::Second, at(t0, 3_000)), Some(clock_time(9_000, 0, false)));
+        assert_eq!(clock.running(), Some(PlayerOrder::Second));
+        // 对方的时间不受影响
+        assert_eq!(clock.press(PlayerOrder::First, at(t0, 4_000)), Some(clock_time(11_000, 0, false)));
+        assert_eq!(clock.times(), (clock_time(9_000, 0, false), clock_time(11_000, 0, false)));
+    }
+
+    #[test]
+    fn byoyomi_consumes_periods() {
+        let mut clock = Clock::new(TimeControl::Byoyomi { main_ms: 1_000, period_ms: 500, periods: 3 });
+        let t0 = Instant::now();
+        clock.start(PlayerOrder::First, t0);
+        // 基本用时用完后进入第一次读秒，走子后读秒时间恢复
+        assert_eq!(clock.time(PlayerOrder::First, at(t0, 1_200)), Some(clock_time(300, 3, true)));
+        assert_eq!(clock.press(PlayerOrder::Second, at(t0, 1_200)), Some(clock_time(500, 3, true)));
+
+        // 一步用掉两次读秒多一点
+        let t1 = at(t0, 2_000);
+        clock.start(PlayerOrder::First, t1);
+        assert_eq!(clock.overdue(at(t1, 1_000)), None);
+        assert_eq!(clock.press(PlayerOrder::Second, at(t1, 1_200)), Some(clock_time(500, 1, true)));
+
+        // 最后一次读秒用完即超时
+        let t2 = at(t1, 2_000);
+        clock.start(PlayerOrder::First, t2);
+        assert_eq!(clock.time(PlayerOrder::First, at(t2, 499)), Some(clock_time(1, 1, true)));
+        assert_eq!(clock.time(PlayerOrder::First, at(t2, 500)), None);
+        assert_eq!(clock.overdue(at(t2, 700)), Some((PlayerOrder::First, 200)));
+        assert_eq!(clock.press(PlayerOrder::Second, at(t2, 700)), None);
+    }
+
+    #[test]
+    fn flagging_at_zero_stops_the_clock() {
+        let mut clock = Clock::new(TimeControl::SuddenDeath { main_ms: 1_000 });
+        let t0 = Instant::now();
+        clock.start(PlayerOrder::First, t0);
+        assert_eq!(clock.time(PlayerOrder::First, at(t0, 999)), Some(clock_time(1, 0, false)));
+        assert_eq!(clock.overdue(at(t0, 999)), None);
+        // 剩余时间正好为零时就算超时
+        assert_eq!(clock.time(PlayerOrder::First, at(t0, 1_000)), None);
+        assert_eq!(clock.overdue(at(t0, 1_000)), Some((PlayerOrder::First, 0)));
+        assert_eq!(clock.press(PlayerOrder::Second, at(t0, 1_000)), None);
+
+        clock.flag(PlayerOrder::First);
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.time(PlayerOrder::First, at(t0, 2_000)), None);
+        assert_eq!(clock.time(PlayerOrder::Second, at(t0, 2_000)), Some(clock_time(1_000, 0, false)));
+        // 超时后不再开始计时
+        clock.start(PlayerOrder::Second, at(t0, 2_000));
+        assert_eq!(clock.running(), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+
+// 读取 FEN、着法、游戏树或 PGN 失败的原因
+#[derive(Clone, Debug, PartialEq)]
+pub struct XinqiParseError {
+    pub line: Option<usize>,   // 从 1 开始，单行文本没有行号
+    pub column: Option<usize>, // 从 1 开始
+    pub field: &'static str,   // 出错的部分，如 "pieces"、"active player"、"node id"
+    pub reason: String,
+}
+
+impl XinqiParseError {
+    pub fn new(field: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            column: None,
+            field,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn at_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    pub fn at(mut self, line: usize, column: usize) -> Self {
+        self.line = Some(line);
+        self.column = Some(column);
+        self
+    }
+
+    // 单行文本嵌入在更长的文本中时，换算成整段文本中的位置。
+    // column 是这一行文本在所在行中的起始列
+    pub fn within(mut self, line: usize, column: usize) -> Self {
+        self.line = Some(line);
+        self.column = Some(self.column.map_or(column, |c| c + column - 1));
+        self
+    }
+}
+
+impl fmt::Display for XinqiParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {}, column {}: ", line, column)?,
+            (Some(line), None) => write!(f, "line {}: ", line)?,
+            (None, Some(column)) => write!(f, "column {}: ", column)?,
+            (None, None) => {},
+        }
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for XinqiParseError {}
+
+// 按空白拆分，同时记录每一段的起始列
+pub fn split_with_columns(s: &str) -> Vec<(usize, &str)> {
+    let mut res = Vec::new();
+    let mut start = None;
+    for (i, c) in s.char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(st)) => {
+                res.push((s[..st].chars().count() + 1, &s[st..i]));
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some(st) = start {
+        res.push((s[..st].chars().count() + 1, &s[st..]));
+    }
+    res
+}
+
+// 把 FEN 中的棋盘部分按 '/' 拆成行，column 是这一部分的起始列
+pub fn split_rows(s: &str, column: usize) -> Vec<(usize, &str)> {
+    let mut res = Vec::new();
+    let mut column = column;
+    for row in s.split('/') {
+        res.push((column, row));
+        column += row.chars().count() + 1;
+    }
+    res
+}
+
+// FEN 开头的 "[name]"
+pub fn check_fen_header(parts: &[(usize, &str)], name: &str) -> Result<(), XinqiParseError> {
+    match parts.first() {
+        Some((_, header)) if *header == format!("[{}]", name) => Ok(()),
+        Some((column, header)) => Err(XinqiParseError::new("header", format!("expected \"[{}]\", found \"{}\"", name, header)).at_column(*column)),
+        None => Err(XinqiParseError::new("header", "empty FEN")),
+    }
+}
+
+// 棋盘部分有几行、每行有几格
+pub fn check_rows(rows: &[(usize, &str)], field: &'static str, column: usize, rows_expected: usize, cells_expected: usize) -> Result<(), XinqiParseError> {
+    if rows.len() != rows_expected {
+        return Err(XinqiParseError::new(field, format!("expected {} rows, found {}", rows_expected, rows.len())).at_column(column));
+    }
+    for (i, (row_column, row)) in rows.iter().enumerate() {
+        let cells = row.chars().count();
+        if cells != cells_expected {
+            return Err(XinqiParseError::new(field, format!("row {} has {} cells, expected {}", i + 1, cells, cells_expected)).at_column(*row_column));
+        }
+    }
+    Ok(())
+}
+
+pub fn invalid_char(field: &'static str, ch: char, column: usize) -> XinqiParseError {
+    XinqiParseError::new(field, format!("invalid character '{}'", ch)).at_column(column)
+}
+
+// 着法中无法识别的部分
+pub fn invalid_step(s: &str, reason: impl fmt::Display) -> XinqiParseError {
+    XinqiParseError::new("step", format!("\"{}\": {}", s, reason))
+}
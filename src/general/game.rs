@@ -8,4 +8,42 @@ pub trait Game: Component<Mutability = Mutable> {
     fn tree(&mut self) -> &mut GameTree<Self::B>;
 
     fn board(&self) -> &Self::B;
+
+    // 双方各自由谁走子
+    fn seats(&mut self) -> &mut Seats;
+
+    // 不计时的对局为 None
+    fn clock(&mut self) -> &mut Option<Clock>;
+
+    // 在游戏树的焦点处走一步，成功时更新棋盘
+    fn try_move(&mut self, step: StepType<Self::B>);
+
+    fn with_time_control(mut self, control: Option<TimeControl>) -> Self
+    where
+        Self: Sized,
+    {
+        if let Some(control) = control {
+            self.tree().set_tag("TimeControl", control.to_string());
+        }
+        *self.clock() = control.map(Clock::new);
+        self
+    }
+
+    // 对局已经在棋盘之外结束时，主线末端不能再走子
+    fn is_over_here(&mut self) -> bool {
+        self.tree().is_over() && self.tree().is_last_board()
+    }
+
+    // 轮到的一方能否由本地玩家在棋盘上走子
+    fn accepts_local_input(&mut self) -> bool {
+        let active = self.board().get_active_player();
+        let at_last_board = self.tree().is_last_board();
+        !self.is_over_here() && self.seats().accepts_local_input(active, at_last_board)
+    }
+
+    // 能否接受网络对局的对方发来的一步
+    fn accepts_remote_input(&mut self) -> bool {
+        let active = self.board().get_active_player();
+        !self.is_over_here() && self.seats().is_remote(active)
+    }
 }
\ No newline at end of file
@@ -3,9 +3,17 @@
 pub mod piece;
 pub mod step; 
 pub mod board;
+#[cfg(feature = "gui")]
 pub mod game;
+pub mod error;
+pub mod seat;
+pub mod clock;
 
 pub use piece::*;
 pub use step::*;
 pub use board::*;
-pub use game::*;
\ No newline at end of file
+#[cfg(feature = "gui")]
+pub use game::*;
+pub use error::*;
+pub use seat::*;
+pub use clock::*;
\ No newline at end of file
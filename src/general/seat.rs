@@ -0,0 +1,103 @@
+use crate::{ai::SearchLimit, general::PlayerOrder};
+
+// 为一方走子的 AI
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AiEngine {
+    WeakMCTS, // 朴素 MCTS
+    MCTSv2,   // 使用这种棋的 MCTS settings
+    External, // 菜单中设置的外部引擎
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AiSeat {
+    pub engine: AiEngine,
+    pub limit: SearchLimit,
+}
+
+impl Default for AiSeat {
+    fn default() -> Self {
+        Self {
+            engine: AiEngine::MCTSv2,
+            limit: SearchLimit::Time(2000),
+        }
+    }
+}
+
+// 一方由谁走子
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Controller {
+    #[default]
+    Human,
+    Remote, // 网络对局的对方
+    AI(AiSeat),
+}
+
+// 先后手双方各自由谁走子
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Seats {
+    pub first: Controller,
+    pub second: Controller,
+}
+
+impl Seats {
+    // 网络对局，remote_player 一方由对方走子，另一方由本地的 local 走子
+    pub fn remote(remote_player: PlayerOrder, local: Controller) -> Self {
+        let mut seats = Self::default();
+        *seats.get_mut(remote_player) = Controller::Remote;
+        *seats.get_mut(remote_player.flip()) = local;
+        seats
+    }
+
+    pub fn get(&self, player: PlayerOrder) -> &Controller {
+        match player {
+            PlayerOrder::First => &self.first,
+            PlayerOrder::Second => &self.second,
+        }
+    }
+
+    pub fn get_mut(&mut self, player: PlayerOrder) -> &mut Controller {
+        match player {
+            PlayerOrder::First => &mut self.first,
+            PlayerOrder::Second => &mut self.second,
+        }
+    }
+
+    pub fn is_remote(&self, player: PlayerOrder) -> bool {
+        *self.get(player) == Controller::Remote
+    }
+
+    pub fn ai(&self, player: PlayerOrder) -> Option<AiSeat> {
+        match self.get(player) {
+            Controller::AI(seat) => Some(*seat),
+            _ => None,
+        }
+    }
+
+    pub fn has_ai(&self) -> bool {
+        self.ai(PlayerOrder::First).is_some() || self.ai(PlayerOrder::Second).is_some()
+    }
+
+    // 网络对局中本地执掌的一方
+    pub fn local_player(&self) -> Option<PlayerOrder> {
+        match (self.first, self.second) {
+            (Controller::Remote, Controller::Remote) => None,
+            (Controller::Remote, _) => Some(PlayerOrder::Second),
+            (_, Controller::Remote) => Some(PlayerOrder::First),
+            _ => None,
+        }
+    }
+
+    // 本地走出的每一步都要发送给对方
+    pub fn has_remote(&self) -> bool {
+        self.first == Controller::Remote || self.second == Controller::Remote
+    }
+
+    // 轮到 player 时能否在棋盘上点击走子。AI 只在游戏树末端自动走子，复盘时 AI 一方也可以由人走子
+    pub fn accepts_local_input(&self, player: PlayerOrder, at_last_board: bool) -> bool {
+        match self.get(player) {
+            Controller::Human => true,
+            Controller::Remote => false,
+            Controller::AI(_) => !at_last_board,
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::graphics::interaction::*;
 
 pub mod entity;
 pub mod interaction;
+pub mod overlay;
 pub struct XinqiGraphicsPlugin;
 
 impl Plugin for XinqiGraphicsPlugin {
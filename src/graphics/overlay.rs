@@ -0,0 +1,76 @@
+// 在棋盘上叠加 AI 候选着法的热力图
+use bevy::prelude::*;
+use crate::general::*;
+
+const HEAT_Z: f32 = 1.5; // 在格子之上、棋子之下
+const MARKER_Z: f32 = 2.5; // 在棋子之上
+
+// 由分析窗口写入，各个棋的绘制代码读取
+#[derive(Resource)]
+pub struct BoardOverlay<B: Board> {
+    pub fen: String, // 候选着法所在的局面
+    pub moves: Vec<(StepType<B>, f32)>, // 候选着法和热度（0 到 1），第一个是最佳着法
+}
+
+impl<B: Board> Default for BoardOverlay<B> {
+    fn default() -> Self {
+        Self {
+            fen: String::new(),
+            moves: Vec::new(),
+        }
+    }
+}
+
+impl<B: Board> BoardOverlay<B> {
+    // 只有局面一致时才显示候选着法
+    pub fn moves_for(&self, board: &B) -> &[(StepType<B>, f32)] {
+        if self.moves.is_empty() || board.write_fen() != self.fen {
+            &[]
+        } else {
+            &self.moves
+        }
+    }
+}
+
+// 热度从低到高由蓝变红
+pub fn heat_color(heat: f32) -> Color {
+    let heat = heat.clamp(0.0, 1.0);
+    Color::srgba(heat, 0.2, 1.0 - heat, 0.3 + 0.4 * heat)
+}
+
+pub fn spawn_heat_cell(commands: &mut Commands, center: Vec2, size: Vec2, heat: f32) -> Entity {
+    commands.spawn((
+        Sprite::from_color(heat_color(heat), size),
+        Transform::from_translation(center.extend(HEAT_Z)),
+    )).id()
+}
+
+// 最佳着法用绿色菱形标出
+pub fn spawn_best_marker(commands: &mut Commands, center: Vec2, size: Vec2) -> Entity {
+    commands.spawn((
+        Sprite::from_color(Color::srgb(0.1, 0.8, 0.2), size * 0.3),
+        Transform::from_translation(center.extend(MARKER_Z))
+            .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+    )).id()
+}
+
+// 同一个格子上有多个候选着法时取最高的热度
+pub fn heat_per_cell<S: Step>(moves: &[(S, f32)], cell_of: impl Fn(&S) -> Option<(usize, usize)>) -> Vec<((usize, usize), f32)> {
+    let mut cells: Vec<((usize, usize), f32)> = Vec::new();
+    for (step, heat) in moves {
+        let Some(pos) = cell_of(step) else {
+            continue;
+        };
+        match cells.iter_mut().find(|(p, _)| *p == pos) {
+            Some((_, h)) => *h = h.max(*heat),
+            None => cells.push((pos, *heat)),
+        }
+    }
+    cells
+}
+
+pub fn despawn_overlay(commands: &mut Commands, overlay: &mut Vec<Entity>) {
+    for e in overlay.drain(..) {
+        commands.entity(e).despawn();
+    }
+}
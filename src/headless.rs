@@ -0,0 +1,176 @@
+// 没有窗口的命令行模式，只用到 general、tree 和 ai，便于在没有显示器的服务器上用脚本做 AI 实验
+use std::{path::PathBuf, str::FromStr, time::Instant};
+
+use crate::{ai::{mctsv2::{MCTSConfig, MCTSv2}, Heuristic, MoveStat, SearchLimit, SearchSignal, AI}, general::*, hequn::general::HequnBoard, tree::game_tree::GameTree, xingxiang::general::XingxiangBoard, zhandi::general::ZhandiBoard};
+
+const USAGE: &str = "\
+usage: xinqi --headless <hequn|zhandi|xingxiang> [options]
+  --fen <fen>        start from this position
+  --file <path>      start from the end of the main line in a .fen, .tree or .pgn file
+  --time <ms>        thinking time per move, default 2000
+  --sims <n>         simulations per move instead of a time limit; with seed=<n> in --mcts the result is reproducible
+  --threads <n>      search threads, default 1
+  --mcts <settings>  comma separated MCTS settings, e.g. c=1.0,w=5,eval_temp=10,depth=20,select=visits|winrate|<t>,seed=1
+  --top <n>          number of candidate moves to print, default 5
+  --play             play the game out instead of analyzing a single position
+  --max-moves <n>    stop playing after this many moves
+  --out <path>       save the played game as .tree, .fen or .pgn";
+
+struct Options {
+    game: String,
+    fen: Option<String>,
+    file: Option<PathBuf>,
+    limit: SearchLimit,
+    threads: usize,
+    config: MCTSConfig,
+    top_n: usize,
+    play: bool,
+    max_moves: Option<usize>,
+    out: Option<PathBuf>,
+}
+
+fn parse_number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value \"{}\" for {}", value, option))
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter();
+        let game = args.next().ok_or("missing game name")?;
+        let mut options = Self {
+            game: game.clone(),
+            fen: None,
+            file: None,
+            limit: SearchLimit::Time(2000),
+            threads: 1,
+            config: MCTSConfig::default(),
+            top_n: 5,
+            play: false,
+            max_moves: None,
+            out: None,
+        };
+        while let Some(option) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", option));
+            match option.as_str() {
+                "--fen" => options.fen = Some(value()?.clone()),
+                "--file" => options.file = Some(PathBuf::from(value()?)),
+                "--time" => options.limit = SearchLimit::Time(parse_number(option, value()?)?),
+                "--sims" => options.limit = SearchLimit::Simulations(parse_number(option, value()?)?),
+                "--threads" => options.threads = parse_number(option, value()?)?,
+                "--mcts" => {
+                    for setting in value()?.split(',') {
+                        let Some((key, value)) = setting.split_once('=') else {
+                            return Err(format!("expected <key>=<value>, found \"{}\"", setting));
+                        };
+                        options.config.set(key.trim(), value.trim())?;
+                    }
+                },
+                "--top" => options.top_n = parse_number(option, value()?)?,
+                "--play" => options.play = true,
+                "--max-moves" => options.max_moves = Some(parse_number(option, value()?)?),
+                "--out" => options.out = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("unknown option {}", option)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// cargo run --release -- --headless <game> [options]
+pub fn run(args: &[String]) {
+    let result = Options::parse(args).and_then(|options| match options.game.as_str() {
+        HequnBoard::NAME => run_game::<HequnBoard>(&options),
+        ZhandiBoard::NAME => run_game::<ZhandiBoard>(&options),
+        XingxiangBoard::NAME => run_game::<XingxiangBoard>(&options),
+        game => Err(format!("unknown game \"{}\"", game)),
+    });
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+}
+
+fn run_game<B: Heuristic>(options: &Options) -> Result<(), String> {
+    let mut tree = match (&options.fen, &options.file) {
+        (Some(_), Some(_)) => return Err("--fen and --file cannot be used together".to_string()),
+        (Some(fen), None) => GameTree::new(B::read_fen(fen.clone()).map_err(|e| e.to_string())?),
+        (None, Some(path)) => GameTree::from_file(path)?,
+        (None, None) => GameTree::new(B::default()),
+    };
+    tree.focus_mainline_end();
+    let ai = MCTSv2::<B>::default().with_config(options.config).with_threads(options.threads);
+
+    if !options.play {
+        let board = tree.board();
+        println!("{}", board.write_fen());
+        if board.end_game() {
+            println!("game over: {}", board.game_info());
+            return Ok(());
+        }
+        let (step, stats, simulations, seconds) = think(&ai, &board, options.limit);
+        println!("best move: {}", board.write_step(step).unwrap_or_default());
+        println!(
+            "simulations: {} in {:.2} s ({:.0}/s, {} threads)",
+            simulations, seconds, simulations as f64 / seconds, ai.threads(),
+        );
+        print_stats(&stats, options.top_n);
+        return Ok(());
+    }
+
+    play_out(&ai, &mut tree, options);
+    println!();
+    println!("{}", tree.to_pgn());
+    if let Some(path) = &options.out {
+        tree.save_file(path)?;
+        println!("saved to {}", path.display());
+    }
+    Ok(())
+}
+
+// 搜索一步，返回选中的着法、根节点的统计信息、模拟次数和用时（秒）
+fn think<B: Heuristic>(ai: &MCTSv2<B>, board: &B, limit: SearchLimit) -> (B::S, Vec<MoveStat<B::S>>, u32, f64) {
+    let signal = SearchSignal::default();
+    let start_time = Instant::now();
+    let step = ai.search(board.clone(), limit, &signal);
+    let seconds = start_time.elapsed().as_secs_f64();
+    // 时限为 0 时不再搜索，只取出刚才那棵搜索树根节点的统计
+    let stats = ai.analyze(board.clone(), 0, &SearchSignal::default());
+    (step, stats, signal.simulations(), seconds)
+}
+
+fn print_stats<S: Step>(stats: &[MoveStat<S>], top_n: usize) {
+    // 胜率和估值都是当前行动方的
+    println!("{:<12} {:>10} {:>9} {:>9}", "move", "visits", "win rate", "eval");
+    for stat in stats.iter().take(top_n) {
+        println!(
+            "{:<12} {:>10} {:>8.1}% {:>8.1}%",
+            stat.san, stat.visits, stat.win_rate * 100.0, stat.evaluation * 100.0,
+        );
+    }
+}
+
+// 双方都由 AI 走子，直到对局结束或达到步数上限，每一步打印一行
+fn play_out<B: Heuristic>(ai: &MCTSv2<B>, tree: &mut GameTree<B>, options: &Options) {
+    let mut moves = 0;
+    println!("{}", tree.board().write_fen());
+    while !tree.board().end_game() && options.max_moves.is_none_or(|max| moves < max) {
+        let board = tree.board();
+        let (step, stats, simulations, _) = think(ai, &board, options.limit);
+        let san = board.write_step(step).unwrap_or_default();
+        let win_rate = stats.iter().find(|stat| stat.step == step).map_or(0.0, |stat| stat.win_rate);
+        let number = match board.get_active_player() {
+            PlayerOrder::First => format!("{}.", board.get_fullmove()),
+            PlayerOrder::Second => format!("{}...", board.get_fullmove()),
+        };
+        println!("{:>6} {:<12} {:>10} simulations, win rate {:>5.1}%", number, san, simulations, win_rate * 100.0);
+        tree.try_move(step);
+        moves += 1;
+    }
+    let board = tree.board();
+    if board.end_game() {
+        println!("{} ({})", board.game_info(), tree.result());
+    } else {
+        println!("stopped after {} moves", moves);
+    }
+}
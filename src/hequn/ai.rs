@@ -1,4 +1,4 @@
-use crate::{hequn::{general::*, utils::*}};
+use crate::{ai::Heuristic, hequn::{general::*, utils::*}};
 use std::{sync::OnceLock};
 
 static LOCAL_RANDIAN: OnceLock<[[Vec<((usize, usize), (usize, usize))>; 5]; 5]> = OnceLock::new();
@@ -133,7 +133,7 @@ fn evaluate_local(pieces: &Vec<Vec<HequnPiecePlus>>, cells: &Vec<Vec<HequnCell>>
     }
 }
 
-pub fn evaluate(board: &HequnBoard) -> f32 {
+pub fn evaluate_with(board: &HequnBoard, temperature: f32) -> f32 {
     let mut res = 0.0;
     let mut pieces = vec![vec![HequnPiecePlus::Wall; 14]; 14];
     for x in 0..BOARD_SIZE_I {
@@ -151,13 +151,34 @@ pub fn evaluate(board: &HequnBoard) -> f32 {
             res += evaluate_local(&pieces, &board.cells, x, y);
         }
     }
-    // 把目差估计转换为（先手方）胜率估计
-    1.0 / (1.0 + (-res / TEMERATURE).exp())
+    // 把目差估计转换为黑方胜率估计
+    let black = 1.0 / (1.0 + (-res / temperature).exp());
+    // 再转换为先手方胜率估计，交换开局时后手方总会选择更有利的颜色
+    match board.phase {
+        HequnPhase::Play => match board.first_color {
+            HequnPiece::Black => black,
+            HequnPiece::White => 1.0 - black,
+        },
+        HequnPhase::Place(_) | HequnPhase::Choose => black.min(1.0 - black),
+    }
 }
 
-const TEMERATURE: f32 = 10.0;
+pub const TEMPERATURE: f32 = 10.0;
 
 pub fn quick_move(board: &HequnBoard) -> Vec<HequnStep> {
+    // 选择颜色时直接选择估值更高的一方
+    if board.phase == HequnPhase::Choose {
+        let black = evaluate_with(&HequnBoard {
+            phase: HequnPhase::Play,
+            ..board.clone()
+        }, TEMPERATURE);
+        return if black >= 0.5 {
+            vec![HequnStep::Choose(HequnPiece::Black)]
+        } else {
+            vec![HequnStep::Choose(HequnPiece::White)]
+        };
+    }
+
     let mut step_results: Vec<(HequnStep, i32)> = Vec::new();
     
     let mut pieces = vec![vec![HequnPiecePlus::Wall; 14]; 14];
@@ -193,7 +214,10 @@ pub fn quick_move(board: &HequnBoard) -> Vec<HequnStep> {
         }
     }
 
-    step_results.push((HequnStep::Pass, 0));
+    // 开局放置阶段不能虚手
+    if board.phase == HequnPhase::Play {
+        step_results.push((HequnStep::Pass, 0));
+    }
 
     for x in 0..BOARD_SIZE_I {
         for y in 0..BOARD_SIZE_J {
@@ -218,4 +242,16 @@ pub fn quick_move(board: &HequnBoard) -> Vec<HequnStep> {
         .filter(|(_, score)| *score == max_score)
         .map(|(step, _)| step)
         .collect();
-}
\ No newline at end of file
+}
+
+impl Heuristic for HequnBoard {
+    const TEMPERATURE: f32 = TEMPERATURE;
+
+    fn evaluate_with(&self, temperature: f32) -> f32 {
+        evaluate_with(self, temperature)
+    }
+
+    fn quick_move(&self) -> Vec<HequnStep> {
+        quick_move(self)
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::{asset::RenderAssetUsages, prelude::*, render::render_resource::{Extent3d, TextureDimension, TextureFormat}};
 
-use crate::{general::*, graphics::{entity::{CellCom, Shape}, interaction::{ClickEvent, DragEvent}}, hequn::{general::{HequnBoard, HequnStep}, utils::*}, net::message::{ReceiveRemoteStep, SendRemoteStep}, tree::game_tree::GameTree};
+use crate::{general::*, graphics::{entity::{CellCom, Shape}, interaction::{ClickEvent, DragEvent}, overlay::*}, hequn::{general::{HequnBoard, HequnStep}, utils::*}, net::message::{try_move_and_send, ReceiveRemoteStep, SendRemoteStep}, tree::game_tree::GameTree};
 
 #[derive(Component)]
 pub struct HequnGame {
@@ -10,29 +10,38 @@ pub struct HequnGame {
     cells: Vec<Vec<Entity>>,
     pieces: Vec<Vec<Entity>>,
     background: Entity,
+    overlay: Vec<Entity>,
     updated: bool,
     
-    remote_play: Option<PlayerOrder>, 
+    seats: Seats,
+    clock: Option<Clock>,
 }
 
 impl HequnGame {
-    pub fn new(remote_play: Option<PlayerOrder>) -> Self {
+    pub fn with_board(board: HequnBoard, seats: Seats) -> Self {
+        Self::with_tree(GameTree::new(board), seats)
+    }
+    // 从游戏树的焦点处继续对局
+    pub fn with_tree(tree: GameTree<HequnBoard>, seats: Seats) -> Self {
         Self {
-            board: HequnBoard::default(),
-            tree: GameTree::new(HequnBoard::default()),
+            board: tree.board(),
+            tree,
             rect: Rect::from_center_size(Vec2::ZERO, Vec2::new(600.0, 600.0)),
             cells: vec![vec![Entity::PLACEHOLDER; BOARD_SIZE_J]; BOARD_SIZE_I],
             pieces: vec![vec![Entity::PLACEHOLDER; BOARD_SIZE_J]; BOARD_SIZE_I],
             background: Entity::PLACEHOLDER,
+            overlay: Vec::new(),
             updated: false,
-            remote_play,
+            seats,
+            clock: None,
         }
     }
-    pub fn try_move(&mut self, step: HequnStep) {
-        if self.tree.try_move(step) {
-            self.updated = false;
-            self.board = self.tree.board();
+    // 由本地玩家通过按钮等方式走出的一步，网络对局中同时发送给对方
+    pub fn try_local_move(&mut self, step: HequnStep, ew_remote: &mut EventWriter<SendRemoteStep>) {
+        if !self.accepts_local_input() {
+            return;
         }
+        try_move_and_send(self, step, ew_remote);
     }
 }
 
@@ -46,6 +55,21 @@ impl Game for HequnGame {
     fn board(&self) -> &Self::B {
         &self.board
     }
+
+    fn seats(&mut self) -> &mut Seats {
+        &mut self.seats
+    }
+
+    fn clock(&mut self) -> &mut Option<Clock> {
+        &mut self.clock
+    }
+
+    fn try_move(&mut self, step: HequnStep) {
+        if self.tree.try_move(step) {
+            self.updated = false;
+            self.board = self.tree.board();
+        }
+    }
 }
 
 #[derive(Resource)]
@@ -224,10 +248,7 @@ fn draw(
             };
             game.pieces[x][y] = piece;
 
-            let clickable = match game.remote_play {
-                Some(remote_player) => remote_player != game.board.get_active_player(),
-                None => true,
-            };
+            let clickable = game.accepts_local_input();
             let cell = commands.spawn((
                 CellCom {
                     shape: Shape::Rect { rect: Rect::from_center_size(leftdown + Vec2::new(x as f32 * dx, y as f32 * dy), dcell_size) },
@@ -255,6 +276,31 @@ fn draw(
     }
 }
 
+// 在空格上显示 AI 候选着法的热度，并标出最佳着法
+fn draw_overlay(
+    commands: &mut Commands,
+    game: &mut HequnGame,
+    overlay: &BoardOverlay<HequnBoard>,
+) {
+    let moves = overlay.moves_for(&game.board);
+    let leftdown = game.rect.center() - game.rect.size() * 0.375;
+    let cell_size = game.rect.size() / 12.0;
+    let (dx, dy) = (cell_size.x, cell_size.y);
+    let dcell_size = cell_size - Vec2::new(2.0, 2.0);
+    let cell_of = |step: &HequnStep| match step {
+        HequnStep::Pos(x, y) => Some((*x, *y)),
+        _ => None,
+    };
+    for ((x, y), heat) in heat_per_cell(moves, cell_of) {
+        let center = leftdown + Vec2::new(x as f32 * dx, y as f32 * dy);
+        game.overlay.push(spawn_heat_cell(commands, center, dcell_size, heat));
+    }
+    if let Some((x, y)) = moves.first().and_then(|(step, _)| cell_of(step)) {
+        let center = leftdown + Vec2::new(x as f32 * dx, y as f32 * dy);
+        game.overlay.push(spawn_best_marker(commands, center, dcell_size));
+    }
+}
+
 fn clear(
     commands: &mut Commands,
     game: &mut HequnGame,
@@ -283,50 +329,26 @@ fn update(
     er_remote: &mut EventReader<ReceiveRemoteStep>,
     ew_remote: &mut EventWriter<SendRemoteStep>,
     textures: &HequnTextureAssets,
+    overlay: &Res<BoardOverlay<HequnBoard>>,
 ) {
-    match game.remote_play {
-        Some(remote_player) => {
-            for event in er_click.read() {
-                if game.board.get_active_player() == remote_player {
-                    break;
-                }
-                for x in 0..BOARD_SIZE_I {
-                    for y in 0..BOARD_SIZE_J {
-                        if game.cells[x][y] == event.cell {
-                            let step = HequnStep::Pos(x, y);
-                            if let Some(step_str) = game.board.write_step(step) {
-                                game.try_move(step);
-                                // info!("hequn send step: {}", step_str);
-                                ew_remote.write(SendRemoteStep { step: step_str });
-                            }
-                        }
-                    }
+    for event in er_click.read() {
+        for x in 0..BOARD_SIZE_I {
+            for y in 0..BOARD_SIZE_J {
+                if game.cells[x][y] == event.cell {
+                    game.try_local_move(HequnStep::Pos(x, y), ew_remote);
                 }
             }
+        }
+    }
 
-            for event in er_remote.read() {
-                // info!("hequn receive step: {}", event.step);
-                if game.board.get_active_player() == remote_player.flip() {
-                    break;
-                }
-                //info!("player correct");
-                if let Some(step) = game.board.read_step(event.step.clone()) {
-                    // info!("step valid");
-                    game.try_move(step);
-                }
-            }
-        },
-        None => {
-            for event in er_click.read() {
-                for x in 0..BOARD_SIZE_I {
-                    for y in 0..BOARD_SIZE_J {
-                        if game.cells[x][y] == event.cell {
-                            game.try_move(HequnStep::Pos(x, y));
-                        }
-                    }
-                }
-            }
-        },
+    for event in er_remote.read() {
+        // info!("hequn receive step: {}", event.step);
+        if !game.accepts_remote_input() {
+            break;
+        }
+        if let Ok(step) = game.board.read_step(event.step.clone()) {
+            game.try_move(step);
+        }
     }
 
     for event in er_update.read() {
@@ -334,6 +356,11 @@ fn update(
         game.board = event.new_board.clone();
     }
 
+    if !game.updated || overlay.is_changed() {
+        despawn_overlay(commands, &mut game.overlay);
+        draw_overlay(commands, game, overlay);
+    }
+
     if !game.updated {
         clear(commands, game);
         draw(commands, textures, game);
@@ -350,9 +377,10 @@ pub fn hequn_update(
     mut er_remote: EventReader<ReceiveRemoteStep>,
     mut ew_remote: EventWriter<SendRemoteStep>,
     textures: Res<HequnTextureAssets>,
+    overlay: Res<BoardOverlay<HequnBoard>>,
 ) {
     for mut game in q_game.iter_mut() {
-        update(&mut commands, &mut game, &mut er_drag, &mut er_click, &mut er_update, &mut er_remote, &mut ew_remote, &textures);
+        update(&mut commands, &mut game, &mut er_drag, &mut er_click, &mut er_update, &mut er_remote, &mut ew_remote, &textures, &overlay);
     }
 }
 
@@ -386,6 +414,9 @@ fn cleanup_game_entities(commands: &mut Commands, game: &HequnGame) {
     if game.background != Entity::PLACEHOLDER {
         commands.entity(game.background).despawn();
     }
+    for e in &game.overlay {
+        commands.entity(*e).despawn();
+    }
     
     // 清理所有格子和棋子
     for x in 0..BOARD_SIZE_I {
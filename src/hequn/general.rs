@@ -8,7 +8,7 @@ pub enum HequnPiece {
 }
 
 impl HequnPiece {
-    fn flip(&self) -> Self {
+    pub fn flip(&self) -> Self {
         match self {
             HequnPiece::Black => HequnPiece::White,
             HequnPiece::White => HequnPiece::Black,
@@ -20,6 +20,7 @@ impl HequnPiece {
 pub enum HequnStep {
     Pos(usize, usize),
     Pass,
+    Choose(HequnPiece), // 三手交换中后手方选择自己执的颜色
 }
 
 impl Step for HequnStep {}
@@ -31,6 +32,17 @@ pub enum HequnCell {
     Colored(HequnPiece),
 }
 
+// 三手交换规则：先手方依次放置黑、白、黑三子，然后后手方选择执黑或执白，之后正常进行
+#[derive(Clone, Copy, PartialEq)]
+pub enum HequnPhase {
+    Place(usize), // 先手方正在放置开局的第 n 个子 (n = 0, 1, 2)
+    Choose,       // 后手方选择颜色
+    Play,         // 正常对局
+}
+
+// 开局放置的子数
+pub const SWAP_STONES: usize = 3;
+
 #[derive(Clone)]
 pub struct HequnBoard {
     pub pieces: Vec<Vec<Option<HequnPiece>>>,
@@ -41,37 +53,34 @@ pub struct HequnBoard {
     pub black_score: usize,
     pub white_score: usize,
     pub fullmove: usize,
+    pub phase: HequnPhase,
+    pub first_color: HequnPiece, // 先手方所执的颜色，不使用交换规则时恒为黑
 }
 
-impl Default for HequnBoard {
-    fn default() -> Self {
-        Self { 
-            pieces: (0..BOARD_SIZE_I).map(|_| (0..BOARD_SIZE_J).map(|_| None).collect()).collect(), 
-            cells: (0..BOARD_SIZE_I).map(|_| (0..BOARD_SIZE_J).map(|_| HequnCell::default()).collect()).collect(), 
-            active_player: HequnPiece::Black, 
-            last_pass: false,
-            end: false,
-            black_score: 0,
-            white_score: 0,
-            fullmove: 1,
+impl HequnBoard {
+    // 使用三手交换规则的初始局面
+    pub fn swap_opening() -> Self {
+        Self {
+            phase: HequnPhase::Place(0),
+            ..Default::default()
         }
     }
-}
 
-impl Board for HequnBoard {
-    type S = HequnStep;
-
-    fn try_move(&self, step: Self::S) -> Option<Self> where Self: Sized {
+    // 与 try_move 相同，但在这一步不合法时给出原因
+    pub fn checked_move(&self, step: HequnStep) -> Result<Self, &'static str> {
         if self.end {
-            return None
+            return Err("the game is over")
         }
         match step {
             HequnStep::Pos(x, y) => {
-                if x > BOARD_SIZE_I || y > BOARD_SIZE_J {
-                    return None
+                if x >= BOARD_SIZE_I || y >= BOARD_SIZE_J {
+                    return Err("the cell is outside the board")
+                }
+                if self.phase == HequnPhase::Choose {
+                    return Err("the second player must choose a color first")
                 }
                 match self.pieces[x][y] {
-                    Some(_) => None,
+                    Some(_) => Err("the cell is occupied"),
                     None => {
                         let mut pieces = self.pieces.clone();
                         pieces[x][y] = Some(self.active_player);
@@ -108,7 +117,13 @@ impl Board for HequnBoard {
                             }
                         }
 
-                        Some(Self {
+                        let phase = match self.phase {
+                            HequnPhase::Place(n) if n + 1 < SWAP_STONES => HequnPhase::Place(n + 1),
+                            HequnPhase::Place(_) => HequnPhase::Choose,
+                            _ => HequnPhase::Play,
+                        };
+
+                        Ok(Self {
                             pieces,
                             cells,
                             active_player: self.active_player.flip(),
@@ -120,12 +135,17 @@ impl Board for HequnBoard {
                                 HequnPiece::Black => self.fullmove,
                                 HequnPiece::White => self.fullmove + 1,
                             },
+                            phase,
+                            first_color: self.first_color,
                         })
                     },
                 }
             },
             HequnStep::Pass => {
-                Some(Self {
+                if self.phase != HequnPhase::Play {
+                    return Err("passing is not allowed during the opening")
+                }
+                Ok(Self {
                     active_player: self.active_player.flip(),
                     last_pass: true,
                     end: self.last_pass,
@@ -136,13 +156,62 @@ impl Board for HequnBoard {
                     ..self.clone()
                 })
             },
+            HequnStep::Choose(color) => {
+                if self.phase != HequnPhase::Choose {
+                    return Err("a color can only be chosen after the opening stones are placed")
+                }
+                // 后手方选择颜色后，由白方继续行动
+                Ok(Self {
+                    phase: HequnPhase::Play,
+                    first_color: color.flip(),
+                    ..self.clone()
+                })
+            },
+        }
+    }
+
+    pub fn player_of(&self, color: HequnPiece) -> PlayerOrder {
+        if color == self.first_color {
+            PlayerOrder::First
+        } else {
+            PlayerOrder::Second
         }
     }
+}
+
+impl Default for HequnBoard {
+    fn default() -> Self {
+        Self { 
+            pieces: (0..BOARD_SIZE_I).map(|_| (0..BOARD_SIZE_J).map(|_| None).collect()).collect(), 
+            cells: (0..BOARD_SIZE_I).map(|_| (0..BOARD_SIZE_J).map(|_| HequnCell::default()).collect()).collect(), 
+            active_player: HequnPiece::Black, 
+            last_pass: false,
+            end: false,
+            black_score: 0,
+            white_score: 0,
+            fullmove: 1,
+            phase: HequnPhase::Play,
+            first_color: HequnPiece::Black,
+        }
+    }
+}
+
+impl Board for HequnBoard {
+    type S = HequnStep;
+
+    const NAME: &'static str = "hequn";
+
+    fn try_move(&self, step: Self::S) -> Option<Self> where Self: Sized {
+        self.checked_move(step).ok()
+    }
 
     fn all_move(&self) -> Vec<Self::S> {
         if self.end {
             return Vec::new();
         }
+        if self.phase == HequnPhase::Choose {
+            return vec![Self::S::Choose(HequnPiece::Black), Self::S::Choose(HequnPiece::White)];
+        }
         let mut res = (0..BOARD_SIZE_I).flat_map(|x| {
             (0..BOARD_SIZE_J).filter_map(move |y| {
                 match self.pieces[x][y] {
@@ -151,7 +220,9 @@ impl Board for HequnBoard {
                 }
             })
         }).collect::<Vec<_>>();
-        res.push(Self::S::Pass);
+        if self.phase == HequnPhase::Play {
+            res.push(Self::S::Pass);
+        }
         res
     }
 
@@ -161,11 +232,11 @@ impl Board for HequnBoard {
     
     fn get_winner(&self) -> Option<PlayerOrder> {
         if self.black_score > self.white_score {
-            Some(PlayerOrder::First)
+            Some(self.player_of(HequnPiece::Black))
         } else if self.black_score == self.white_score {
             None
         } else {
-            Some(PlayerOrder::Second)
+            Some(self.player_of(HequnPiece::White))
         }
     }
 
@@ -179,39 +250,41 @@ impl Board for HequnBoard {
                 "White Win"
             }
         } else {
-            match self.active_player {
-                HequnPiece::Black => "Black Play",
-                HequnPiece::White => "White Play",
+            match self.phase {
+                HequnPhase::Place(_) => match self.active_player {
+                    HequnPiece::Black => "Opening: First Places Black",
+                    HequnPiece::White => "Opening: First Places White",
+                },
+                HequnPhase::Choose => "Opening: Second Chooses Color",
+                HequnPhase::Play => match self.active_player {
+                    HequnPiece::Black => "Black Play",
+                    HequnPiece::White => "White Play",
+                },
             }
         }
     }
 
-    fn read_step(&self, s: String) -> Option<Self::S> {
-        if self.end {
-            return None;
-        }
-        if s == "pass" {
-            return Some(HequnStep::Pass);
-        }
-
-        let mut chars = s.chars();
-
-        if let Some(first_char) = chars.next() {
-            let first_char = first_char as u8;
-            if first_char >= b'a' && first_char < b'a' + BOARD_SIZE_I as u8 {
-                let x = (first_char - b'a') as usize;
+    fn read_step(&self, s: String) -> Result<Self::S, XinqiParseError> {
+        let step = match s.as_str() {
+            "pass" => HequnStep::Pass,
+            "black" => HequnStep::Choose(HequnPiece::Black),
+            "white" => HequnStep::Choose(HequnPiece::White),
+            _ => {
+                let mut chars = s.chars();
+                let x = match chars.next() {
+                    Some(c) if c >= 'a' && c < (b'a' + BOARD_SIZE_I as u8) as char => (c as u8 - b'a') as usize,
+                    _ => return Err(invalid_step(&s, "expected a column letter, \"pass\", \"black\" or \"white\"")),
+                };
                 let num_str: String = chars.collect();
-                if let Ok(y) = num_str.parse::<usize>() {
-                    if y == 0 { return None } // 由于类型限制必须单独处理
-                    let y = y - 1;
-                    if y < BOARD_SIZE_J && self.pieces[x][y].is_none() {
-                        return Some(HequnStep::Pos(x, y));
-                    }
-                }
-            }
-        }
-
-        None
+                let y = match num_str.parse::<usize>() {
+                    Ok(y) if (1..=BOARD_SIZE_J).contains(&y) => y - 1,
+                    _ => return Err(invalid_step(&s, format!("expected a row number from 1 to {}", BOARD_SIZE_J))),
+                };
+                HequnStep::Pos(x, y)
+            },
+        };
+        self.checked_move(step).map_err(|reason| invalid_step(&s, format!("illegal move, {}", reason)))?;
+        Ok(step)
     }
 
     fn write_step(&self, step: Self::S) -> Option<String> {
@@ -227,57 +300,55 @@ impl Board for HequnBoard {
                 }
             }
             HequnStep::Pass => Some("pass".to_string()),
+            HequnStep::Choose(color) => {
+                if self.phase != HequnPhase::Choose {
+                    None
+                } else {
+                    match color {
+                        HequnPiece::Black => Some("black".to_string()),
+                        HequnPiece::White => Some("white".to_string()),
+                    }
+                }
+            },
         }
     }
 
-    fn read_fen(s: String) -> Option<Self> where Self: Sized {
-        if !s.starts_with("[hequn] ") {
-            return None;
-        }
-        
-        let parts: Vec<&str> = s.split_whitespace().collect();
+    fn read_fen(s: String) -> Result<Self, XinqiParseError> where Self: Sized {
+        let parts = split_with_columns(&s);
+        check_fen_header(&parts, Self::NAME)?;
         // 应该有5个部分: [hequn], pieces, cells, active_player, last_pass
-        if parts.len() != 5 {
-            return None;
+        // 使用三手交换规则时还有第6个部分: 开局阶段或先手方的颜色
+        if parts.len() != 5 && parts.len() != 6 {
+            return Err(XinqiParseError::new("fields", format!("expected 5 or 6 fields, found {}", parts.len())));
         }
         
         // 解析pieces部分
-        let pieces_str = parts[1];
-        let pieces_rows: Vec<&str> = pieces_str.split('/').collect();
-        if pieces_rows.len() != BOARD_SIZE_I {
-            return None;
-        }
+        let (pieces_column, pieces_str) = parts[1];
+        let pieces_rows = split_rows(pieces_str, pieces_column);
+        check_rows(&pieces_rows, "pieces", pieces_column, BOARD_SIZE_I, BOARD_SIZE_J)?;
         
         let mut pieces: Vec<Vec<Option<HequnPiece>>> = vec![vec![None; BOARD_SIZE_I]; BOARD_SIZE_J];
-        for (i, row_str) in pieces_rows.iter().enumerate() {
-            if row_str.len() != BOARD_SIZE_J {
-                return None;
-            }
+        for (i, (row_column, row_str)) in pieces_rows.iter().enumerate() {
             for (j, ch) in row_str.chars().enumerate() {
                 pieces[i][j] = match ch {
                     'b' => Some(HequnPiece::Black),
                     'w' => Some(HequnPiece::White),
                     '-' => None,
-                    _ => return None, // 无效字符
+                    _ => return Err(invalid_char("pieces", ch, row_column + j)),
                 };
             }
         }
         
         // 解析cells部分
-        let cells_str = parts[2];
-        let cells_rows: Vec<&str> = cells_str.split('/').collect();
-        if cells_rows.len() != BOARD_SIZE_I {
-            return None;
-        }
+        let (cells_column, cells_str) = parts[2];
+        let cells_rows = split_rows(cells_str, cells_column);
+        check_rows(&cells_rows, "cells", cells_column, BOARD_SIZE_I, BOARD_SIZE_J)?;
 
         let mut black_score = 0;
         let mut white_score = 0;
         
         let mut cells: Vec<Vec<HequnCell>> = vec![vec![HequnCell::Grey; BOARD_SIZE_I]; BOARD_SIZE_J];
-        for (i, row_str) in cells_rows.iter().enumerate() {
-            if row_str.len() != BOARD_SIZE_J {
-                return None;
-            }
+        for (i, (row_column, row_str)) in cells_rows.iter().enumerate() {
             for (j, ch) in row_str.chars().enumerate() {
                 cells[i][j] = match ch {
                     'b' => { 
@@ -289,27 +360,41 @@ impl Board for HequnBoard {
                         HequnCell::Colored(HequnPiece::White)
                     },
                     '-' => HequnCell::Grey,
-                    _ => return None, // 无效字符
+                    _ => return Err(invalid_char("cells", ch, row_column + j)),
                 };
             }
         }
         
         // 解析当前玩家
-        let active_player = match parts[3] {
+        let active_player = match parts[3].1 {
             "b" => HequnPiece::Black,
             "w" => HequnPiece::White,
-            _ => return None,
+            p => return Err(XinqiParseError::new("active player", format!("expected \"b\" or \"w\", found \"{}\"", p)).at_column(parts[3].0)),
         };
         
         // 解析最后一步是否是pass
-        let last_pass = match parts[4] {
+        let last_pass = match parts[4].1 {
             "1" => true,
             "0" => false,
-            _ => return None,
+            p => return Err(XinqiParseError::new("last pass", format!("expected \"0\" or \"1\", found \"{}\"", p)).at_column(parts[4].0)),
+        };
+
+        // 解析开局阶段, p0/p1/p2 表示先手方正在放置第几个子, c 表示后手方选择颜色, b/w 表示先手方所执的颜色
+        let (phase, first_color) = match parts.get(5).map(|p| p.1) {
+            None | Some("b") => (HequnPhase::Play, HequnPiece::Black),
+            Some("w") => (HequnPhase::Play, HequnPiece::White),
+            Some("c") => (HequnPhase::Choose, HequnPiece::Black),
+            Some(p) => {
+                let n = p.strip_prefix('p')
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .filter(|n| *n < SWAP_STONES)
+                    .ok_or_else(|| XinqiParseError::new("opening phase", format!("expected \"b\", \"w\", \"c\" or \"p0\" to \"p{}\", found \"{}\"", SWAP_STONES - 1, p)).at_column(parts[5].0))?;
+                (HequnPhase::Place(n), HequnPiece::Black)
+            },
         };
         
         // 创建并返回游戏状态实例
-        Some(Self {
+        Ok(Self {
             pieces,
             cells,
             active_player,
@@ -318,6 +403,8 @@ impl Board for HequnBoard {
             black_score,
             white_score,
             fullmove: 1,
+            phase,
+            first_color,
         })
     }
 
@@ -357,7 +444,15 @@ impl Board for HequnBoard {
 
         let last_pass = if self.last_pass { "1" } else { "0" };
 
-        format!("[hequn] {} {} {} {}", pieces, cells, active_player, last_pass)
+        // 不使用交换规则的局面保持原来的格式
+        let phase = match (self.phase, self.first_color) {
+            (HequnPhase::Play, HequnPiece::Black) => String::new(),
+            (HequnPhase::Play, HequnPiece::White) => String::from(" w"),
+            (HequnPhase::Choose, _) => String::from(" c"),
+            (HequnPhase::Place(n), _) => format!(" p{}", n),
+        };
+
+        format!("[hequn] {} {} {} {}{}", pieces, cells, active_player, last_pass, phase)
     }
     
     fn get_fullmove(&self) -> usize {
@@ -365,9 +460,147 @@ impl Board for HequnBoard {
     }
     
     fn get_active_player(&self) -> PlayerOrder {
-        match self.active_player {
-            HequnPiece::Black => PlayerOrder::First,
-            HequnPiece::White => PlayerOrder::Second,
+        match self.phase {
+            HequnPhase::Place(_) => PlayerOrder::First,
+            HequnPhase::Choose => PlayerOrder::Second,
+            HequnPhase::Play => self.player_of(self.active_player),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(board: &HequnBoard, step: &str) -> HequnBoard {
+        let step = board.read_step(step.to_string()).unwrap();
+        board.try_move(step).unwrap()
+    }
+
+    fn round_trip(board: &HequnBoard) -> HequnBoard {
+        let fen = board.write_fen();
+        let read = HequnBoard::read_fen(fen.clone()).unwrap();
+        assert_eq!(read.write_fen(), fen);
+        read
+    }
+
+    // 放置三子后由后手方选择颜色
+    fn opening() -> HequnBoard {
+        let mut board = HequnBoard::swap_opening();
+        for step in ["a1", "c3", "e5"] {
+            board = play(&board, step);
+        }
+        board
+    }
+
+    #[test]
+    fn fen_round_trip_through_the_opening() {
+        let mut board = HequnBoard::swap_opening();
+        for (n, step) in ["a1", "c3", "e5"].into_iter().enumerate() {
+            assert!(board.write_fen().ends_with(&format!(" p{}", n)));
+            assert!(round_trip(&board).phase == HequnPhase::Place(n));
+            board = play(&board, step);
+        }
+
+        assert!(board.write_fen().ends_with(" c"));
+        assert!(round_trip(&board).phase == HequnPhase::Choose);
+
+        let chose_black = play(&board, "black");
+        assert!(chose_black.write_fen().ends_with(" w"));
+        let read = round_trip(&chose_black);
+        assert!(read.phase == HequnPhase::Play && read.first_color == HequnPiece::White);
+
+        let chose_white = play(&board, "white");
+        let read = round_trip(&chose_white);
+        assert!(read.phase == HequnPhase::Play && read.first_color == HequnPiece::Black);
+    }
+
+    #[test]
+    fn fen_without_swap_rule_keeps_five_fields() {
+        let board = play(&HequnBoard::default(), "d4");
+        let fen = board.write_fen();
+        assert_eq!(fen.split(' ').count(), 5);
+        let read = round_trip(&board);
+        assert!(read.phase == HequnPhase::Play && read.first_color == HequnPiece::Black);
+
+        let explicit = HequnBoard::read_fen(format!("{} b", fen)).unwrap();
+        assert_eq!(explicit.write_fen(), fen);
+        assert!(HequnBoard::read_fen(format!("{} p3", fen)).is_err());
+        assert!(HequnBoard::read_fen(format!("{} x", fen)).is_err());
+    }
+
+    #[test]
+    fn choose_only_in_choose_phase() {
+        let normal = HequnBoard::default();
+        assert!(normal.checked_move(HequnStep::Choose(HequnPiece::Black)).is_err());
+        assert!(normal.read_step("white".to_string()).is_err());
+        assert!(normal.write_step(HequnStep::Choose(HequnPiece::White)).is_none());
+
+        let placing = play(&HequnBoard::swap_opening(), "a1");
+        assert!(placing.checked_move(HequnStep::Choose(HequnPiece::Black)).is_err());
+        assert!(placing.checked_move(HequnStep::Pass).is_err());
+
+        let choosing = opening();
+        assert!(choosing.checked_move(HequnStep::Pos(3, 3)).is_err());
+        assert!(choosing.checked_move(HequnStep::Pass).is_err());
+        assert!(choosing.all_move().len() == 2);
+
+        let chosen = play(&choosing, "black");
+        assert!(chosen.checked_move(HequnStep::Choose(HequnPiece::White)).is_err());
+    }
+
+    #[test]
+    fn choose_reassigns_colors() {
+        let board = opening();
+        assert_eq!(board.get_active_player(), PlayerOrder::Second);
+        assert!(board.active_player == HequnPiece::White);
+
+        // 后手方执黑，先手方改执白并继续行动
+        let chose_black = play(&board, "black");
+        assert_eq!(chose_black.player_of(HequnPiece::Black), PlayerOrder::Second);
+        assert_eq!(chose_black.player_of(HequnPiece::White), PlayerOrder::First);
+        assert_eq!(chose_black.get_active_player(), PlayerOrder::First);
+
+        // 后手方执白，由后手方继续行动
+        let chose_white = play(&board, "white");
+        assert_eq!(chose_white.player_of(HequnPiece::White), PlayerOrder::Second);
+        assert_eq!(chose_white.player_of(HequnPiece::Black), PlayerOrder::First);
+        assert_eq!(chose_white.get_active_player(), PlayerOrder::Second);
+
+        // 开局的三子保持原来的颜色
+        assert!(chose_black.pieces[0][0] == Some(HequnPiece::Black));
+        assert!(chose_black.pieces[2][2] == Some(HequnPiece::White));
+    }
+
+    // 把 FEN 中第 index 个空格分隔的部分换成 value，返回新的 FEN 和这一部分的列号
+    fn replace_field(fen: &str, index: usize, value: &str) -> (String, usize) {
+        let mut fields: Vec<&str> = fen.split(' ').collect();
+        let column = fields[..index].iter().map(|f| f.len() + 1).sum::<usize>() + 1;
+        fields[index] = value;
+        (fields.join(" "), column)
+    }
+
+    #[test]
+    fn parse_errors_name_the_field_and_column() {
+        let fen = HequnBoard::default().write_fen();
+        // 棋子部分第二行的第三格
+        let index = fen.find(' ').unwrap() + 1 + (BOARD_SIZE_J + 1) + 2;
+        let mut bad = fen.clone();
+        bad.replace_range(index..index + 1, "x");
+        let error = HequnBoard::read_fen(bad).err().unwrap();
+        assert_eq!((error.field, error.column), ("pieces", Some(index + 1)));
+        assert!(error.reason.contains("'x'"));
+
+        let (bad, column) = replace_field(&fen, 3, "r");
+        let error = HequnBoard::read_fen(bad).err().unwrap();
+        assert_eq!((error.field, error.column), ("active player", Some(column)));
+        assert!(error.reason.contains("\"r\""));
+
+        let board = HequnBoard::default();
+        for (step, reason) in [("z1", "column letter"), ("a11", "row number"), ("black", "illegal move")] {
+            let error = board.read_step(step.to_string()).err().unwrap();
+            assert_eq!(error.field, "step");
+            assert!(error.reason.contains(reason), "{}: {}", step, error.reason);
+        }
+    }
+}
@@ -1,13 +1,18 @@
+#[cfg(feature = "gui")]
 use bevy::prelude::*;
-use crate::{general::UpdateBoard, graphics::XinqiGraphicsPlugin, hequn::{game::*, general::HequnBoard}, tree::game_tree_event::*};
+#[cfg(feature = "gui")]
+use crate::{general::UpdateBoard, graphics::{overlay::BoardOverlay, XinqiGraphicsPlugin}, hequn::{game::*, general::HequnBoard}, tree::game_tree_event::*};
 
 pub mod general;
+#[cfg(feature = "gui")]
 pub mod game;
 pub mod ai;
 mod utils;
 
+#[cfg(feature = "gui")]
 pub struct HequnPlugin;
 
+#[cfg(feature = "gui")]
 impl Plugin for HequnPlugin {
     fn build(&self, app: &mut App) {
         if !app.is_plugin_added::<XinqiGraphicsPlugin>() {
@@ -15,12 +20,14 @@ impl Plugin for HequnPlugin {
         }
         app.add_event::<EndHequnGame>();
         app.add_event::<UpdateBoard<HequnBoard>>();
+        app.init_resource::<BoardOverlay<HequnBoard>>();
         app.add_systems(Startup, hequn_setup);
         app.add_systems(Update, (handle_end_hequn_game, hequn_update).chain());
         app.add_systems(Update, handle_hequn_tree_events);
     }
 }
 
+#[cfg(feature = "gui")]
 fn handle_hequn_tree_events(
     mut q_hequn: Query<&mut HequnGame>,
     mut ew_update_board: EventWriter<UpdateBoard<HequnBoard>>,
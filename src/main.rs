@@ -1,18 +1,65 @@
+// 不带 gui 编译时（cargo build --no-default-features）不链接 Bevy，只保留命令行工具，界面使用的部分接口用不到
+#![cfg_attr(not(feature = "gui"), allow(dead_code, unused_imports))]
+
+#[cfg(feature = "gui")]
 use bevy::prelude::*;
 
+#[cfg(feature = "gui")]
 use crate::{graphics::XinqiGraphicsPlugin, hequn::HequnPlugin, ui::UiPlugin, xingxiang::XingxiangPlugin, zhandi::ZhandiPlugin};
 
 mod general;
 mod tree;
+#[cfg(feature = "gui")]
 mod ui;
 mod hequn;
+#[cfg(feature = "gui")]
 mod graphics;
 mod zhandi;
 mod xingxiang;
 mod ai;
+#[cfg(feature = "gui")]
 mod net;
+mod headless;
+mod engine;
 
 fn main() {
+    // cargo run --release -- --benchmark [time_ms]
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "--benchmark") {
+        let time_limit_ms = args.get(2).and_then(|t| t.parse().ok()).unwrap_or(2000);
+        run_benchmark(time_limit_ms);
+        return;
+    }
+    // cargo run --release -- --headless <game> [options]
+    if args.get(1).is_some_and(|arg| arg == "--headless") {
+        headless::run(&args[2..]);
+        return;
+    }
+    // cargo run --release -- --arena <game> --a <player> --b <player> [options]
+    if args.get(1).is_some_and(|arg| arg == "--arena") {
+        ai::arena::run(&args[2..]);
+        return;
+    }
+    // cargo run --release -- --lobby [address]，见 net.md
+    #[cfg(feature = "gui")]
+    if args.get(1).is_some_and(|arg| arg == "--lobby") {
+        net::lobby::run(&args[2..]);
+        return;
+    }
+    // 通过标准输入输出使用引擎协议，见 ai.md
+    if args.get(1).is_some_and(|arg| arg == "--engine") {
+        engine::server::run();
+        return;
+    }
+
+    #[cfg(feature = "gui")]
+    run_app();
+    #[cfg(not(feature = "gui"))]
+    eprintln!("built without the gui feature, use --headless, --arena, --engine or --benchmark");
+}
+
+#[cfg(feature = "gui")]
+fn run_app() {
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -31,3 +78,10 @@ fn main() {
         .run();
 }
 
+
+fn run_benchmark(time_limit_ms: u32) {
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    ai::benchmark::benchmark::<hequn::general::HequnBoard>("hequn", time_limit_ms, max_threads);
+    ai::benchmark::benchmark::<zhandi::general::ZhandiBoard>("zhandi", time_limit_ms, max_threads);
+    ai::benchmark::benchmark::<xingxiang::general::XingxiangBoard>("xingxiang", time_limit_ms, max_threads);
+}
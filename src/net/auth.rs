@@ -0,0 +1,279 @@
+// 房间密码的挑战-应答验证和加密传输。密码本身不在网络上传输：双方交换随机数，
+// 各自用以密码为密钥的 HMAC-SHA256 证明知道密码；需要加密时再从密码和随机数导出 ChaCha20-Poly1305 的密钥
+use std::{net::SocketAddr, time::Duration};
+use bevy::prelude::*;
+use chacha20poly1305::{aead::{Aead, KeyInit}, ChaCha20Poly1305, Key, Nonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::net::{message::Message, net::Link, record::Recorder, session::PROTOCOL_VERSION};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+type HmacSha256 = Hmac<Sha256>;
+
+// 验证时每条消息要在这么久之内收到
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+// 菜单中最多显示这么多被拒绝的连接
+const MAX_REJECTED: usize = 5;
+
+// 区分 HMAC 的用途，一方的证明不能冒充另一方的，也不能当作密钥
+const CLIENT_PROOF: &[u8] = b"xinqi client proof";
+const HOST_PROOF: &[u8] = b"xinqi host proof";
+const SESSION_KEY: &[u8] = b"xinqi session key";
+
+// 会话记录中代替随机数和证明的内容
+const REDACTED: &str = "redacted";
+
+// 房间密码，为空时不需要密码。是否加密由监听的一方决定，加密需要密码。
+// 连接的一方可以要求加密，监听的一方不加密时拒绝连接
+#[derive(Clone, Default, Debug)]
+pub struct RoomKey {
+    pub password: String,
+    pub encrypted: bool,
+    pub require_encryption: bool,
+}
+
+impl RoomKey {
+    pub fn locked(&self) -> bool {
+        !self.password.is_empty()
+    }
+
+    fn encrypts(&self) -> bool {
+        self.encrypted && self.locked()
+    }
+}
+
+// 监听的一方最近拒绝的连接及原因
+#[derive(Resource, Default)]
+pub struct RejectedPeers {
+    pub list: Vec<(SocketAddr, String)>,
+}
+
+impl RejectedPeers {
+    pub fn push(&mut self, addr: SocketAddr, reason: String) {
+        self.list.push((addr, reason));
+        if self.list.len() > MAX_REJECTED {
+            self.list.remove(0);
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// 挑战中的随机数和两个标志，双方的证明都覆盖整个挑战，中间人改动是否加密会使验证失败
+struct Transcript {
+    host_nonce: Vec<u8>,
+    locked: bool,
+    encrypted: bool,
+}
+
+fn mac(password: &str, label: &[u8], transcript: &Transcript, client_nonce: &[u8]) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(password.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(label);
+    mac.update(&transcript.host_nonce);
+    mac.update(&[transcript.locked as u8, transcript.encrypted as u8]);
+    mac.update(client_nonce);
+    mac
+}
+
+fn prove(password: &str, label: &[u8], transcript: &Transcript, client_nonce: &[u8]) -> String {
+    to_hex(&mac(password, label, transcript, client_nonce).finalize().into_bytes())
+}
+
+// 比较时间与内容无关
+fn verify(password: &str, label: &[u8], transcript: &Transcript, client_nonce: &[u8], proof: &str) -> bool {
+    from_hex(proof).is_some_and(|proof| mac(password, label, transcript, client_nonce).verify_slice(&proof).is_ok())
+}
+
+fn session_key(password: &str, transcript: &Transcript, client_nonce: &[u8]) -> [u8; 32] {
+    let mut key = [0; 32];
+    key.copy_from_slice(&mac(password, SESSION_KEY, transcript, client_nonce).finalize().into_bytes());
+    key
+}
+
+// 一个方向上的加密。nonce 由方向和这个方向上的消息序号组成，双方各自计数，不在网络上传输，
+// 因此重放、丢弃或者调换顺序的消息都无法解密
+pub struct LineCipher {
+    cipher: ChaCha20Poly1305,
+    direction: u8,
+    counter: u64,
+}
+
+impl LineCipher {
+    fn new(key: &[u8; 32], direction: u8) -> Self {
+        Self { cipher: ChaCha20Poly1305::new(Key::from_slice(key)), direction, counter: 0 }
+    }
+
+    fn next_nonce(&mut self) -> Nonce {
+        let mut nonce = [0; 12];
+        nonce[0] = self.direction;
+        nonce[4..].copy_from_slice(&self.counter.to_le_bytes());
+        self.counter += 1;
+        Nonce::from(nonce)
+    }
+
+    // 一行明文加密为一行十六进制的密文
+    pub fn seal(&mut self, line: &str) -> String {
+        let nonce = self.next_nonce();
+        to_hex(&self.cipher.encrypt(&nonce, line.as_bytes()).expect("encrypting a line cannot fail"))
+    }
+
+    pub fn open(&mut self, line: &str) -> Result<String, Error> {
+        let nonce = self.next_nonce();
+        let sealed = from_hex(line).ok_or("malformed encrypted message")?;
+        let plain = self.cipher.decrypt(&nonce, sealed.as_slice()).map_err(|_| "failed to decrypt a message")?;
+        Ok(String::from_utf8(plain)?)
+    }
+}
+
+// 本地发送和接收两个方向的加密
+pub fn line_ciphers(key: &[u8; 32], host: bool) -> (LineCipher, LineCipher) {
+    let (send, receive) = if host { (1, 2) } else { (2, 1) };
+    (LineCipher::new(key, send), LineCipher::new(key, receive))
+}
+
+// 记录验证消息时隐去随机数和证明，拿到记录的人不能用它们离线猜测密码
+fn redact(message: &Message) -> String {
+    let hidden = || REDACTED.to_string();
+    let redacted = match message {
+        Message::Challenge { password, encrypted, .. } => Message::Challenge { nonce: hidden(), password: *password, encrypted: *encrypted },
+        Message::Answer { .. } => Message::Answer { nonce: hidden(), proof: hidden() },
+        Message::Welcome { .. } => Message::Welcome { proof: hidden() },
+        _ => return serde_json::to_string(message).unwrap(),
+    };
+    serde_json::to_string(&redacted).unwrap()
+}
+
+async fn send(link: &mut Link, recorder: &Recorder, message: &Message) -> Result<(), Error> {
+    recorder.sent(&redact(message));
+    link.send(&serde_json::to_string(message)?).await
+}
+
+async fn receive(link: &mut Link, recorder: &Recorder) -> Result<Message, Error> {
+    loop {
+        let line = tokio::time::timeout(AUTH_TIMEOUT, link.read_line()).await
+            .map_err(|_| "authentication timed out")??
+            .ok_or("connection closed during authentication")?;
+        match serde_json::from_str(&line) {
+            Ok(Message::Ping) => {},
+            Ok(message) => {
+                recorder.received(&redact(&message));
+                return Ok(message);
+            },
+            Err(_) if line.is_empty() => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// 监听的一方在握手之前验证连接来的一方，失败时返回拒绝的原因。验证消息隐去秘密后记录到 recorder
+pub async fn challenge_peer(link: &mut Link, key: &RoomKey, recorder: &Recorder) -> Result<(), Error> {
+    let host_nonce: [u8; 16] = rand::random();
+    let transcript = Transcript { host_nonce: host_nonce.to_vec(), locked: key.locked(), encrypted: key.encrypts() };
+    send(link, recorder, &Message::Challenge { nonce: to_hex(&host_nonce), password: transcript.locked, encrypted: transcript.encrypted }).await?;
+    let (client_nonce, proof) = match receive(link, recorder).await? {
+        Message::Answer { nonce, proof } => (from_hex(&nonce).ok_or("malformed nonce")?, proof),
+        // 不认识挑战的旧版本直接发来握手信息
+        Message::Hello(hello) => {
+            return Err(format!("protocol version {} is required, but {} uses {}", PROTOCOL_VERSION, hello.app, hello.protocol).into());
+        },
+        _ => return Err("expected an answer to the challenge".into()),
+    };
+    if key.locked() && !verify(&key.password, CLIENT_PROOF, &transcript, &client_nonce, &proof) {
+        return Err("wrong password".into());
+    }
+
+    let proof = if key.locked() { prove(&key.password, HOST_PROOF, &transcript, &client_nonce) } else { String::new() };
+    send(link, recorder, &Message::Welcome { proof }).await?;
+    if key.encrypts() {
+        link.encrypt(&session_key(&key.password, &transcript, &client_nonce), true);
+    }
+    Ok(())
+}
+
+// 连接的一方回应挑战，并确认监听的一方也知道密码
+pub async fn answer_challenge(link: &mut Link, key: &RoomKey, recorder: &Recorder) -> Result<(), Error> {
+    let transcript = match receive(link, recorder).await? {
+        Message::Challenge { nonce, password, encrypted } => Transcript {
+            host_nonce: from_hex(&nonce).ok_or("malformed nonce")?,
+            locked: password,
+            encrypted,
+        },
+        Message::Rejected { reason } => return Err(format!("Rejected: {}", reason).into()),
+        _ => return Err("expected a challenge from the host".into()),
+    };
+    let locked = transcript.locked;
+    let encrypted = locked && transcript.encrypted;
+    if locked && !key.locked() {
+        return Err("the host requires a password".into());
+    }
+    if key.require_encryption && !encrypted {
+        return Err("the host does not encrypt the connection".into());
+    }
+
+    let client_nonce: [u8; 16] = rand::random();
+    let proof = if locked { prove(&key.password, CLIENT_PROOF, &transcript, &client_nonce) } else { String::new() };
+    send(link, recorder, &Message::Answer { nonce: to_hex(&client_nonce), proof }).await?;
+    match receive(link, recorder).await? {
+        Message::Welcome { proof } => {
+            if locked && !verify(&key.password, HOST_PROOF, &transcript, &client_nonce, &proof) {
+                return Err("the host could not prove that it knows the password".into());
+            }
+        },
+        Message::Rejected { reason } => return Err(format!("Rejected: {}", reason).into()),
+        _ => return Err("expected a welcome from the host".into()),
+    }
+    if encrypted {
+        link.encrypt(&session_key(&key.password, &transcript, &client_nonce), false);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(locked: bool, encrypted: bool) -> Transcript {
+        Transcript { host_nonce: vec![1; 16], locked, encrypted }
+    }
+
+    #[test]
+    fn proofs_cover_the_challenge_flags() {
+        let client_nonce = [2; 16];
+        let sent = transcript(true, true);
+        for label in [CLIENT_PROOF, HOST_PROOF] {
+            let proof = prove("secret", label, &sent, &client_nonce);
+            assert!(verify("secret", label, &sent, &client_nonce, &proof));
+            assert!(!verify("wrong", label, &sent, &client_nonce, &proof));
+            // 中间人关掉加密后双方看到的挑战不同
+            assert!(!verify("secret", label, &transcript(true, false), &client_nonce, &proof));
+        }
+        let proof = prove("secret", CLIENT_PROOF, &sent, &client_nonce);
+        assert!(!verify("secret", HOST_PROOF, &sent, &client_nonce, &proof));
+    }
+
+    #[test]
+    fn recorded_messages_hide_nonces_and_proofs() {
+        let answer = Message::Answer { nonce: to_hex(&[3; 16]), proof: prove("secret", CLIENT_PROOF, &transcript(true, true), &[3; 16]) };
+        let challenge = Message::Challenge { nonce: to_hex(&[1; 16]), password: true, encrypted: true };
+        for message in [answer, challenge, Message::Welcome { proof: String::from("abcd") }] {
+            let recorded = redact(&message);
+            assert!(!recorded.contains("0303") && !recorded.contains("0101") && !recorded.contains("abcd"), "{}", recorded);
+            // 仍然是一条完整的消息
+            assert!(serde_json::from_str::<Message>(&recorded).is_ok());
+        }
+        let challenge = redact(&Message::Challenge { nonce: String::new(), password: true, encrypted: false });
+        assert!(matches!(serde_json::from_str(&challenge), Ok(Message::Challenge { password: true, encrypted: false, .. })));
+        let rejected = serde_json::to_string(&Message::Rejected { reason: String::from("wrong password") }).unwrap();
+        assert_eq!(redact(&Message::Rejected { reason: String::from("wrong password") }), rejected);
+    }
+}
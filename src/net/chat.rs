@@ -0,0 +1,54 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+
+use crate::{net::{message::Message, session::{LocalProfile, Session}, types::*}, tree::game_tree::ChatLine};
+
+// 本次运行中收发的所有聊天消息
+#[derive(Resource, Default)]
+pub struct ChatLog {
+    pub lines: Vec<ChatLine>,
+}
+
+// 收到或者发出一条聊天消息，正在进行的对局把它记录到游戏树中
+#[derive(Event)]
+pub struct ChatEvent {
+    pub line: ChatLine,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// 聊天记录中显示的时间，没有时区信息，按 UTC 显示
+pub fn format_time(time: u64) -> String {
+    format!("{:02}:{:02}:{:02}", time / 3600 % 24, time / 60 % 60, time % 60)
+}
+
+pub fn send_chat(
+    text: String,
+    profile: &LocalProfile,
+    log: &mut ChatLog,
+    ew_net: &mut EventWriter<SendNetMsgEvent>,
+    ew_chat: &mut EventWriter<ChatEvent>,
+) {
+    let message = serde_json::to_string(&Message::Chat { text: text.clone() }).unwrap();
+    ew_net.write(SendNetMsgEvent { message });
+    let line = ChatLine { time: now(), sender: profile.name.clone(), text };
+    log.lines.push(line.clone());
+    ew_chat.write(ChatEvent { line });
+}
+
+pub fn receive_chat(
+    session: Option<Res<Session>>,
+    mut log: ResMut<ChatLog>,
+    mut er_net: EventReader<ReceiveNetMsgEvent>,
+    mut ew_chat: EventWriter<ChatEvent>,
+) {
+    for event in er_net.read() {
+        let Ok(Message::Chat { text }) = serde_json::from_str(&event.message) else { continue; };
+        let sender = session.as_ref().and_then(|session| session.peer.as_ref()).map_or(String::from("Opponent"), |peer| peer.name.clone());
+        let line = ChatLine { time: now(), sender, text };
+        log.lines.push(line.clone());
+        ew_chat.write(ChatEvent { line });
+    }
+}
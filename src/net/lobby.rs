@@ -0,0 +1,260 @@
+// 大厅服务器：登记开放的房间，并在房主和加入的人之间转发连接，双方都不需要知道对方的地址
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::{io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader}, net::{TcpListener, TcpStream}, sync::mpsc};
+
+use crate::net::net::Link;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const DEFAULT_LOBBY_ADDR: &str = "0.0.0.0:18387";
+
+// 房主这么久没有接上加入的人就放弃
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+const USAGE: &str = "\
+usage: xinqi --lobby [address]
+  address  where to listen, default 0.0.0.0:18387";
+
+// 大厅和客户端之间的消息，一行一条 JSON。开始转发之后连接上传输的就是普通的网络对局消息
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub enum LobbyMessage {
+    // 房主开一个房间，这个连接此后用来接收有人加入的通知，断开后房间关闭
+    Open { host: String, game: String, #[serde(default)] locked: bool },
+    Opened { room: u64 },
+    List,
+    Rooms { rooms: Vec<Room> },
+    // 加入房间，收到 Joined 之后这个连接就接到了房主
+    Join { room: u64 },
+    Joined,
+    // 通知房主有人加入，房主用 ticket 另开一个连接接上
+    Incoming { ticket: u64 },
+    Accept { ticket: u64 },
+    Error { reason: String },
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Room {
+    pub id: u64,
+    pub host: String,
+    pub game: String,
+    pub guests: usize, // 正在转发的连接数，包括对手和观众
+    #[serde(default)]
+    pub locked: bool, // 需要密码
+}
+
+// 菜单中显示的房间列表
+#[derive(Resource, Default)]
+pub struct LobbyRooms {
+    pub rooms: Vec<Room>,
+    pub error: Option<String>,
+    pub opened: Option<u64>, // 自己开的房间
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &LobbyMessage) -> Result<(), Error> {
+    let line = serde_json::to_string(message)?;
+    writer.write_all(line.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+async fn read_message(link: &mut Link) -> Result<LobbyMessage, Error> {
+    let Some(line) = link.read_line().await? else {
+        return Err("the lobby closed the connection".into());
+    };
+    match serde_json::from_str(&line)? {
+        LobbyMessage::Error { reason } => Err(reason.into()),
+        message => Ok(message),
+    }
+}
+
+// 在大厅开一个房间，返回房间号和用来接收加入通知的连接
+pub async fn open_room(lobby: &str, host: String, game: String, locked: bool) -> Result<(u64, Link), Error> {
+    let mut link = Link::connect(lobby).await?;
+    link.send(&serde_json::to_string(&LobbyMessage::Open { host, game, locked })?).await?;
+    match read_message(&mut link).await? {
+        LobbyMessage::Opened { room } => Ok((room, link)),
+        message => Err(format!("unexpected reply from the lobby: {:?}", message).into()),
+    }
+}
+
+// 等待下一个加入房间的人，另开一个连接接上
+pub async fn accept_guest(control: &mut Link, lobby: &str) -> Result<Link, Error> {
+    loop {
+        if let LobbyMessage::Incoming { ticket } = read_message(control).await? {
+            let mut link = Link::connect(lobby).await?;
+            link.send(&serde_json::to_string(&LobbyMessage::Accept { ticket })?).await?;
+            return Ok(link);
+        }
+    }
+}
+
+pub async fn join_room(lobby: &str, room: u64) -> Result<Link, Error> {
+    let mut link = Link::connect(lobby).await?;
+    link.send(&serde_json::to_string(&LobbyMessage::Join { room })?).await?;
+    match read_message(&mut link).await? {
+        LobbyMessage::Joined => Ok(link),
+        message => Err(format!("unexpected reply from the lobby: {:?}", message).into()),
+    }
+}
+
+pub async fn list_rooms(lobby: &str) -> Result<Vec<Room>, Error> {
+    let mut link = Link::connect(lobby).await?;
+    link.send(&serde_json::to_string(&LobbyMessage::List)?).await?;
+    match read_message(&mut link).await? {
+        LobbyMessage::Rooms { rooms } => Ok(rooms),
+        message => Err(format!("unexpected reply from the lobby: {:?}", message).into()),
+    }
+}
+
+struct OpenRoom {
+    room: Room,
+    control: mpsc::UnboundedSender<LobbyMessage>,
+}
+
+#[derive(Default)]
+struct Lobby {
+    rooms: HashMap<u64, OpenRoom>,
+    waiting: HashMap<u64, (u64, BufReader<TcpStream>)>, // ticket -> (房间号, 等待房主接上的连接)
+    next_id: u64,
+}
+
+impl Lobby {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn count_guest(&mut self, room: u64, delta: isize) {
+        if let Some(open) = self.rooms.get_mut(&room) {
+            open.room.guests = open.room.guests.saturating_add_signed(delta);
+        }
+    }
+}
+
+type Shared = Arc<Mutex<Lobby>>;
+
+// cargo run --release -- --lobby [address]
+pub fn run(args: &[String]) {
+    let addr = match args {
+        [] => DEFAULT_LOBBY_ADDR.to_string(),
+        [addr] if !addr.starts_with('-') => addr.clone(),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        },
+    };
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start the tokio runtime");
+    if let Err(e) = runtime.block_on(serve(addr)) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn serve(addr: String) -> Result<(), Error> {
+    let listener = TcpListener::bind(&addr).await?;
+    println!("lobby listening on {}", listener.local_addr()?);
+    let lobby = Shared::default();
+    loop {
+        let (socket, peer_addr) = listener.accept().await?;
+        let lobby = lobby.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(socket, lobby).await {
+                println!("{}: {}", peer_addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_client(socket: TcpStream, lobby: Shared) -> Result<(), Error> {
+    let mut stream = BufReader::new(socket);
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Ok(());
+    }
+    match serde_json::from_str(line.trim())? {
+        LobbyMessage::Open { host, game, locked } => host_room(stream, lobby, Room { id: 0, host, game, guests: 0, locked }).await,
+        LobbyMessage::List => {
+            let mut rooms: Vec<Room> = lobby.lock().unwrap().rooms.values().map(|open| open.room.clone()).collect();
+            rooms.sort_by_key(|room| room.id);
+            write_message(stream.get_mut(), &LobbyMessage::Rooms { rooms }).await
+        },
+        LobbyMessage::Join { room } => {
+            let control = lobby.lock().unwrap().rooms.get(&room).map(|open| open.control.clone());
+            let Some(control) = control else {
+                let reason = format!("room {} does not exist", room);
+                return write_message(stream.get_mut(), &LobbyMessage::Error { reason }).await;
+            };
+            let ticket = {
+                let mut lobby = lobby.lock().unwrap();
+                let ticket = lobby.next_id();
+                lobby.waiting.insert(ticket, (room, stream));
+                ticket
+            };
+            let _ = control.send(LobbyMessage::Incoming { ticket });
+            tokio::time::sleep(ACCEPT_TIMEOUT).await;
+            let expired = lobby.lock().unwrap().waiting.remove(&ticket);
+            if let Some((_, mut stream)) = expired {
+                let reason = String::from("the host did not answer");
+                write_message(stream.get_mut(), &LobbyMessage::Error { reason }).await?;
+            }
+            Ok(())
+        },
+        LobbyMessage::Accept { ticket } => {
+            let waiting = lobby.lock().unwrap().waiting.remove(&ticket);
+            let Some((room, mut guest)) = waiting else {
+                return Err(format!("unknown ticket {}", ticket).into());
+            };
+            write_message(guest.get_mut(), &LobbyMessage::Joined).await?;
+            lobby.lock().unwrap().count_guest(room, 1);
+            let result = relay(guest, stream).await;
+            lobby.lock().unwrap().count_guest(room, -1);
+            result
+        },
+        message => Err(format!("unexpected message {:?}", message).into()),
+    }
+}
+
+// 房主的连接保持到房主断开为止，期间把加入的通知转告房主
+async fn host_room(mut stream: BufReader<TcpStream>, lobby: Shared, mut room: Room) -> Result<(), Error> {
+    let (control, mut notices) = mpsc::unbounded_channel();
+    let room = {
+        let mut lobby = lobby.lock().unwrap();
+        let id = lobby.next_id();
+        room.id = id;
+        println!("room {} opened by {} ({})", id, room.host, room.game);
+        lobby.rooms.insert(id, OpenRoom { room, control });
+        id
+    };
+
+    let mut result = write_message(stream.get_mut(), &LobbyMessage::Opened { room }).await;
+    let mut line = String::new();
+    while result.is_ok() {
+        tokio::select! {
+            Some(notice) = notices.recv() => result = write_message(stream.get_mut(), &notice).await,
+            read = stream.read_line(&mut line) => match read {
+                // 房主不会再发来消息，读到的内容都忽略
+                Ok(0) => break,
+                Ok(_) => line.clear(),
+                Err(e) => result = Err(e.into()),
+            },
+        }
+    }
+
+    lobby.lock().unwrap().rooms.remove(&room);
+    println!("room {} closed", room);
+    result
+}
+
+// 把两个连接接在一起，读取第一行时多读进缓冲区的内容先转给对方
+async fn relay(a: BufReader<TcpStream>, b: BufReader<TcpStream>) -> Result<(), Error> {
+    let a_buffered = a.buffer().to_vec();
+    let b_buffered = b.buffer().to_vec();
+    let (mut a, mut b) = (a.into_inner(), b.into_inner());
+    b.write_all(&a_buffered).await?;
+    a.write_all(&b_buffered).await?;
+    tokio::io::copy_bidirectional(&mut a, &mut b).await?;
+    Ok(())
+}
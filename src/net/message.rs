@@ -1,19 +1,243 @@
-use bevy::prelude::Event;
+use bevy::{ecs::system::SystemParam, prelude::*};
 use serde::{Deserialize, Serialize};
 
+use crate::{general::{board::*, game::Game, ClockTime, TimeControl, XinqiParseError}, net::{session::{fen_hash, Hello}, types::{ReceiveNetMsgEvent, SendNetMsgEvent}}, tree::game_tree::GameTree, zhandi::general::DEFAULT_KOMI};
+
 #[derive(Deserialize, Serialize)]
 pub enum Message {
-    CreateNewGame { game_name: String, player_order: bool },
-    AcceptCreateNewGame { game_name: String, player_order: bool },
-    Step(String),
+    Hello(Hello),
+    CreateNewGame {
+        game_name: String,
+        player_order: bool,
+        #[serde(default)] time_control: Option<TimeControl>,
+        #[serde(default)] start: GameStart,
+        #[serde(default)] rules: GameRules,
+    },
+    AcceptCreateNewGame {
+        game_name: String,
+        player_order: bool,
+        #[serde(default)] time_control: Option<TimeControl>,
+        #[serde(default)] start: GameStart,
+        #[serde(default)] rules: GameRules,
+    },
+    // ply 是走完这一步后主线的步数，hash 是走完后局面 FEN 的哈希
+    Step { step: String, ply: usize, hash: u64 },
+    Clock(ClockMessage),
+    Action(GameAction),
+    // 局面不一致时请求监听的一方发来完整的游戏树
+    SyncRequest,
+    SyncTree { game: String, tree: String },
+    Chat { text: String },
+    // 断线重连后告诉对方自己主线的步数，对方补发缺少的着法
+    Resume { plies: usize },
+    // 拒绝对方的连接，随后断开
+    Rejected { reason: String },
+    // 监听的一方在握手之前发出的挑战，password 表示需要密码，encrypted 表示验证之后加密连接
+    Challenge { nonce: String, password: bool, encrypted: bool },
+    // 连接的一方的回应，proof 由密码和双方的随机数计算，不需要密码时为空
+    Answer { nonce: String, proof: String },
+    // 监听的一方接受连接，并证明自己也知道密码
+    Welcome { proof: String },
+    // 心跳，连接层收到后直接丢弃
+    Ping,
+    // 主动断开连接，对方不再等待重新连接
+    Bye,
+}
+
+// 网络对局从哪里开始，双方用同样的方式建立游戏树
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub enum GameStart {
+    #[default]
+    Initial,
+    Fen(String),
+    // 从规则决定的初始局面走出的着法
+    Moves(Vec<String>),
+    // GameTree::to_string 的结果，从主线末端继续
+    Tree(String),
+}
+
+impl GameStart {
+    // initial 是按对局规则得到的初始局面，FEN 和游戏树自带规则，不使用它
+    pub fn build_tree<B: Board>(&self, initial: B) -> Result<GameTree<B>, XinqiParseError> {
+        let mut tree = match self {
+            GameStart::Initial => GameTree::new(initial),
+            GameStart::Fen(fen) => GameTree::new(B::read_fen(fen.clone())?),
+            GameStart::Moves(steps) => {
+                let mut tree = GameTree::new(initial);
+                for s in steps {
+                    let step = tree.board().read_step(s.clone())?;
+                    if !tree.try_move(step) {
+                        return Err(XinqiParseError::new("step", format!("illegal move \"{}\"", s)));
+                    }
+                }
+                tree
+            },
+            GameStart::Tree(tree) => GameTree::from_string(tree.clone())?,
+        };
+        tree.focus_mainline_end();
+        Ok(tree)
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            GameStart::Initial => String::from("initial position"),
+            GameStart::Fen(fen) => format!("FEN {}", fen),
+            GameStart::Moves(steps) => format!("after {}", steps.join(" ")),
+            GameStart::Tree(_) => String::from("continue a game tree"),
+        }
+    }
+}
+
+// 网络对局的规则选项，只影响从初始局面开始或者走出着法的对局
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct GameRules {
+    pub hequn_swap_rule: bool,
+    pub zhandi_komi: usize,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self { hequn_swap_rule: false, zhandi_komi: DEFAULT_KOMI }
+    }
+}
+
+impl GameRules {
+    // 邀请中显示的这种棋的规则
+    pub fn describe(&self, game_name: &str) -> String {
+        match game_name {
+            "Hequn" if self.hequn_swap_rule => String::from("three-move swap opening"),
+            "Zhandi" => format!("komi {}", self.zhandi_komi),
+            _ => String::from("standard"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub enum ClockMessage {
+    // 走子一方按钟之后双方的时间，plies 是主线的步数
+    Sync { plies: usize, first: ClockTime, second: ClockTime },
+    // player_order 一方超时
+    Timeout { player_order: bool },
+}
+
+// 对局中需要对方同意的请求，以及认输
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub enum GameAction {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    // 悔棋后主线剩下 plies 步
+    RequestTakeback { plies: usize },
+    AcceptTakeback { plies: usize },
+    DeclineTakeback,
 }
 
 #[derive(Event)]
 pub struct ReceiveRemoteStep {
     pub step: String,
+    pub ply: usize,
+    pub hash: u64,
 }
 
 #[derive(Event)]
 pub struct SendRemoteStep {
     pub step: String,
+    pub ply: usize,
+    pub hash: u64,
+}
+
+// 本地走一步，网络对局中把延长了主线的一步连同步数和局面校验发送给对方
+pub fn try_move_and_send<G: Game>(game: &mut G, step: StepType<G::B>, ew_remote: &mut EventWriter<SendRemoteStep>) {
+    let step_str = game.board().write_step(step);
+    let plies = game.tree().mainline_plies();
+    game.try_move(step);
+    if !game.seats().has_remote() {
+        return;
+    }
+    let tree = game.tree();
+    if tree.mainline_plies() != plies + 1 || tree.focus() != tree.mainline_end() {
+        return;
+    }
+    if let Some(step) = step_str {
+        let hash = fen_hash(&game.board().write_fen());
+        ew_remote.write(SendRemoteStep { step, ply: plies + 1, hash });
+    }
+}
+
+#[derive(Event)]
+pub struct ReceiveRemoteClock {
+    pub message: ClockMessage,
+}
+
+#[derive(Event)]
+pub struct SendRemoteClock {
+    pub message: ClockMessage,
+}
+
+#[derive(Event)]
+pub struct ReceiveRemoteAction {
+    pub action: GameAction,
+}
+
+#[derive(Event)]
+pub struct SendRemoteAction {
+    pub action: GameAction,
+}
+
+// 收到的对局消息转换成的事件
+#[derive(SystemParam)]
+pub struct ReceivedGameEvents<'w> {
+    ew_step: EventWriter<'w, ReceiveRemoteStep>,
+    ew_clock: EventWriter<'w, ReceiveRemoteClock>,
+    ew_action: EventWriter<'w, ReceiveRemoteAction>,
+}
+
+// 要发给对方的对局事件
+#[derive(SystemParam)]
+pub struct SentGameEvents<'w, 's> {
+    er_step: EventReader<'w, 's, SendRemoteStep>,
+    er_clock: EventReader<'w, 's, SendRemoteClock>,
+    er_action: EventReader<'w, 's, SendRemoteAction>,
+}
+
+// 对局中的消息在网络消息和各自的事件之间转换，开始对局的邀请由菜单处理
+pub fn route_game_messages(
+    mut er_net: EventReader<ReceiveNetMsgEvent>,
+    mut ew_net: EventWriter<SendNetMsgEvent>,
+    received: ReceivedGameEvents,
+    sent: SentGameEvents,
+) {
+    let ReceivedGameEvents { mut ew_step, mut ew_clock, mut ew_action } = received;
+    let SentGameEvents { mut er_step, mut er_clock, mut er_action } = sent;
+    for event in er_net.read() {
+        let Ok(message) = serde_json::from_str(&event.message) else { continue; };
+        match message {
+            Message::Step { step, ply, hash } => {
+                ew_step.write(ReceiveRemoteStep { step, ply, hash });
+            },
+            Message::Clock(message) => {
+                ew_clock.write(ReceiveRemoteClock { message });
+            },
+            Message::Action(action) => {
+                ew_action.write(ReceiveRemoteAction { action });
+            },
+            _ => {},
+        }
+    }
+
+    let mut send = |message: Message| {
+        let message = serde_json::to_string(&message).unwrap();
+        ew_net.write(SendNetMsgEvent { message });
+    };
+    for event in er_step.read() {
+        send(Message::Step { step: event.step.clone(), ply: event.ply, hash: event.hash });
+    }
+    for event in er_clock.read() {
+        send(Message::Clock(event.message.clone()));
+    }
+    for event in er_action.read() {
+        send(Message::Action(event.action.clone()));
+    }
 }
\ No newline at end of file
@@ -1,10 +1,16 @@
 use bevy::prelude::*;
 
-use crate::net::{message::*, net::*, types::*};
+use crate::net::{auth::RejectedPeers, chat::*, lobby::LobbyRooms, message::*, net::*, record::NetRecording, session::*, spectate::Spectators, types::*};
 
 mod types;
 mod net;
 pub mod message;
+pub mod session;
+pub mod spectate;
+pub mod chat;
+pub mod lobby;
+pub mod auth;
+pub mod record;
 
 pub use crate::net::types::{NetState, NetCommand, ReceiveNetMsgEvent, SendNetMsgEvent};
 
@@ -16,17 +22,32 @@ impl Plugin for NetPlugin {
             app.add_plugins(bevy_tokio_tasks::TokioTasksPlugin::default());
         }
         app.init_resource::<NetState>();
+        app.init_resource::<LocalProfile>();
+        app.init_resource::<Spectators>();
+        app.init_resource::<LobbyRooms>();
+        app.init_resource::<RejectedPeers>();
+        app.init_resource::<NetRecording>();
+        app.init_resource::<ChatLog>();
         app.add_event::<NetCommand>();
         app.add_event::<ReceiveNetMsgEvent>();
         app.add_event::<SendNetMsgEvent>();
         app.add_event::<ReceiveRemoteStep>();
         app.add_event::<SendRemoteStep>();
+        app.add_event::<ReceiveRemoteClock>();
+        app.add_event::<SendRemoteClock>();
+        app.add_event::<ReceiveRemoteAction>();
+        app.add_event::<SendRemoteAction>();
+        app.add_event::<ChatEvent>();
         app.add_systems(
             Update, 
             (
                 handle_net_commands,
                 process_incoming_messages,
                 handle_outgoing_messages,
+                route_game_messages,
+                send_hello,
+                handle_hello,
+                receive_chat,
             )
         );
     }
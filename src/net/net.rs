@@ -1,196 +1,697 @@
 use bevy::prelude::*;
 use bevy_tokio_tasks::TokioTasksRuntime;
-use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}};
-use crate::net::types::*;
-use std::{net::SocketAddr};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::tcp::{OwnedReadHalf, OwnedWriteHalf}, sync::mpsc::{UnboundedReceiver, UnboundedSender, WeakUnboundedSender}, task::JoinHandle};
+use crate::net::{auth::{self, LineCipher, RejectedPeers, RoomKey}, lobby::{self, LobbyRooms}, message::Message, record::{LogEvent, NetRecording, Recorder}, session::{Hello, LocalProfile, Session}, spectate::Spectators, types::*};
+use std::{fmt, future::Future, net::SocketAddr, path::PathBuf, pin::Pin, time::Duration};
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
+// 连接后每隔这么久发送一次心跳
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+// 这么久收不到对方的任何消息就认为连接已经断开
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+// 重新连接失败后隔这么久再试
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+// 连接结束的原因，只有意外断开时才尝试重新连接
+enum Closed {
+    ByUser,
+    ByPeer,
+    Dropped,
+}
+
+// 监听的一方转播给一个观众的记录中的身份
+const BROADCAST_ROLE: &str = "broadcast";
+
+// 本地在这个连接中的身份
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Host,
+    Client,
+    Spectator,
+}
+
+impl Role {
+    fn name(self) -> &'static str {
+        match self {
+            Role::Host => "host",
+            Role::Client => "client",
+            Role::Spectator => "spectator",
+        }
+    }
+}
+
+// 监听的一方从哪里接受连接
+enum ListenOn {
+    Address(String),
+    Room { lobby: String, host: String, game: String },
+}
+
+// 连接的一方怎样找到监听的一方
+enum Dial {
+    Address(String),
+    Room { lobby: String, room: u64 },
+}
+
+impl Dial {
+    // 连接并通过房间密码的验证
+    async fn dial(&self, key: &RoomKey, recorder: &Recorder) -> Result<Link, Error> {
+        let mut link = match self {
+            Dial::Address(addr) => Link::connect(addr).await?,
+            Dial::Room { lobby, room } => lobby::join_room(lobby, *room).await?,
+        };
+        auth::answer_challenge(&mut link, key, recorder).await?;
+        Ok(link)
+    }
+}
+
+impl fmt::Display for Dial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dial::Address(addr) => write!(f, "{}", addr),
+            Dial::Room { lobby, room } => write!(f, "room {} on {}", room, lobby),
+        }
+    }
+}
+
+// 连接的读取一端，加密后每行是一条密文
+struct LineReader {
+    reader: BufReader<OwnedReadHalf>,
+    cipher: Option<LineCipher>,
+}
+
+impl LineReader {
+    // 读一行，连接关闭时返回 None
+    async fn read_line(&mut self) -> Result<Option<String>, Error> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        match &mut self.cipher {
+            Some(cipher) if !line.is_empty() => cipher.open(line).map(Some),
+            _ => Ok(Some(line.to_string())),
+        }
+    }
+}
+
+struct LineWriter {
+    writer: OwnedWriteHalf,
+    cipher: Option<LineCipher>,
+}
+
+impl LineWriter {
+    async fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        let sealed = self.cipher.as_mut().map(|cipher| cipher.seal(line));
+        self.writer.write_all(sealed.as_deref().unwrap_or(line).as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+// 一个 TCP 连接，一行一条消息。first 是判断对方身份时已经读出的第一条消息
+pub struct Link {
+    reader: LineReader,
+    writer: LineWriter,
+    peer_addr: SocketAddr,
+    first: Option<String>,
+}
+
+impl Link {
+    pub async fn connect(addr: &str) -> Result<Self, Error> {
+        Self::new(tokio::net::TcpStream::connect(addr).await?)
+    }
+
+    fn new(socket: tokio::net::TcpStream) -> Result<Self, Error> {
+        let peer_addr = socket.peer_addr()?;
+        let (reader, writer) = socket.into_split();
+        Ok(Self {
+            reader: LineReader { reader: BufReader::new(reader), cipher: None },
+            writer: LineWriter { writer, cipher: None },
+            peer_addr,
+            first: None,
+        })
+    }
+
+    pub async fn send(&mut self, line: &str) -> Result<(), Error> {
+        self.writer.write_line(line).await
+    }
+
+    // 读一行，连接关闭时返回 None
+    pub async fn read_line(&mut self) -> Result<Option<String>, Error> {
+        self.reader.read_line().await
+    }
+
+    // 验证通过后双方同时开始加密，此后的每一行都是密文
+    pub fn encrypt(&mut self, key: &[u8; 32], host: bool) {
+        let (send, receive) = auth::line_ciphers(key, host);
+        self.writer.cipher = Some(send);
+        self.reader.cipher = Some(receive);
+    }
+
+    fn encrypted(&self) -> bool {
+        self.writer.cipher.is_some()
+    }
+}
+
+// 读出对方的下一条消息，跳过心跳。对方主动断开时返回 None，连接断开或太久收不到消息时返回错误
+async fn read_message(reader: &mut LineReader) -> Result<Option<String>, Error> {
+    loop {
+        // 对方每隔 PING_INTERVAL 发来心跳，太久收不到任何消息就认为连接已经断开
+        let read = tokio::time::timeout(PEER_TIMEOUT, reader.read_line()).await
+            .map_err(|_| format!("nothing received for {:?}", PEER_TIMEOUT))?;
+        let Some(msg) = read? else {
+            return Err("connection closed".into());
+        };
+        match serde_json::from_str(&msg) {
+            Ok(Message::Ping) => {},
+            Ok(Message::Bye) => return Ok(None),
+            _ if msg.is_empty() => {},
+            _ => return Ok(Some(msg)),
+        }
+    }
+}
+
+// 发送消息任务，有记录时同时记录发出的消息
+fn spawn_writer(mut writer: LineWriter, mut outgoing_rx: UnboundedReceiver<String>, recorder: Option<Recorder>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(msg) = outgoing_rx.recv().await {
+            if let Some(recorder) = &recorder {
+                recorder.sent(&msg);
+            }
+            if let Err(e) = writer.write_line(&msg).await {
+                error!("Write error: {}", e);
+                break;
+            }
+        }
+    })
+}
+
+// 心跳任务，不阻止发送通道关闭
+fn spawn_pinger(tx: WeakUnboundedSender<String>) -> JoinHandle<()> {
+    let ping = serde_json::to_string(&Message::Ping).unwrap();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PING_INTERVAL);
+        loop {
+            interval.tick().await;
+            match tx.upgrade() {
+                Some(tx) if tx.send(ping.clone()).is_ok() => {},
+                _ => break,
+            }
+        }
+    })
+}
+
 pub fn handle_net_commands(
     mut _commands: Commands,
     mut net_commands: EventReader<NetCommand>,
     runtime: Res<TokioTasksRuntime>,
     mut net_state: ResMut<NetState>,
+    recording: Res<NetRecording>,
 ) {
     for command in net_commands.read() {
-        match command {
-            NetCommand::Listen(addr) => {
-                match *net_state {
-                    NetState::Disconnected => {},
-                    _ => { continue; }
-                }
+        if let NetCommand::ListRooms(lobby) = command {
+            let lobby = lobby.clone();
+            runtime.spawn_background_task(move |mut ctx| async move {
+                let result = lobby::list_rooms(&lobby).await.map_err(|e| e.to_string());
+                ctx.run_on_main_thread(move |main_ctx| {
+                    let mut rooms = main_ctx.world.resource_mut::<LobbyRooms>();
+                    match result {
+                        Ok(list) => {
+                            rooms.rooms = list;
+                            rooms.error = None;
+                        },
+                        Err(e) => rooms.error = Some(e),
+                    }
+                }).await;
+            });
+            continue;
+        }
+        if let NetCommand::Disconnect = command {
+            // take 获取所有权的同时会将 net_state 自动置为默认值，也就是 Disconnected
+            match std::mem::take(&mut *net_state) {
+                NetState::Disconnected => {},
+                NetState::Listening(addr, sender) => {
+                    println!("Try to cancel server listening to {}", addr);
+                    let _ = sender.send(());
+                },
+                NetState::Connected(socket_addr, sender) => {
+                    println!("Try to disconnect with {}", socket_addr);
+                    let _ = sender.send(());
+                },
+                NetState::Reconnecting(addr, sender) => {
+                    println!("Stop waiting for {} to reconnect", addr);
+                    let _ = sender.send(());
+                },
+            }
+            continue;
+        }
 
-                let addr = addr.clone();
+        match *net_state {
+            NetState::Disconnected => {},
+            _ => { continue; }
+        }
+        match command {
+            NetCommand::Listen(addr, key) => {
+                let on = ListenOn::Address(addr.clone());
+                let key = key.clone();
+                let log_dir = recording.dir.clone();
                 runtime.spawn_background_task(move |ctx| async move {
-                    if let Err(e) = listen_server(addr, ctx).await {
+                    if let Err(e) = listen_server(on, key, log_dir, ctx).await {
                         error!("Server error: {}", e);
                     }
                 });
             }
-            
-            NetCommand::Connect(addr) => {
-                match *net_state {
-                    NetState::Disconnected => {},
-                    _ => { continue; }
-                }
-
-                let addr = addr.clone();
-                runtime.spawn_background_task(move |ctx| async move {
-                    if let Err(e) = connect_client(addr, ctx).await {
+            NetCommand::OpenRoom { lobby, host, game, key } => {
+                let on = ListenOn::Room { lobby: lobby.clone(), host: host.clone(), game: game.clone() };
+                let key = key.clone();
+                let log_dir = recording.dir.clone();
+                runtime.spawn_background_task(move |mut ctx| async move {
+                    if let Err(e) = listen_server(on, key, log_dir, ctx.clone()).await {
+                        error!("Server error: {}", e);
+                        report_lobby_error(&mut ctx, e).await;
+                    }
+                });
+            }
+            NetCommand::Connect(addr, key) | NetCommand::Watch(addr, key) => {
+                let dial = Dial::Address(addr.clone());
+                let role = if let NetCommand::Watch(..) = command { Role::Spectator } else { Role::Client };
+                let key = key.clone();
+                let log_dir = recording.dir.clone();
+                runtime.spawn_background_task(move |mut ctx| async move {
+                    if let Err(e) = connect_client(dial, role, key, log_dir, ctx.clone()).await {
                         eprintln!("Connection error: {}", e);
+                        report_connection_error(&mut ctx, e).await;
                     }
                 });
             }
-            
-            NetCommand::Disconnect => {
-                // take 获取所有权的同时会将 net_state 自动置为默认值，也就是 Disconnected
-                match std::mem::take(&mut *net_state) {
-                    NetState::Disconnected => {},
-                    NetState::Listening(socket_addr, sender) => {
-                        println!("Try to cancel server listening to {}", socket_addr);
-                        let _ = sender.send(());
-                    },
-                    NetState::Connected(socket_addr, sender) => {
-                        println!("Try to disconnect with {}", socket_addr);
-                        let _ = sender.send(());
-                    },
-                }
+            NetCommand::JoinRoom { lobby, room, spectator, key } => {
+                let dial = Dial::Room { lobby: lobby.clone(), room: *room };
+                let role = if *spectator { Role::Spectator } else { Role::Client };
+                let key = key.clone();
+                let log_dir = recording.dir.clone();
+                runtime.spawn_background_task(move |mut ctx| async move {
+                    if let Err(e) = connect_client(dial, role, key, log_dir, ctx.clone()).await {
+                        eprintln!("Connection error: {}", e);
+                        report_lobby_error(&mut ctx, e).await;
+                    }
+                });
             }
+            NetCommand::ListRooms(_) | NetCommand::Disconnect => {},
         }
     }
 }
 
+async fn report_lobby_error(ctx: &mut bevy_tokio_tasks::TaskContext, e: Error) {
+    let error = e.to_string();
+    ctx.run_on_main_thread(move |main_ctx| {
+        main_ctx.world.resource_mut::<LobbyRooms>().error = Some(error);
+    }).await;
+}
+
+// 连接失败时在菜单中显示原因，例如密码错误
+async fn report_connection_error(ctx: &mut bevy_tokio_tasks::TaskContext, e: Error) {
+    let error = e.to_string();
+    ctx.run_on_main_thread(move |main_ctx| {
+        match main_ctx.world.get_resource_mut::<Session>() {
+            Some(mut session) => session.error = Some(error),
+            None => {
+                let mut session = Session::default();
+                session.error = Some(error);
+                main_ctx.world.insert_resource(session);
+            },
+        }
+    }).await;
+}
+
+async fn record_rejection(ctx: &mut bevy_tokio_tasks::TaskContext, addr: SocketAddr, reason: String) {
+    info!("Rejected {}: {}", addr, reason);
+    ctx.run_on_main_thread(move |main_ctx| {
+        main_ctx.world.resource_mut::<RejectedPeers>().push(addr, reason);
+    }).await;
+}
+
+// 验证房间密码，再读出对方的握手信息，判断对方是对手还是观众
+fn greet(
+    mut link: Link,
+    key: RoomKey,
+    recorder: Recorder,
+    joined_tx: UnboundedSender<(Link, bool)>,
+    mut ctx: bevy_tokio_tasks::TaskContext,
+) {
+    tokio::spawn(async move {
+        if let Err(e) = auth::challenge_peer(&mut link, &key, &recorder).await {
+            record_rejection(&mut ctx, link.peer_addr, e.to_string()).await;
+            reject(link, &e.to_string(), &recorder).await;
+            return;
+        }
+        let Ok(Some(first)) = read_message(&mut link.reader).await else { return; };
+        let spectator = matches!(serde_json::from_str(&first), Ok(Message::Hello(Hello { spectator: true, .. })));
+        link.first = Some(first);
+        let _ = joined_tx.send((link, spectator));
+    });
+}
+
+// 拒绝没有通过验证的连接，以及已经有对手时其他想要对局的连接
+async fn reject(mut link: Link, reason: &str, recorder: &Recorder) {
+    for message in [Message::Rejected { reason: reason.to_string() }, Message::Bye] {
+        let message = serde_json::to_string(&message).unwrap();
+        recorder.sent(&message);
+        if link.send(&message).await.is_err() {
+            break;
+        }
+    }
+    recorder.log(LogEvent::Closed { reason: format!("rejected {}: {}", link.peer_addr, reason) });
+}
+
 async fn listen_server(
-    addr: String,
+    on: ListenOn,
+    key: RoomKey,
+    log_dir: PathBuf,
     mut ctx: bevy_tokio_tasks::TaskContext,
 ) -> Result<(), Error> {
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    let local_addr = listener.local_addr()?;
-    
-    info!("Server listening on {}", local_addr);
+    // 接受连接的任务把通过验证、读出握手信息的连接交给下面的循环
+    let (joined_tx, mut joined_rx) = tokio::sync::mpsc::unbounded_channel();
+    let greet_ctx = ctx.clone();
+    ctx.run_on_main_thread(|main_ctx| {
+        main_ctx.world.resource_mut::<RejectedPeers>().list.clear();
+    }).await;
+    // 记录在知道监听的地址后开始，包括之后每个连接的验证过程
+    let (local_addr, acceptor, recorder) = match on {
+        ListenOn::Address(addr) => {
+            let listener = tokio::net::TcpListener::bind(&addr).await?;
+            let local_addr = listener.local_addr()?;
+            info!("Server listening on {}", local_addr);
+            let recorder = Recorder::start(log_dir.clone(), Role::Host.name(), local_addr.to_string());
+            let greet_recorder = recorder.clone();
+            let acceptor = tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((socket, _)) => match Link::new(socket) {
+                            Ok(link) => greet(link, key.clone(), greet_recorder.clone(), joined_tx.clone(), greet_ctx.clone()),
+                            Err(e) => error!("Accept error: {}", e),
+                        },
+                        Err(e) => {
+                            error!("Accept error: {}", e);
+                            break;
+                        },
+                    }
+                }
+            });
+            (local_addr.to_string(), acceptor, recorder)
+        },
+        ListenOn::Room { lobby, host, game } => {
+            let (room, mut control) = lobby::open_room(&lobby, host, game, key.locked()).await?;
+            info!("Room {} opened on {}", room, lobby);
+            ctx.run_on_main_thread(move |main_ctx| {
+                let mut rooms = main_ctx.world.resource_mut::<LobbyRooms>();
+                rooms.opened = Some(room);
+                rooms.error = None;
+            }).await;
+            let local_addr = format!("room {} on {}", room, lobby);
+            let recorder = Recorder::start(log_dir.clone(), Role::Host.name(), local_addr.clone());
+            let greet_recorder = recorder.clone();
+            let acceptor = tokio::spawn(async move {
+                loop {
+                    match lobby::accept_guest(&mut control, &lobby).await {
+                        Ok(link) => greet(link, key.clone(), greet_recorder.clone(), joined_tx.clone(), greet_ctx.clone()),
+                        Err(e) => {
+                            error!("Lobby error: {}", e);
+                            break;
+                        },
+                    }
+                }
+            });
+            (local_addr, acceptor, recorder)
+        },
+    };
 
-    let (drop_tx, drop_rx) = tokio::sync::oneshot::channel();
-    
+    let (drop_tx, mut drop_rx) = tokio::sync::oneshot::channel();
+    let state_addr = local_addr.clone();
     ctx.run_on_main_thread(move |main_ctx| {
-        *main_ctx.world.resource_mut::<NetState>() = NetState::Listening(local_addr, drop_tx);
+        *main_ctx.world.resource_mut::<NetState>() = NetState::Listening(state_addr, drop_tx);
     }).await;
-    
-    tokio::select! {
-        result = listener.accept() => {
-            match result {
-                Ok((socket, peer_addr)) => {
-                    handle_connection(socket, peer_addr, ctx).await?;
+
+    // 第一个想要对局的连接成为对手，之后的连接只能观看。对手意外断开后等待它重新连接
+    let mut player: Option<Pin<Box<dyn Future<Output = Closed> + Send>>> = None;
+    let mut resume = false;
+    loop {
+        tokio::select! {
+            joined = joined_rx.recv() => {
+                let Some((link, spectator)) = joined else { break; };
+                if spectator {
+                    // 每个观众的转播单独记录，不和对局的记录混在一起
+                    let spectator_recorder = Recorder::start(log_dir.clone(), BROADCAST_ROLE, local_addr.clone());
+                    tokio::spawn(serve_spectator(link, spectator_recorder, ctx.clone()));
+                } else if player.is_none() {
+                    player = Some(Box::pin(handle_connection(link, Role::Host, resume, recorder.clone(), ctx.clone())));
+                } else {
+                    let mut reject_ctx = ctx.clone();
+                    let reject_recorder = recorder.clone();
+                    tokio::spawn(async move {
+                        let reason = "the game already has two players";
+                        record_rejection(&mut reject_ctx, link.peer_addr, reason.to_string()).await;
+                        reject(link, reason, &reject_recorder).await;
+                    });
                 }
-                Err(e) => {
-                    return Err(Box::new(e) as Error);
+            }
+            closed = async { player.as_mut().unwrap().await }, if player.is_some() => {
+                player = None;
+                match closed {
+                    Closed::ByUser | Closed::ByPeer => break,
+                    Closed::Dropped => {
+                        info!("Connection to the opponent lost, waiting for it to reconnect");
+                        resume = true;
+                        let (tx, rx) = tokio::sync::oneshot::channel();
+                        drop_rx = rx;
+                        let state_addr = local_addr.clone();
+                        ctx.run_on_main_thread(move |main_ctx| {
+                            *main_ctx.world.resource_mut::<NetState>() = NetState::Reconnecting(state_addr, tx);
+                        }).await;
+                    },
                 }
             }
-        }
-        // 监听一次性通道
-        _ = drop_rx => {
-            println!("Cancel server listening");
-            // 这里不需要手动 drop(listener)
+            // 监听一次性通道，对手连接期间由连接自己的通道处理
+            _ = &mut drop_rx, if player.is_none() => {
+                println!("Cancel server listening");
+                break;
+            }
         }
     }
 
+    acceptor.abort();
+    ctx.run_on_main_thread(|main_ctx| {
+        *main_ctx.world.resource_mut::<NetState>() = NetState::Disconnected;
+        main_ctx.world.resource_mut::<Spectators>().clear();
+        main_ctx.world.resource_mut::<LobbyRooms>().opened = None;
+    }).await;
+
     Ok(())
 }
 
 async fn connect_client(
-    addr: String,
-    ctx: bevy_tokio_tasks::TaskContext,
+    dial: Dial,
+    role: Role,
+    key: RoomKey,
+    log_dir: PathBuf,
+    mut ctx: bevy_tokio_tasks::TaskContext,
 ) -> Result<(), Error> {
-    let socket = tokio::net::TcpStream::connect(&addr).await?;
-    let peer_addr = socket.peer_addr()?;
+    let recorder = Recorder::start(log_dir, role.name(), dial.to_string());
+    let mut link = dial.dial(&key, &recorder).await?;
+
+    // 连接意外断开后每隔一段时间尝试重新连接，直到连上或者用户放弃
+    let mut resume = false;
+    loop {
+        match handle_connection(link, role, resume, recorder.clone(), ctx.clone()).await {
+            Closed::ByUser | Closed::ByPeer => break,
+            Closed::Dropped => resume = true,
+        }
+        info!("Connection to {} lost, reconnecting", dial);
+
+        let (cancel_tx, mut cancel_rx) = tokio::sync::oneshot::channel();
+        let reconnecting = dial.to_string();
+        ctx.run_on_main_thread(move |main_ctx| {
+            *main_ctx.world.resource_mut::<NetState>() = NetState::Reconnecting(reconnecting, cancel_tx);
+        }).await;
+
+        let reconnect = async {
+            loop {
+                match dial.dial(&key, &recorder).await {
+                    Ok(link) => break link,
+                    Err(e) => {
+                        info!("Reconnect to {} failed: {}", dial, e);
+                        tokio::time::sleep(RECONNECT_INTERVAL).await;
+                    }
+                }
+            }
+        };
+        tokio::select! {
+            new_link = reconnect => link = new_link,
+            _ = &mut cancel_rx => {
+                println!("Cancel reconnecting to {}", dial);
+                break;
+            }
+        }
+    }
+
+    ctx.run_on_main_thread(|main_ctx| {
+        *main_ctx.world.resource_mut::<NetState>() = NetState::Disconnected;
+    }).await;
 
-    handle_connection(socket, peer_addr, ctx).await?;
     Ok(())
 }
 
 async fn handle_connection(
-    socket: tokio::net::TcpStream,
-    peer_addr: SocketAddr,
+    link: Link,
+    role: Role,
+    resume: bool,
+    recorder: Recorder,
     mut ctx: bevy_tokio_tasks::TaskContext,
-) -> Result<(), Error> {
+) -> Closed {
+    let encrypted = link.encrypted();
+    let Link { mut reader, writer, peer_addr, first } = link;
     info!("Client to: {}", peer_addr);
+    recorder.log(LogEvent::Connected { peer: peer_addr.to_string(), encrypted });
     let (drop_tx, drop_rx) = tokio::sync::oneshot::channel();
     ctx.run_on_main_thread(move |main_ctx| {
         *main_ctx.world.resource_mut::<NetState>() = NetState::Connected(peer_addr, drop_tx);
     }).await;
-
-    let (reader, mut writer) = socket.into_split();
-    let mut reader = BufReader::new(reader);
     
     // 创建消息通道
     let (incoming_tx, incoming_rx) = tokio::sync::mpsc::unbounded_channel();
-    let (outgoing_tx, mut outgoing_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (outgoing_tx, outgoing_rx) = tokio::sync::mpsc::unbounded_channel();
+    let ping_tx = outgoing_tx.downgrade();
+    let bye_tx = outgoing_tx.clone();
+    if let Some(first) = first {
+        recorder.received(&first);
+        let _ = incoming_tx.send(first);
+    }
     
-    // 在主线程注册连接
+    // 在主线程注册连接，重新连接时保留原来的会话以便恢复对局
     ctx.run_on_main_thread(move |main_ctx| {
         main_ctx.world.insert_resource(NetConnection {
             tx: Some(outgoing_tx),
             incoming_rx,
         });
+        match main_ctx.world.get_resource_mut::<Session>() {
+            Some(mut session) if resume => session.reconnect(),
+            _ => main_ctx.world.insert_resource(Session::new(role == Role::Host, role == Role::Spectator)),
+        }
+        main_ctx.world.resource_mut::<Session>().encrypted = encrypted;
     }).await;
     
-    // 接收消息任务
-    let read_handle = tokio::spawn(async move {
-        let mut line = String::new();
+    // 接收消息任务，对方主动断开时返回 true
+    let read_recorder = recorder.clone();
+    let mut read_handle = tokio::spawn(async move {
         loop {
-            line.clear();
-            match reader.read_line(&mut line).await {
-                Ok(0) => break, // EOF
-                Ok(_) => {
-                    let msg = line.trim().to_string();
-                    if !msg.is_empty() {
-                        let _ = incoming_tx.send(msg);
-                    }
-                }
-                Err(e) => { 
-                    error!("Read error: {}", e);
-                    break;
+            match read_message(&mut reader).await {
+                Ok(Some(msg)) => {
+                    read_recorder.received(&msg);
+                    let _ = incoming_tx.send(msg);
+                },
+                Ok(None) => {
+                    read_recorder.received(&serde_json::to_string(&Message::Bye).unwrap());
+                    return true;
+                },
+                Err(e) => {
+                    warn!("Connection lost: {}", e);
+                    return false;
                 },
             }
         }
     });
-    
-    // 发送消息任务
-    let write_handle = tokio::spawn(async move {
-        while let Some(msg) = outgoing_rx.recv().await {
-            if let Err(e) = writer.write_all(msg.as_bytes()).await {
-                error!("Write error: {}", e);
-                break;
-            }
-            if let Err(e) = writer.write_all(b"\n").await {
-                error!("Write newline error: {}", e);
-                break;
-            }
-            if let Err(e) = writer.flush().await {
-                error!("Flush error: {}", e);
-                break;
-            }
-        }
-    });
+    let mut write_handle = spawn_writer(writer, outgoing_rx, Some(recorder.clone()));
+    let ping_handle = spawn_pinger(ping_tx);
 
     // 等待两个任务完成（任意一个结束就退出）
-    tokio::select! {
-         _ = read_handle => {
+    let closed = tokio::select! {
+        result = &mut read_handle => {
             info!("Read task finished, connection closed");
+            if result.unwrap_or(false) { Closed::ByPeer } else { Closed::Dropped }
         },
-        _ = write_handle => {
+        _ = &mut write_handle => {
             info!("Write task finished, connection closed");
+            Closed::Dropped
         },
         _ = drop_rx => {
             info!("Disconnection requested by user");
+            Closed::ByUser
         },
-    }
+    };
 
     ctx.run_on_main_thread(|main_ctx| {
-        *main_ctx.world.resource_mut::<NetState>() = NetState::Disconnected;
         main_ctx.world.remove_resource::<NetConnection>();
     }).await;
+    ping_handle.abort();
+    read_handle.abort();
 
-    Ok(())
+    // 告诉对方不必等待重新连接，发送通道关闭后发送任务会把剩下的消息写完再结束，Bye 也由它记录
+    if let Closed::ByUser = closed {
+        let _ = bye_tx.send(serde_json::to_string(&Message::Bye).unwrap());
+    }
+    drop(bye_tx);
+    if tokio::time::timeout(PING_INTERVAL, &mut write_handle).await.is_err() {
+        write_handle.abort();
+    }
+    let reason = match closed {
+        Closed::ByUser => "disconnected by the user",
+        Closed::ByPeer => "disconnected by the peer",
+        Closed::Dropped => "connection lost",
+    };
+    recorder.log(LogEvent::Closed { reason: reason.to_string() });
+
+    closed
+}
+
+// 观众只接收对局，由 spectate 系统发送游戏树和着法，观众发来的消息除了重新同步的请求都忽略
+async fn serve_spectator(link: Link, recorder: Recorder, mut ctx: bevy_tokio_tasks::TaskContext) {
+    let encrypted = link.encrypted();
+    let Link { mut reader, writer, peer_addr, first } = link;
+    recorder.log(LogEvent::Connected { peer: peer_addr.to_string(), encrypted });
+    if let Some(first) = &first {
+        recorder.received(first);
+    }
+    let name = match first.as_deref().map(serde_json::from_str) {
+        Some(Ok(Message::Hello(hello))) => hello.name,
+        _ => String::new(),
+    };
+    info!("Spectator {} joined from {}", name, peer_addr);
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let ping_tx = tx.downgrade();
+    let id = ctx.run_on_main_thread(move |main_ctx| {
+        let hello = Hello::local(main_ctx.world.resource::<LocalProfile>(), 0, false);
+        let _ = tx.send(serde_json::to_string(&Message::Hello(hello)).unwrap());
+        main_ctx.world.resource_mut::<Spectators>().join(name, peer_addr, tx)
+    }).await;
+
+    let mut write_handle = spawn_writer(writer, rx, Some(recorder.clone()));
+    let ping_handle = spawn_pinger(ping_tx);
+    let mut request_ctx = ctx.clone();
+    let read_recorder = recorder.clone();
+    let read = async move {
+        while let Ok(Some(msg)) = read_message(&mut reader).await {
+            read_recorder.received(&msg);
+            if let Ok(Message::SyncRequest) = serde_json::from_str(&msg) {
+                request_ctx.run_on_main_thread(move |main_ctx| {
+                    main_ctx.world.resource_mut::<Spectators>().request_tree(id);
+                }).await;
+            }
+        }
+    };
+    tokio::select! {
+        _ = read => {},
+        _ = &mut write_handle => {},
+    }
+    ping_handle.abort();
+    write_handle.abort();
+
+    info!("Spectator {} left", peer_addr);
+    recorder.log(LogEvent::Closed { reason: String::from("spectator left") });
+    ctx.run_on_main_thread(move |main_ctx| {
+        main_ctx.world.resource_mut::<Spectators>().leave(id);
+    }).await;
 }
 
 pub fn process_incoming_messages(
@@ -0,0 +1,137 @@
+// 把每次网络会话收发的消息记录到文件，一行一条带时间的 JSON，用于复盘和排查局面不一致
+use std::{fs, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, sync::mpsc::UnboundedSender};
+
+use crate::net::message::Message;
+
+pub const NET_LOG_EXTENSION: &str = "netlog";
+
+// 记录保存的目录，由文件设置决定
+#[derive(Resource)]
+pub struct NetRecording {
+    pub dir: PathBuf,
+}
+
+impl Default for NetRecording {
+    fn default() -> Self {
+        Self { dir: PathBuf::from("games/netlog") }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub enum LogEvent {
+    // 开始监听或者连接，target 是监听的地址或者连接的目标
+    Start { role: String, target: String },
+    // 通过验证，开始交换消息。重新连接后再记录一次
+    Connected { peer: String, encrypted: bool },
+    // 收发的消息原文，不包括心跳
+    Sent { message: String },
+    Received { message: String },
+    Closed { reason: String },
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LogEntry {
+    pub time: u64, // Unix 时间（毫秒）
+    pub event: LogEvent,
+}
+
+impl LogEntry {
+    // 收发的网络消息，以及是否是本地发出的
+    pub fn message(&self) -> Option<(bool, Message)> {
+        match &self.event {
+            LogEvent::Sent { message } => serde_json::from_str(message).ok().map(|message| (true, message)),
+            LogEvent::Received { message } => serde_json::from_str(message).ok().map(|message| (false, message)),
+            _ => None,
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+// 一次会话的记录，断线重连时沿用。文件在第一次连上时才创建，没有人连接的监听不留下记录
+#[derive(Clone)]
+pub struct Recorder {
+    tx: UnboundedSender<LogEntry>,
+    ping: String,
+}
+
+impl Recorder {
+    pub fn start(dir: PathBuf, role: &str, target: String) -> Self {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<LogEntry>();
+        let path = dir.join(format!("{}.{}", now_millis(), NET_LOG_EXTENSION));
+        tokio::spawn(async move {
+            let mut pending = Vec::new();
+            let mut file = None;
+            while let Some(entry) = rx.recv().await {
+                let mut line = serde_json::to_string(&entry).unwrap();
+                line.push('\n');
+                if file.is_none() {
+                    if !matches!(entry.event, LogEvent::Connected { .. }) {
+                        pending.push(line);
+                        continue;
+                    }
+                    let _ = tokio::fs::create_dir_all(&dir).await;
+                    match tokio::fs::File::create(&path).await {
+                        Ok(created) => file = Some(created),
+                        Err(e) => {
+                            warn!("failed to record the session to {}: {}", path.display(), e);
+                            break;
+                        },
+                    }
+                }
+                let Some(file) = file.as_mut() else { break; };
+                for line in pending.drain(..).chain(std::iter::once(line)) {
+                    if let Err(e) = file.write_all(line.as_bytes()).await {
+                        warn!("failed to record the session to {}: {}", path.display(), e);
+                        return;
+                    }
+                }
+            }
+        });
+        let recorder = Self { tx, ping: serde_json::to_string(&Message::Ping).unwrap() };
+        recorder.log(LogEvent::Start { role: role.to_string(), target });
+        recorder
+    }
+
+    pub fn log(&self, event: LogEvent) {
+        let _ = self.tx.send(LogEntry { time: now_millis(), event });
+    }
+
+    pub fn sent(&self, message: &str) {
+        if message != self.ping {
+            self.log(LogEvent::Sent { message: message.to_string() });
+        }
+    }
+
+    pub fn received(&self, message: &str) {
+        self.log(LogEvent::Received { message: message.to_string() });
+    }
+}
+
+pub fn read_log(path: &Path) -> Result<Vec<LogEntry>, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    content.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+// 目录中的所有记录，最新的在前
+pub fn list_logs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == NET_LOG_EXTENSION))
+        .collect();
+    files.sort();
+    files.reverse();
+    files
+}
@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::net::{message::Message, types::*};
+
+// 网络消息的格式改变时增加版本号，版本不同的双方不能对局
+pub const PROTOCOL_VERSION: u32 = 7;
+
+// 可以进行网络对局的棋，与开始对局邀请中的 game_name 相同
+pub const GAMES: [&str; 3] = ["Hequn", "Zhandi", "Xingxiang"];
+
+// 连接建立后双方首先互相发送的握手信息
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Hello {
+    pub protocol: u32,
+    pub app: String,
+    pub name: String,
+    pub games: Vec<String>,
+    // 监听一方发送自己的会话编号，连接一方发送上次连接时得到的编号，相同时恢复原来的对局
+    #[serde(default)]
+    pub session: u64,
+    // 以观众的身份连接，只接收对局
+    #[serde(default)]
+    pub spectator: bool,
+}
+
+impl Hello {
+    pub fn local(profile: &LocalProfile, session: u64, spectator: bool) -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            app: format!("xinqi {}", env!("CARGO_PKG_VERSION")),
+            name: profile.name.clone(),
+            games: GAMES.iter().map(|game| game.to_string()).collect(),
+            session,
+            spectator,
+        }
+    }
+}
+
+// 本地玩家的名字，握手时发送给对方
+#[derive(Resource)]
+pub struct LocalProfile {
+    pub name: String,
+}
+
+impl Default for LocalProfile {
+    fn default() -> Self {
+        Self { name: String::from("Player") }
+    }
+}
+
+// 一次会话的状态，在连接建立时重新创建，断开后保留以便显示出错的原因。意外断开后重新连接时沿用原来的会话
+#[derive(Resource, Default)]
+pub struct Session {
+    pub host: bool, // 监听的一方，局面不一致时以它的游戏树为准
+    pub spectator: bool, // 本地是观众
+    pub encrypted: bool, // 连接是否加密
+    pub id: u64,    // 由监听的一方生成，连接的一方收到握手信息之前为 0
+    pub peer: Option<Hello>,
+    pub error: Option<String>,
+    pub notice: Option<String>, // 不影响对局的提示，例如重新同步了游戏树
+    hello_sent: bool,
+    pub resuming: bool, // 重新连接上了原来的对手，等待交换双方的主线步数
+}
+
+impl Session {
+    pub fn new(host: bool, spectator: bool) -> Self {
+        let id = if host { rand::random::<u64>().max(1) } else { 0 };
+        Self { host, spectator, id, ..default() }
+    }
+
+    // 意外断开后重新连上，重新握手
+    pub fn reconnect(&mut self) {
+        self.hello_sent = false;
+        self.error = None;
+        self.notice = None;
+    }
+
+    // 对方是否可以进行这种棋，对方没有发来握手信息时不作限制
+    pub fn peer_supports(&self, game_name: &str) -> bool {
+        self.peer.as_ref().is_none_or(|hello| hello.games.iter().any(|game| game == game_name))
+    }
+}
+
+// 局面校验使用的 FNV-1a 哈希，与 Rust 版本和平台无关
+pub fn fen_hash(fen: &str) -> u64 {
+    fen.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn send_hello(
+    session: Option<ResMut<Session>>,
+    profile: Res<LocalProfile>,
+    mut ew_net: EventWriter<SendNetMsgEvent>,
+) {
+    let Some(mut session) = session.filter(|session| !session.hello_sent) else {
+        return;
+    };
+    session.hello_sent = true;
+    let hello = Hello::local(&profile, session.id, session.spectator);
+    let message = serde_json::to_string(&Message::Hello(hello)).unwrap();
+    ew_net.write(SendNetMsgEvent { message });
+}
+
+// 协议版本不同或者被对方拒绝时断开连接并报告原因，会话编号相同时准备恢复对局
+pub fn handle_hello(
+    session: Option<ResMut<Session>>,
+    mut er_net: EventReader<ReceiveNetMsgEvent>,
+    mut ew_nc: EventWriter<NetCommand>,
+) {
+    let Some(mut session) = session else {
+        er_net.clear();
+        return;
+    };
+    for event in er_net.read() {
+        let hello = match serde_json::from_str(&event.message) {
+            Ok(Message::Hello(hello)) => hello,
+            // 对方随后会断开连接
+            Ok(Message::Rejected { reason }) => {
+                session.error = Some(format!("Rejected: {}", reason));
+                continue;
+            },
+            _ => continue,
+        };
+        info!("session: peer {} ({}), protocol {}", hello.name, hello.app, hello.protocol);
+        if hello.protocol != PROTOCOL_VERSION {
+            session.error = Some(format!(
+                "{} uses protocol version {} ({}), but this is version {}",
+                hello.name, hello.protocol, hello.app, PROTOCOL_VERSION,
+            ));
+            ew_nc.write(NetCommand::Disconnect);
+            continue;
+        }
+        session.resuming = session.id != 0 && hello.session == session.id;
+        if session.resuming {
+            session.notice = Some(format!("{} reconnected, resuming the game", hello.name));
+        }
+        if !session.host {
+            session.id = hello.session;
+        }
+        session.peer = Some(hello);
+    }
+}
@@ -0,0 +1,77 @@
+use std::net::SocketAddr;
+use bevy::prelude::*;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::net::message::Message;
+
+pub struct Spectator {
+    pub id: u64,
+    pub name: String,
+    pub addr: SocketAddr,
+    tx: UnboundedSender<String>,
+    needs_tree: bool, // 刚加入或请求重新同步，等待发送完整的游戏树
+}
+
+// 监听一方的观众，观众只接收对局，发来的消息除了重新同步的请求都忽略
+#[derive(Resource, Default)]
+pub struct Spectators {
+    list: Vec<Spectator>,
+    next_id: u64,
+}
+
+impl Spectators {
+    pub fn join(&mut self, name: String, addr: SocketAddr, tx: UnboundedSender<String>) -> u64 {
+        self.next_id += 1;
+        self.list.push(Spectator { id: self.next_id, name, addr, tx, needs_tree: true });
+        self.next_id
+    }
+
+    pub fn leave(&mut self, id: u64) {
+        self.list.retain(|spectator| spectator.id != id);
+    }
+
+    // 停止监听时断开所有观众
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Spectator> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn request_tree(&mut self, id: u64) {
+        for spectator in self.list.iter_mut().filter(|spectator| spectator.id == id) {
+            spectator.needs_tree = true;
+        }
+    }
+
+    pub fn request_all(&mut self) {
+        for spectator in self.list.iter_mut() {
+            spectator.needs_tree = true;
+        }
+    }
+
+    pub fn needs_tree(&self) -> bool {
+        self.list.iter().any(|spectator| spectator.needs_tree)
+    }
+
+    // 等待完整游戏树的观众不需要单独的着法
+    pub fn broadcast(&self, message: &Message) {
+        let message = serde_json::to_string(message).unwrap();
+        for spectator in self.list.iter().filter(|spectator| !spectator.needs_tree) {
+            let _ = spectator.tx.send(message.clone());
+        }
+    }
+
+    pub fn send_tree(&mut self, tree: &Message) {
+        let message = serde_json::to_string(tree).unwrap();
+        for spectator in self.list.iter_mut().filter(|spectator| spectator.needs_tree) {
+            let _ = spectator.tx.send(message.clone());
+            spectator.needs_tree = false;
+        }
+    }
+}
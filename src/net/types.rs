@@ -1,13 +1,17 @@
 use bevy::prelude::*;
 use std::net::SocketAddr;
 
+use crate::net::auth::RoomKey;
+
 // 网络状态
 #[derive(Resource, Default, Debug)]
 pub enum NetState {
     #[default]
     Disconnected,
-    Listening(SocketAddr, tokio::sync::oneshot::Sender<()>),
+    Listening(String, tokio::sync::oneshot::Sender<()>), // 监听的地址，或者大厅中的房间
     Connected(SocketAddr, tokio::sync::oneshot::Sender<()>),
+    // 连接意外断开后等待对方重新连接（监听的一方）或不断尝试重新连接（连接的一方），对局保留
+    Reconnecting(String, tokio::sync::oneshot::Sender<()>),
 }
 
 // 网络消息事件
@@ -25,8 +29,14 @@ pub struct SendNetMsgEvent {
 // 网络命令
 #[derive(Event)]
 pub enum NetCommand {
-    Listen(String),
-    Connect(String),
+    Listen(String, RoomKey),
+    Connect(String, RoomKey),
+    // 以观众的身份连接
+    Watch(String, RoomKey),
+    // 在大厅开一个房间等待对手，host 是显示在房间列表中的名字
+    OpenRoom { lobby: String, host: String, game: String, key: RoomKey },
+    JoinRoom { lobby: String, room: u64, spectator: bool, key: RoomKey },
+    ListRooms(String),
     Disconnect,
 }
 
@@ -0,0 +1,44 @@
+// 着法评注符号，对应 PGN 中的 $1 到 $6
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Nag {
+    Good,
+    Mistake,
+    Brilliant,
+    Blunder,
+    Interesting,
+    Dubious,
+}
+
+impl Nag {
+    pub const ALL: [Nag; 6] = [Nag::Brilliant, Nag::Good, Nag::Interesting, Nag::Dubious, Nag::Mistake, Nag::Blunder];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Nag::Good => "!",
+            Nag::Mistake => "?",
+            Nag::Brilliant => "!!",
+            Nag::Blunder => "??",
+            Nag::Interesting => "!?",
+            Nag::Dubious => "?!",
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            Nag::Good => 1,
+            Nag::Mistake => 2,
+            Nag::Brilliant => 3,
+            Nag::Blunder => 4,
+            Nag::Interesting => 5,
+            Nag::Dubious => 6,
+        }
+    }
+
+    pub fn from_symbol(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|nag| nag.symbol() == s)
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|nag| nag.code() == code)
+    }
+}
@@ -0,0 +1,102 @@
+// 游戏树、FEN 和 PGN 文件的读写，根据扩展名判断文件格式
+use std::{fs, path::Path};
+use crate::{general::*, tree::game_tree::GameTree};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FileFormat {
+    Tree,
+    Fen,
+    Pgn,
+}
+
+impl FileFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            FileFormat::Tree => "tree",
+            FileFormat::Fen => "fen",
+            FileFormat::Pgn => "pgn",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "tree" => Some(FileFormat::Tree),
+            "fen" => Some(FileFormat::Fen),
+            "pgn" => Some(FileFormat::Pgn),
+            _ => None,
+        }
+    }
+}
+
+impl<B: Board> GameTree<B> {
+    // FEN 文件保存当前局面
+    pub fn save_file(&self, path: &Path) -> Result<(), String> {
+        let format = FileFormat::from_path(path).ok_or("unknown file extension")?;
+        let content = match format {
+            FileFormat::Tree => self.to_string(),
+            FileFormat::Fen => self.board().write_fen(),
+            FileFormat::Pgn => self.to_pgn(),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, content + "\n").map_err(|e| e.to_string())
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let format = FileFormat::from_path(path).ok_or("unknown file extension")?;
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let tree = match format {
+            FileFormat::Tree => Self::from_string(content),
+            FileFormat::Fen => B::read_fen(content.trim().to_string()).map(Self::new),
+            FileFormat::Pgn => Self::from_pgn(content),
+        };
+        tree.map_err(|e| e.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hequn::general::HequnBoard;
+
+    fn sample_tree() -> GameTree<HequnBoard> {
+        let mut tree = GameTree::new(HequnBoard::default());
+        for s in ["d4", "e5", "c3", "pass"] {
+            let step = tree.board().read_step(s.to_string()).unwrap();
+            assert!(tree.try_move(step));
+        }
+        tree.set_tag("Event", String::from("file test"));
+        tree
+    }
+
+    #[test]
+    fn save_and_load_each_format() {
+        let dir = std::env::temp_dir().join(format!("xinqi_file_test_{}", std::process::id()));
+        let tree = sample_tree();
+        for format in [FileFormat::Tree, FileFormat::Fen, FileFormat::Pgn] {
+            let path = dir.join("game").with_extension(format.extension());
+            assert_eq!(FileFormat::from_path(&path), Some(format));
+            tree.save_file(&path).unwrap();
+            let loaded = GameTree::<HequnBoard>::from_file(&path).unwrap();
+            match format {
+                FileFormat::Tree => assert_eq!(loaded.to_string(), tree.to_string()),
+                FileFormat::Fen => {
+                    assert_eq!(loaded.board().write_fen(), tree.board().write_fen());
+                    assert_eq!(loaded.node_count(), 1);
+                },
+                FileFormat::Pgn => {
+                    assert_eq!(loaded.to_pgn(), tree.to_pgn());
+                    assert_eq!(loaded.mainline_plies(), 4);
+                    assert_eq!(loaded.tag("Event"), Some("file test"));
+                },
+            }
+        }
+
+        let unknown = dir.join("game.txt");
+        assert!(tree.save_file(&unknown).is_err());
+        assert!(GameTree::<HequnBoard>::from_file(&dir.join("missing.tree")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
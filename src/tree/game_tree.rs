@@ -1,6 +1,10 @@
+#[cfg(feature = "gui")]
 use bevy::prelude::*;
-use bevy_egui::egui::{self, text::Fonts, Align2, FontId, RichText, Sense};
-use crate::{general::*, tree::{pgn::parse_pgn, game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}}};
+use serde::{Deserialize, Serialize};
+use crate::{general::*, tree::{annotation::Nag, pgn::{parse_pgn, PgnToken, RESULTS}}};
+
+#[cfg(feature = "gui")]
+mod ui;
 
 #[derive(Clone)]
 struct MoveData {
@@ -14,6 +18,9 @@ struct GameTreeNode<B> where B: Board {
     board: B,
     sons: Vec<(StepType<B>, usize, MoveData)>, // 默认第一个是主分支
     parent: Option<usize>,
+    comment: String, // 走到这个节点的着法的注释，根节点的注释是对整盘棋的注释
+    nag: Option<Nag>,
+    clock: Option<u32>, // 走出这一步后走子一方剩余的时间（毫秒）
 }
 
 impl<B: Board> GameTreeNode<B> {
@@ -22,15 +29,41 @@ impl<B: Board> GameTreeNode<B> {
             board: board,
             sons: Vec::new(),
             parent: None,
+            comment: String::new(),
+            nag: None,
+            clock: None,
         }
     }
+
+    fn has_annotation(&self) -> bool {
+        !self.comment.is_empty() || self.nag.is_some()
+    }
 }
 
-#[derive(Component, Default)]
+// 游戏树文本中节点信息之后的注释和标签部分，旧版本的游戏树文本没有这两部分
+const ANNOTATIONS_TITLE: &str = "[annotations]";
+const TAGS_TITLE: &str = "[tags]";
+const CLOCKS_TITLE: &str = "[clocks]";
+const CHAT_TITLE: &str = "[chat]";
+
+// 网络对局中的一条聊天消息，time 是 Unix 时间（秒）
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ChatLine {
+    pub time: u64,
+    pub sender: String,
+    pub text: String,
+}
+
+#[derive(Default)]
+#[cfg_attr(feature = "gui", derive(Component))]
 pub struct GameTree<B> where B: Board {
     nodes: Vec<GameTreeNode<B>>,
     root: usize,
     focus: usize,
+    editing_comment: Option<usize>, // 正在编辑注释的节点
+    tags: Vec<(String, String)>, // PGN 标签，不包括由棋盘决定的 Game 和 FEN
+    over: bool, // 对局因超时等原因在棋盘之外结束，主线末端不能再走子
+    chat: Vec<ChatLine>, // 对局期间的聊天记录
 }
 
 impl<B: Board> GameTree<B> {
@@ -43,30 +76,202 @@ impl<B: Board> GameTree<B> {
             nodes: vec![GameTreeNode::new(board)],
             root: 0,
             focus: 0,
+            editing_comment: None,
+            tags: Vec::new(),
+            over: false,
+            chat: Vec::new(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: String) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.tags.push((name.to_string(), value)),
+        }
+    }
+
+    // 读取完整的 PGN，包括标签、变着、注释和结果。不认识或不合法的着法会返回带有位置的错误信息
+    pub fn from_pgn(pgn: String) -> Result<Self, XinqiParseError> {
+        let mut tree = Self::new(B::default());
+        let mut tags: Vec<(String, String)> = Vec::new();
+        let mut current = tree.root;
+        let mut variations: Vec<usize> = Vec::new(); // 进入变着前所在的节点
+        let mut in_movetext = false;
+
+        for (token, pos) in parse_pgn(&pgn)? {
+            match token {
+                PgnToken::Tag(name, value) => {
+                    if in_movetext {
+                        return Err(pos.error("tag", "tag pair after moves"));
+                    }
+                    match name.as_str() {
+                        "Game" => {
+                            if !value.eq_ignore_ascii_case(B::NAME) {
+                                return Err(pos.error("Game tag", format!("this is a {} game, not {}", value, B::NAME)));
+                            }
+                        },
+                        "FEN" => {
+                            let board = B::read_fen(value).map_err(|e| pos.locate(e, "FEN tag"))?;
+                            tree = Self::new(board);
+                        },
+                        _ => tags.push((name, value)),
+                    }
+                },
+                PgnToken::Move(san) => {
+                    in_movetext = true;
+                    let step = tree.nodes[current].board.read_step(san.clone()).map_err(|e| pos.locate(e, "move"))?;
+                    tree.focus = current;
+                    if !tree.try_move(step) {
+                        return Err(pos.error("step", format!("illegal move \"{}\" at {}", san, tree.nodes[current].board.write_fen())));
+                    }
+                    current = tree.focus;
+                },
+                PgnToken::Nag(nag) => {
+                    if current == tree.root {
+                        return Err(pos.error("glyph", "glyph before the first move"));
+                    }
+                    tree.nodes[current].nag = Some(nag);
+                },
+                PgnToken::Comment(comment) => {
+                    let node = &mut tree.nodes[current];
+                    let (clock, comment) = extract_clock(&comment);
+                    if clock.is_some() {
+                        node.clock = clock;
+                    }
+                    if comment.is_empty() {
+                        continue;
+                    }
+                    if node.comment.is_empty() {
+                        node.comment = comment;
+                    } else {
+                        node.comment = format!("{} {}", node.comment, comment);
+                    }
+                },
+                PgnToken::VariationStart => {
+                    in_movetext = true;
+                    // 变着是上一步的替代着法
+                    let Some(parent) = tree.nodes[current].parent else {
+                        return Err(pos.error("variation", "variation before the first move"));
+                    };
+                    variations.push(current);
+                    current = parent;
+                },
+                PgnToken::VariationEnd => {
+                    current = variations.pop().ok_or_else(|| pos.error("variation", "unmatched ')'"))?;
+                },
+                PgnToken::Result(result) => {
+                    if !variations.is_empty() {
+                        return Err(pos.error("result", "result inside a variation"));
+                    }
+                    in_movetext = true;
+                    if let Some((_, v)) = tags.iter_mut().find(|(n, _)| n == "Result") {
+                        *v = result;
+                    } else {
+                        tags.push(("Result".to_string(), result));
+                    }
+                },
+            }
         }
+
+        if !variations.is_empty() {
+            return Err(XinqiParseError::new("variation", "unclosed variation at the end of PGN"));
+        }
+
+        tree.tags = tags;
+        tree.focus = tree.root;
+        tree.over = tree.ended_off_board();
+        Ok(tree)
     }
 
-    pub fn from_pgn(pgn: String) -> Option<Self> {
-        let mut board = B::default();
-        let mut tree = Self::new(board.clone());
+    // 主线最后的局面决定结果，对局还没有结束时使用 Result 标签
+    pub fn result(&self) -> String {
+        let mut current = self.root;
+        while let Some((_, son, _)) = self.nodes[current].sons.first() {
+            current = *son;
+        }
+        let board = &self.nodes[current].board;
+        if board.end_game() {
+            match board.get_winner() {
+                Some(PlayerOrder::First) => "1-0",
+                Some(PlayerOrder::Second) => "0-1",
+                None => "1/2-1/2",
+            }.to_string()
+        } else {
+            self.tag("Result").filter(|r| RESULTS.contains(r)).unwrap_or("*").to_string()
+        }
+    }
+
+    // 导出整棵游戏树，包括所有变着、注释和每步剩余的时间（%clk）
+    // 聊天记录不写入 PGN，只有游戏树代码会保存
+    pub fn to_pgn(&self) -> String {
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut lines = vec![format!("[Game \"{}\"]", B::NAME)];
+        let initial = self.nodes[self.root].board.write_fen();
+        if initial != B::default().write_fen() {
+            lines.push(format!("[FEN \"{}\"]", escape(&initial)));
+        }
+        for (name, value) in &self.tags {
+            if name != "Result" {
+                lines.push(format!("[{} \"{}\"]", name, escape(value)));
+            }
+        }
+        let result = self.result();
+        lines.push(format!("[Result \"{}\"]", result));
+        lines.push(String::new());
 
-        let steps = parse_pgn(&pgn);
+        let mut movetext = Vec::new();
+        if !self.nodes[self.root].comment.is_empty() {
+            movetext.push(Self::pgn_comment(&self.nodes[self.root].comment));
+        }
+        self.write_pgn_moves(self.root, &mut movetext, true);
+        movetext.push(result);
+        lines.push(movetext.join(" "));
+        lines.join("\n")
+    }
 
-        for step in steps.into_iter() {
-            if let Some(s) = board.read_step(step) {
-                if tree.try_move(s) {
-                    board = tree.board();
+    // 从 current 开始写出主线，每一步之后写出这一步的变着
+    fn write_pgn_moves(&self, mut current: usize, out: &mut Vec<String>, mut need_number: bool) {
+        while let Some((_, son, move_data)) = self.nodes[current].sons.first() {
+            out.push(self.pgn_move(*son, move_data, need_number));
+            let comment = self.node_comment(*son);
+            if !comment.is_empty() {
+                out.push(Self::pgn_comment(&comment));
+            }
+            for (_, var, var_data) in &self.nodes[current].sons[1..] {
+                let mut variation = vec![self.pgn_move(*var, var_data, true)];
+                let var_comment = self.node_comment(*var);
+                if !var_comment.is_empty() {
+                    variation.push(Self::pgn_comment(&var_comment));
                 }
+                self.write_pgn_moves(*var, &mut variation, !var_comment.is_empty());
+                out.push(format!("({})", variation.join(" ")));
             }
+            need_number = self.nodes[current].sons.len() > 1 || !comment.is_empty();
+            current = *son;
         }
+    }
 
-        Some(tree)
+    fn pgn_move(&self, son: usize, move_data: &MoveData, need_number: bool) -> String {
+        let glyph = self.nodes[son].nag.map_or("", |nag| nag.symbol());
+        match move_data.player {
+            PlayerOrder::First => format!("{}.{}{}", move_data.ply, move_data.san, glyph),
+            PlayerOrder::Second if need_number => format!("{}...{}{}", move_data.ply, move_data.san, glyph),
+            PlayerOrder::Second => format!("{}{}", move_data.san, glyph),
+        }
     }
 
     pub fn pgn(&self, mut current: usize) -> String {
         let mut path: Vec<usize> = Vec::new();
         let mut sans: Vec<String> = Vec::new();
 
+        if !self.nodes[self.root].comment.is_empty() {
+            sans.push(Self::pgn_comment(&self.nodes[self.root].comment));
+        }
+
         while current != self.root {
             if let Some(parent) = self.nodes[current].parent {
                 path.push(current);
@@ -80,9 +285,13 @@ impl<B: Board> GameTree<B> {
         for node in path {
             for (_step, son, move_data) in self.nodes[current].sons.iter() {
                 if node == *son {
+                    let glyph = self.nodes[node].nag.map_or("", |nag| nag.symbol());
                     match move_data.player {
-                        PlayerOrder::First => sans.push(format!("{}.{}", move_data.ply, move_data.san)),
-                        PlayerOrder::Second => sans.push(move_data.san.clone()),
+                        PlayerOrder::First => sans.push(format!("{}.{}{}", move_data.ply, move_data.san, glyph)),
+                        PlayerOrder::Second => sans.push(format!("{}{}", move_data.san, glyph)),
+                    }
+                    if !self.nodes[node].comment.is_empty() {
+                        sans.push(Self::pgn_comment(&self.nodes[node].comment));
                     }
                     current = *son;
                     break
@@ -92,59 +301,160 @@ impl<B: Board> GameTree<B> {
         sans.join(" ")
     }
 
-    pub fn from_string(s: String) -> Option<Self> {
+    // 导出 PGN 时棋钟的时间写在注释开头，例如 {[%clk 0:04:58] 好棋}
+    fn node_comment(&self, node: usize) -> String {
+        let node = &self.nodes[node];
+        match node.clock {
+            Some(ms) if node.comment.is_empty() => format!("[%clk {}]", pgn_clock(ms)),
+            Some(ms) => format!("[%clk {}] {}", pgn_clock(ms), node.comment),
+            None => node.comment.clone(),
+        }
+    }
+
+    // PGN 注释中不能出现右花括号
+    fn pgn_comment(comment: &str) -> String {
+        format!("{{{}}}", comment.replace('}', ")"))
+    }
+
+    pub fn from_string(s: String) -> Result<Self, XinqiParseError> {
+        // trim 去掉的空行也计入行号
+        let first_line = s[..s.len() - s.trim_start().len()].matches('\n').count() + 1;
+        let line_no = |index: usize| first_line + index;
         let lines: Vec<&str> = s.trim().lines().collect();
     
-        if lines.len() < 4 || lines[0] != "[chess game tree]" {
-            return None
+        if lines.first() != Some(&"[chess game tree]") {
+            return Err(XinqiParseError::new("header", "expected \"[chess game tree]\"").at(line_no(0), 1))
+        }
+        if lines.len() < 3 {
+            return Err(XinqiParseError::new("header", "missing initial FEN or node count"))
         }
 
-        let initial_fen = lines[1];
-        let nodes_count: usize = lines[2].parse().unwrap_or(0);
-        let info_lines = &lines[3..];
+        let initial_board = B::read_fen(lines[1].to_string()).map_err(|e| e.within(line_no(1), 1))?;
+        let nodes_count: usize = match lines[2].parse() {
+            Ok(n) if n >= 1 => n,
+            _ => return Err(XinqiParseError::new("node count", format!("expected a positive number, found \"{}\"", lines[2])).at(line_no(2), 1)),
+        };
+        // 节点信息之后依次是可选的注释部分、棋钟部分、标签部分和聊天部分
+        let section_start = |title: &str| lines[3..].iter().position(|line| *line == title).map(|pos| pos + 3);
+        let starts: Vec<usize> = [ANNOTATIONS_TITLE, CLOCKS_TITLE, TAGS_TITLE, CHAT_TITLE].iter().filter_map(|title| section_start(title)).collect();
+        let info_end = starts.iter().copied().min().unwrap_or(lines.len());
+        let info_lines = &lines[3..info_end];
+        // 返回部分第一行的下标和部分中的各行，部分结束于下一个部分的标题
+        let section = |title: &str| match section_start(title) {
+            Some(start) => {
+                let end = starts.iter().copied().filter(|s| *s > start).min().unwrap_or(lines.len());
+                (start + 1, &lines[start + 1..end])
+            },
+            None => (0, &lines[0..0]),
+        };
+        let (annotation_first, annotation_lines) = section(ANNOTATIONS_TITLE);
+        let (clock_first, clock_lines) = section(CLOCKS_TITLE);
+        let (tag_first, tag_lines) = section(TAGS_TITLE);
+        let (chat_first, chat_lines) = section(CHAT_TITLE);
         
-        let initial_board = B::read_fen(initial_fen.to_string())?;
         let mut tree = GameTree {
             nodes: vec![GameTreeNode::new(B::default()); nodes_count],
             root: 0,
             focus: 0,
+            editing_comment: None,
+            tags: Vec::new(),
+            over: false,
+            chat: Vec::new(),
         };
         tree.nodes[0] = GameTreeNode::new(initial_board);
 
-        for (node_id, line) in info_lines.iter().enumerate() {
+        for (node_id, text) in info_lines.iter().enumerate() {
             if node_id >= nodes_count {
                 break 
             }
+            let line = line_no(3 + node_id);
+
+            let mut column = 1;
+            for move_info in text.split('|') {
+                let info_column = column;
+                column += move_info.chars().count() + 1;
+                if move_info.is_empty() {
+                    continue;
+                }
 
-            let move_infos: Vec<&str> = line.split('|').collect();
-
-            for move_info in move_infos {
                 // 解析 (son_id, san)
-                if let Some(inner) = move_info.strip_prefix('(')
-                    .and_then(|s| s.strip_suffix(')')) 
-                {
-                    let parts: Vec<&str> = inner.splitn(2, ", ").collect();
-                    if parts.len() == 2 {
-                        let Ok(son_id) = parts[0].parse::<usize>() else {
-                            return None
-                        };
-                        let san = parts[1].to_string();
-                        let step = tree.nodes[node_id].board.read_step(san)?;
-                        let board = tree.nodes[node_id].board.try_move(step)?;
-                        tree.nodes[son_id].board = board;
-                        tree.nodes[son_id].parent = Some(node_id);
-                        let move_data = MoveData {
-                            ply: tree.nodes[node_id].board.get_fullmove(),
-                            san: tree.nodes[node_id].board.write_step(step).unwrap(),
-                            player: tree.nodes[node_id].board.get_active_player(),
-                        };
-                        tree.nodes[node_id].sons.push((step, son_id, move_data));
-                    }
-                }
+                let Some((son, san)) = move_info.strip_prefix('(')
+                    .and_then(|s| s.strip_suffix(')'))
+                    .and_then(|s| s.split_once(", ")) 
+                else {
+                    return Err(XinqiParseError::new("move", format!("expected \"(node id, move)\", found \"{}\"", move_info)).at(line, info_column))
+                };
+                // 子节点总是排在父节点之后
+                let son_id = match son.parse::<usize>() {
+                    Ok(id) if id > node_id && id < nodes_count => id,
+                    _ => return Err(XinqiParseError::new("node id", format!("expected a number from {} to {}, found \"{}\"", node_id + 1, nodes_count - 1, son)).at(line, info_column + 1)),
+                };
+                let san_column = info_column + son.chars().count() + 3;
+                let step = tree.nodes[node_id].board.read_step(san.to_string()).map_err(|e| e.within(line, san_column))?;
+                let board = tree.nodes[node_id].board.try_move(step)
+                    .ok_or_else(|| invalid_step(san, "illegal move").within(line, san_column))?;
+                tree.nodes[son_id].board = board;
+                tree.nodes[son_id].parent = Some(node_id);
+                let move_data = MoveData {
+                    ply: tree.nodes[node_id].board.get_fullmove(),
+                    san: tree.nodes[node_id].board.write_step(step).unwrap(),
+                    player: tree.nodes[node_id].board.get_active_player(),
+                };
+                tree.nodes[node_id].sons.push((step, son_id, move_data));
+            }
+        }
+
+        // 每行是 "{node_id} {评注符号或 -} {JSON 字符串形式的注释}"
+        for (i, text) in annotation_lines.iter().enumerate() {
+            let line = line_no(annotation_first + i);
+            let parts: Vec<&str> = text.splitn(3, ' ').collect();
+            if parts.len() != 3 {
+                return Err(XinqiParseError::new("annotation", "expected \"{node id} {glyph or -} {comment}\"").at(line, 1))
             }
+            let node = match parts[0].parse::<usize>() {
+                Ok(node_id) if node_id < nodes_count => &mut tree.nodes[node_id],
+                _ => return Err(XinqiParseError::new("node id", format!("expected a number from 0 to {}, found \"{}\"", nodes_count - 1, parts[0])).at(line, 1)),
+            };
+            let glyph_column = parts[0].chars().count() + 2;
+            node.nag = if parts[1] == "-" {
+                None
+            } else {
+                Some(Nag::from_symbol(parts[1])
+                    .ok_or_else(|| XinqiParseError::new("glyph", format!("unknown glyph \"{}\"", parts[1])).at(line, glyph_column))?)
+            };
+            node.comment = serde_json::from_str(parts[2])
+                .map_err(|e| XinqiParseError::new("comment", e.to_string()).at(line, glyph_column + parts[1].chars().count() + 1))?;
+        }
+
+        // 每行是 "{node_id} {毫秒}"
+        for (i, text) in clock_lines.iter().enumerate() {
+            let line = line_no(clock_first + i);
+            let Some((node_id, ms)) = text.split_once(' ') else {
+                return Err(XinqiParseError::new("clock", "expected \"{node id} {milliseconds}\"").at(line, 1))
+            };
+            let node = match node_id.parse::<usize>() {
+                Ok(node_id) if node_id < nodes_count => &mut tree.nodes[node_id],
+                _ => return Err(XinqiParseError::new("node id", format!("expected a number from 0 to {}, found \"{}\"", nodes_count - 1, node_id)).at(line, 1)),
+            };
+            node.clock = Some(ms.parse().map_err(|_| XinqiParseError::new("clock", format!("expected milliseconds, found \"{}\"", ms)).at(line, node_id.chars().count() + 2))?);
+        }
+
+        // 每行是 JSON 形式的 [name, value]
+        for (i, text) in tag_lines.iter().enumerate() {
+            let tag = serde_json::from_str(text)
+                .map_err(|e| XinqiParseError::new("tag", e.to_string()).at(line_no(tag_first + i), 1))?;
+            tree.tags.push(tag);
         }
 
-        Some(tree) 
+        // 每行是 JSON 形式的 {time, sender, text}
+        for (i, text) in chat_lines.iter().enumerate() {
+            let line = serde_json::from_str(text)
+                .map_err(|e| XinqiParseError::new("chat", e.to_string()).at(line_no(chat_first + i), 1))?;
+            tree.chat.push(line);
+        }
+
+        tree.over = tree.ended_off_board();
+        Ok(tree) 
     }
 
     pub fn to_string(&self) -> String {
@@ -162,13 +472,56 @@ impl<B: Board> GameTree<B> {
         .collect::<Vec<String>>()
         .join("\n");
 
-        format!("{}\n{}\n{}\n{}", title, initial, nodes, info)
+        let annotations = self.nodes.iter().enumerate()
+            .filter(|(_, node)| node.has_annotation())
+            .map(|(id, node)| {
+                format!(
+                    "{} {} {}",
+                    id,
+                    node.nag.map_or("-", |nag| nag.symbol()),
+                    serde_json::to_string(&node.comment).unwrap(),
+                )
+            })
+            .collect::<Vec<String>>();
+
+        let clocks = self.nodes.iter().enumerate()
+            .filter_map(|(id, node)| node.clock.map(|ms| format!("{} {}", id, ms)))
+            .collect::<Vec<String>>();
+
+        let mut text = format!("{}\n{}\n{}\n{}", title, initial, nodes, info);
+        if !annotations.is_empty() {
+            text = format!("{}\n{}\n{}", text, ANNOTATIONS_TITLE, annotations.join("\n"));
+        }
+        if !clocks.is_empty() {
+            text = format!("{}\n{}\n{}", text, CLOCKS_TITLE, clocks.join("\n"));
+        }
+        if !self.tags.is_empty() {
+            let tags = self.tags.iter()
+                .map(|tag| serde_json::to_string(tag).unwrap())
+                .collect::<Vec<String>>();
+            text = format!("{}\n{}\n{}", text, TAGS_TITLE, tags.join("\n"));
+        }
+        if !self.chat.is_empty() {
+            let chat = self.chat.iter()
+                .map(|line| serde_json::to_string(line).unwrap())
+                .collect::<Vec<String>>();
+            text = format!("{}\n{}\n{}", text, CHAT_TITLE, chat.join("\n"));
+        }
+        text
     }
 
     pub fn board(&self) -> B {
         self.nodes[self.focus].board.clone()
     }
 
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
     pub fn is_first_board(&self) -> bool {
         return self.focus == self.root
     }
@@ -177,36 +530,104 @@ impl<B: Board> GameTree<B> {
         return self.nodes[self.focus].sons.len() == 0
     }
 
-    pub fn move_to_start(&self, ew_mtn: &mut EventWriter<MoveToNodeEvent>) {
-        let mut target = self.focus;
-        while let Some(parent) = self.nodes[target].parent {
-            target = parent;
+    // 主线最后一个节点
+    pub fn mainline_end(&self) -> usize {
+        let mut current = self.root;
+        while let Some((_, son, _)) = self.nodes[current].sons.first() {
+            current = *son;
+        }
+        current
+    }
+
+    // 主线上的步数
+    pub fn mainline_plies(&self) -> usize {
+        let mut plies = 0;
+        let mut current = self.root;
+        while let Some((_, son, _)) = self.nodes[current].sons.first() {
+            current = *son;
+            plies += 1;
+        }
+        plies
+    }
+
+    // 主线上走完第 ply 步的局面
+    pub fn mainline_board_at(&self, ply: usize) -> Option<B> {
+        let mut current = self.root;
+        for _ in 0..ply {
+            current = self.nodes[current].sons.first()?.1;
         }
-        ew_mtn.write(MoveToNodeEvent { node_id: target });
+        Some(self.nodes[current].board.clone())
     }
 
-    pub fn move_backward(&self, ew_mtn: &mut EventWriter<MoveToNodeEvent>) {
-        let mut target = self.focus;
-        if let Some(parent) = self.nodes[target].parent {
-            target = parent;
+    // 主线上第 ply 步之后的着法，以及走完每一步的局面
+    pub fn mainline_steps_after(&self, ply: usize) -> Vec<(String, B)> {
+        let mut steps = Vec::new();
+        let mut current = self.root;
+        let mut plies = 0;
+        while let Some((_, son, move_data)) = self.nodes[current].sons.first() {
+            current = *son;
+            plies += 1;
+            if plies > ply {
+                steps.push((move_data.san.clone(), self.nodes[current].board.clone()));
+            }
         }
-        ew_mtn.write(MoveToNodeEvent { node_id: target });
+        steps
+    }
+
+    pub fn mainline_board(&self) -> B {
+        self.nodes[self.mainline_end()].board.clone()
     }
 
-    pub fn move_forward(&self, ew_mtn: &mut EventWriter<MoveToNodeEvent>) {
-        let mut target = self.focus;
-        if !self.nodes[target].sons.is_empty() {
-            target = self.nodes[target].sons[0].1;
+    pub fn set_clock(&mut self, node: usize, ms: u32) {
+        self.nodes[node].clock = Some(ms);
+    }
+
+    // 从根节点走到 node 的过程中双方最后记录的剩余时间
+    pub fn clocks_at(&self, node: usize) -> (Option<u32>, Option<u32>) {
+        let (mut first, mut second) = (None, None);
+        let mut current = node;
+        while let Some(parent) = self.nodes[current].parent {
+            if let Some(ms) = self.nodes[current].clock {
+                let mover = self.nodes[parent].board.get_active_player();
+                match mover {
+                    PlayerOrder::First => { first.get_or_insert(ms); },
+                    PlayerOrder::Second => { second.get_or_insert(ms); },
+                }
+            }
+            current = parent;
         }
-        ew_mtn.write(MoveToNodeEvent { node_id: target });
+        (first, second)
+    }
+
+    // 对局在棋盘之外结束，例如超时
+    pub fn set_result(&mut self, result: &str, termination: &str) {
+        self.set_tag("Result", result.to_string());
+        self.set_tag("Termination", termination.to_string());
+        self.over = true;
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
     }
 
-    pub fn move_to_end(&self, ew_mtn: &mut EventWriter<MoveToNodeEvent>) {
-        let mut target = self.focus;
-        while !self.nodes[target].sons.is_empty() {
-            target = self.nodes[target].sons[0].1;
+    // 读取棋谱时由标签恢复 over：set_result 总是同时写入 Termination 和确定的 Result
+    fn ended_off_board(&self) -> bool {
+        self.tag("Termination").is_some() && self.tag("Result").is_some_and(|result| result != "*")
+    }
+
+    pub fn chat(&self) -> &[ChatLine] {
+        &self.chat
+    }
+
+    pub fn add_chat(&mut self, line: ChatLine) {
+        self.chat.push(line);
+    }
+
+    // 不经过事件，直接把焦点移到主线末尾，用于没有界面的场合
+    pub fn focus_mainline_end(&mut self) {
+        while let Some((_, son, _)) = self.nodes[self.focus].sons.first() {
+            self.focus = *son;
         }
-        ew_mtn.write(MoveToNodeEvent { node_id: target });
     }
 
     // 由于rust的禁止双重借用的规则被迫用了比较奇怪的写法，实际上函数式会好一些
@@ -257,13 +678,8 @@ impl<B: Board> GameTree<B> {
         }
     }
 
-    pub fn handle_delete_variation(&mut self, e: &DeleteVariationEvent, ew: &mut EventWriter<UpdateBoard<B>>) {
-        let current = e.node_id;
-        if current == self.root {
-            warn!("Try to delete game tree root");
-            return;
-        }
-
+    // 删除 current 和它的所有后继，返回焦点是否保留
+    fn delete_node(&mut self, current: usize) -> bool {
         let mut remaining_nodes = Vec::new();
         let mut node_mapping = vec![None; self.nodes.len()];
 
@@ -292,361 +708,221 @@ impl<B: Board> GameTree<B> {
 
         self.nodes = new_nodes;
         self.root = 0;
-        // 更新焦点。如果原来的焦点被删除，将焦点移到根并更新棋盘。
-        if node_mapping[self.focus].is_none() {
-            self.focus = 0;
-            ew.write(UpdateBoard::new(self.board()));
-        } else {
-            self.focus = node_mapping[self.focus].unwrap();
+        self.editing_comment = None;
+        match node_mapping[self.focus] {
+            Some(focus) => {
+                self.focus = focus;
+                true
+            },
+            None => false,
         }
     }
 
-    pub fn handle_move_to_node(&mut self, e: &MoveToNodeEvent, ew: &mut EventWriter<UpdateBoard<B>>) {
-        let node_id = e.node_id;
-        if node_id >= self.nodes.len() {
-            warn!("game_tree: try to move to a node that does not exist.");
-            return;
+    // 悔棋：删除主线第 plies 步之后的着法，焦点移到那里。这一步的其他变着保留，第一个变着成为主线
+    pub fn take_back(&mut self, plies: usize) -> bool {
+        let mut current = self.root;
+        for _ in 0..plies {
+            match self.nodes[current].sons.first() {
+                Some((_, son, _)) => current = *son,
+                None => return false,
+            }
+        }
+        if self.nodes[current].sons.is_empty() {
+            return false;
         }
-        self.focus = node_id;
-        ew.write(UpdateBoard::new(self.board()));
+        self.focus = current;
+        let son = self.nodes[current].sons[0].1;
+        self.delete_node(son);
+        true
     }
 
-    fn show_context_menu(
-        &mut self,
-        current: usize,
-        response: &egui::Response,
-        ew_dv: &mut EventWriter<DeleteVariationEvent>,
-    ) {
-        egui::Popup::context_menu(response)
-            .show(|ui| {
-            ui.set_min_width(120.0);
-            
-            if ui.button("Promote Variation").clicked() {
-                if let Some(parent) = self.nodes[current].parent {
-                    let mut pos = 0;
-                    for (idx, (_, son_id, _)) in self.nodes[parent].sons.iter().enumerate() {
-                        if *son_id == current {
-                            pos = idx;
-                        }
-                    }
-                    if pos != 0 {
-                        self.nodes[parent].sons.swap(0, pos);
-                    }
-                }
-            }
-            
-            if ui.button("Set as mainline").clicked() {
-                let mut cur = current;
-                while cur != self.root {
-                    if let Some(parent) = self.nodes[cur].parent {
-                        let mut pos = 0;
-                        for (idx, (_, son_id, _)) in self.nodes[parent].sons.iter().enumerate() {
-                            if *son_id == cur {
-                                pos = idx;
-                            }
-                        }
-                        if pos != 0 {
-                            self.nodes[parent].sons.swap(0, pos);
-                        }
-                        cur = parent;
-                    } else {
-                        unreachable!()
-                    }
-                }
+    // 撤回 player 在主线上的最后一步时，主线剩下的步数
+    pub fn takeback_plies(&self, player: PlayerOrder) -> Option<usize> {
+        let mut result = None;
+        let mut plies = 0;
+        let mut current = self.root;
+        while let Some((_, son, move_data)) = self.nodes[current].sons.first() {
+            if move_data.player == player {
+                result = Some(plies);
             }
-            
-            if ui.button("Delete Variation").clicked() {
-                ew_dv.write(DeleteVariationEvent::new(current));
-            }
-            
-            if ui.button("Copy PGN").clicked() {
-                ui.ctx().copy_text(self.pgn(current));
-            }
-        });
+            current = *son;
+            plies += 1;
+        }
+        result
     }
+}
 
-    fn get_text_width(s: &String, f: &Fonts, font_id: &FontId) -> f32 {
-        let mut res: f32 = 0.0;
-        for c in s.chars() {
-            res += f.glyph_width(font_id, c);
-        }
-        res
+// PGN 中 %clk 命令的时间格式 h:mm:ss，不足一秒的部分保留一位小数
+fn pgn_clock(ms: u32) -> String {
+    let seconds = ms / 1000;
+    let tenths = ms % 1000 / 100;
+    let hms = format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if tenths == 0 { hms } else { format!("{}.{}", hms, tenths) }
+}
+
+// 从注释中取出 [%clk h:mm:ss]，返回时间和剩下的注释
+fn extract_clock(comment: &str) -> (Option<u32>, String) {
+    let Some(start) = comment.find("[%clk ") else {
+        return (None, comment.to_string());
+    };
+    let Some(len) = comment[start..].find(']') else {
+        return (None, comment.to_string());
+    };
+    let value = comment[start + 6..start + len].trim();
+    let mut ms: f64 = 0.0;
+    for part in value.split(':') {
+        let Ok(x) = part.parse::<f64>() else {
+            return (None, comment.to_string());
+        };
+        ms = ms * 60.0 + x;
     }
+    let rest = format!("{} {}", &comment[..start], &comment[start + len + 1..]);
+    (Some((ms * 1000.0).round() as u32), rest.trim().to_string())
+}
 
-    fn show_labels_horizontal(
-        &mut self, 
-        ui: &mut egui::Ui, 
-        prefix: String, 
-        labels: Vec<(String, usize)>,
-        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
-        ew_dv: &mut EventWriter<DeleteVariationEvent>,
-    ) {
-        let font_id = egui::FontId::default();
-        let mono_font = FontId::monospace(14.0);
-
-        ui.horizontal(|ui| {
-            ui.label(RichText::new(prefix.clone()).font(mono_font.clone()));
-            for (_, (s, idx)) in labels.iter().enumerate() {
-                let new_width = ui.fonts(|f| Self::get_text_width(s, f, &font_id));
-                let response = ui.allocate_response(
-                    egui::Vec2::new(
-                        new_width,
-                        ui.text_style_height(&egui::TextStyle::Body),
-                    ),
-                    egui::Sense::click(), 
-                );
-                if *idx == self.focus {
-                    let rect = response.rect;
-                    // 使用默认悬停颜色
-                    ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
-                }
-                if response.hovered() {
-                    let rect = response.rect;
-                    // 使用默认悬停颜色
-                    ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
-                }
-                ui.painter().text(
-                    response.rect.left_center(),
-                    egui::Align2::LEFT_CENTER,
-                    s,
-                    font_id.clone(),
-                    ui.visuals().text_color(),
-                );
-                if response.clicked() {
-                    ew_mtn.write(MoveToNodeEvent::new(*idx));
-                }
-                self.show_context_menu(*idx, &response, ew_dv);
-            }
-        });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hequn::general::HequnBoard;
+
+    // 导出再读入后再次导出的结果不变
+    fn round_trip(tree: &GameTree<HequnBoard>) -> GameTree<HequnBoard> {
+        let pgn = tree.to_pgn();
+        let read = GameTree::<HequnBoard>::from_pgn(pgn.clone()).unwrap();
+        assert_eq!(read.to_pgn(), pgn);
+        assert_eq!(read.node_count(), tree.node_count());
+        read
     }
 
-    // 展示一些步，自动换行，换行前使用 header_prefix 作为前缀，换行后使用 prefix 作为前缀
-    fn show_labels(
-        &mut self, 
-        ui: &mut egui::Ui, 
-        header_prefix: String, 
-        prefix: String, 
-        labels: Vec<(String, usize)>,
-        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
-        ew_dv: &mut EventWriter<DeleteVariationEvent>,
-    ) {
-        let font_id = egui::FontId::default();
-        let mono_font = FontId::monospace(14.0);
-        let initial_width = ui.available_width();
-        let mut width = initial_width;
-        width -= ui.fonts(|f| Self::get_text_width(&header_prefix, f, &mono_font));
-        let mut last = 0;
-        let mut first_line = true;
-        for (idx, (s, _)) in labels.iter().enumerate() {
-            let new_width = ui.fonts(|f| Self::get_text_width(s, f, &font_id));
-            if width < new_width + ui.spacing().item_spacing.x {
-                self.show_labels_horizontal(
-                    ui, 
-                    if first_line { header_prefix.clone() } else { prefix.clone() }, 
-                    Vec::from(&labels[last..idx]), 
-                    ew_mtn,
-                    ew_dv,
-                );
-                last = idx;
-                first_line = false;
-                width = initial_width;
-                width -= ui.fonts(|f| Self::get_text_width(&prefix, f, &mono_font));
-            }
-            width -= new_width + ui.spacing().item_spacing.x;
+    fn son(tree: &GameTree<HequnBoard>, node: usize, index: usize) -> usize {
+        tree.nodes[node].sons[index].1
+    }
+
+    #[test]
+    fn nested_variations_comments_and_glyphs() {
+        let pgn = "{a short game} 1.d4! {center} e5 (1...e6?? {bad} 2.f5 (2.f6 $5 g7) g6) (1...c3) 2.d5 $6 h8 *";
+        let tree = round_trip(&GameTree::<HequnBoard>::from_pgn(pgn.to_string()).unwrap());
+
+        assert_eq!(tree.nodes[tree.root].comment, "a short game");
+        let d4 = son(&tree, tree.root, 0);
+        assert_eq!(tree.nodes[d4].comment, "center");
+        assert_eq!(tree.nodes[d4].nag, Some(Nag::Good));
+        // 1... 的主线和两个变着
+        assert_eq!(tree.nodes[d4].sons.len(), 3);
+        let e6 = son(&tree, d4, 1);
+        assert_eq!(tree.nodes[e6].comment, "bad");
+        assert_eq!(tree.nodes[e6].nag, Some(Nag::Blunder));
+        // 变着中的变着
+        assert_eq!(tree.nodes[e6].sons.len(), 2);
+        let f6 = son(&tree, e6, 1);
+        assert_eq!(tree.nodes[f6].nag, Some(Nag::Interesting));
+        assert_eq!(tree.nodes[f6].sons.len(), 1);
+        assert!(tree.nodes[son(&tree, d4, 2)].sons.is_empty());
+
+        let e5 = son(&tree, d4, 0);
+        let d5 = son(&tree, e5, 0);
+        assert_eq!(tree.nodes[d5].nag, Some(Nag::Dubious));
+        assert_eq!(tree.mainline_plies(), 4);
+        assert_eq!(tree.result(), "*");
+    }
+
+    #[test]
+    fn escaped_tags_and_result() {
+        let mut tree = GameTree::new(HequnBoard::default());
+        for s in ["d4", "e5"] {
+            let step = tree.board().read_step(s.to_string()).unwrap();
+            assert!(tree.try_move(step));
         }
-        self.show_labels_horizontal(
-            ui, 
-            if first_line { header_prefix.clone() } else { prefix.clone() }, 
-            Vec::from(&labels[last..]), 
-            ew_mtn,
-            ew_dv,
-        );
+        tree.set_tag("Event", String::from(r#"quoted "name" and \ backslash"#));
+        tree.set_result("0-1", "resignation");
+        let pgn = tree.to_pgn();
+        assert!(pgn.contains(r#"[Event "quoted \"name\" and \\ backslash"]"#));
+        // 默认的初始局面不写 FEN
+        assert!(!pgn.contains("[FEN "));
+
+        let read = round_trip(&tree);
+        assert_eq!(read.tag("Event"), tree.tag("Event"));
+        assert_eq!(read.tag("Termination"), Some("resignation"));
+        assert_eq!(read.result(), "0-1");
     }
 
-    fn dfs_branch(
-        &mut self, 
-        current: usize, 
-        mut labels: Vec<(String, usize)>,
-        header_prefix: String,
-        prefix: String,
-        ui: &mut egui::Ui,
-        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
-        ew_dv: &mut EventWriter<DeleteVariationEvent>,
-    ) {
-        let son_num = self.nodes[current].sons.len();
-        if son_num > 1 {
-            self.show_labels(ui, header_prefix.clone(), prefix.clone(), labels, ew_mtn, ew_dv);
-
-            let new_header = format!("{prefix}{PRE1}");
-            let new_pre = format!("{prefix}{PRE3}");
-            let last_header = format!("{prefix}{PRE2}");
-            let last_pre = format!("{prefix}{PRE4}");
-
-            for i in 0..son_num {
-                let (_step, son, move_data) = &self.nodes[current].sons[i];
-                let son = *son;
-                let san = match move_data.player {
-                    PlayerOrder::First => format!("{}.{}", move_data.ply, move_data.san),
-                    PlayerOrder::Second => format!("{}...{}", move_data.ply, move_data.san),
-                };
-                
-                self.dfs_branch(
-                    son,
-                    vec![(san, son)],
-                    if i == son_num - 1 { last_header.clone() } else { new_header.clone() },
-                    if i == son_num - 1 { last_pre.clone() } else { new_pre.clone() },
-                    ui,
-                    ew_mtn,
-                    ew_dv,
-                );
-            }
-        } else if son_num == 1 {
-            let (_step, son, move_data) = &self.nodes[current].sons[0];
-            let son = *son;
-            let san = match move_data.player {
-                PlayerOrder::First => format!("{}.{}", move_data.ply, move_data.san),
-                PlayerOrder::Second => format!("{}...{}", move_data.ply, move_data.san),
-            };
-            self.dfs_branch(
-                son, 
-                {
-                    labels.push((san, son));
-                    labels
-                },
-                header_prefix, 
-                prefix, 
-                ui, 
-                ew_mtn,
-                ew_dv);
-        } else {
-            self.show_labels(ui, header_prefix, prefix, labels, ew_mtn, ew_dv);
+    #[test]
+    fn fen_root_and_swap_steps() {
+        let initial = HequnBoard::swap_opening();
+        let mut tree = GameTree::new(initial.clone());
+        for s in ["a1", "b2", "c3", "white", "d4", "pass", "pass"] {
+            let step = tree.board().read_step(s.to_string()).unwrap();
+            assert!(tree.try_move(step));
         }
+        assert!(tree.board().end_game());
+        let pgn = tree.to_pgn();
+        assert!(pgn.contains(&format!("[FEN \"{}\"]", initial.write_fen())));
+        assert!(pgn.contains("white") && pgn.contains("pass"));
+
+        let read = round_trip(&tree);
+        assert_eq!(read.nodes[read.root].board.write_fen(), initial.write_fen());
+        assert_eq!(read.mainline_plies(), 7);
+        assert_eq!(read.mainline_board().write_fen(), tree.mainline_board().write_fen());
+        assert_eq!(read.result(), tree.result());
+        assert_ne!(read.result(), "*");
     }
 
-    fn dfs_mainline(
-        &mut self, 
-        current: usize,
-        ui: &mut egui::Ui,
-        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
-        ew_dv: &mut EventWriter<DeleteVariationEvent>,
-    ) {
-        let son_num = self.nodes[current].sons.len();
-        let total_width = ui.available_width();
-
-        if son_num >= 1 {
-            // 有支线时先展示支线
-            for i in 1..son_num {
-                let (_step, son, move_data) = &self.nodes[current].sons[i];
-                let son = *son;
-                let san = match move_data.player {
-                    PlayerOrder::First => format!("{}.{}", move_data.ply, move_data.san),
-                    PlayerOrder::Second => format!("{}...{}", move_data.ply, move_data.san),
-                };
-                
-                self.dfs_branch(
-                    son,
-                    vec![(san, son)],
-                    if i == son_num - 1 { String::from(PRE2) } else { String::from(PRE1) },
-                    if i == son_num - 1 { String::from(PRE4) } else { String::from(PRE3) },
-                    ui,
-                    ew_mtn,
-                    ew_dv,
-                );
-            }
-            let (_step, son, move_data) = self.nodes[current].sons[0].clone();
-            match move_data.player {
-                PlayerOrder::First => {
-                    ui.horizontal(|ui| {
-                        ui.add_sized([total_width * 0.15, 0.0], egui::Label::new(move_data.ply.to_string()));
-                        let response = ui.add_sized(
-                            [total_width * 0.40, 0.0],
-                            egui::Label::new(move_data.san.clone()).sense(Sense::click()),
-                        );
-                        // if response.secondary_clicked() {
-                        //     self.context_menu = Some(son);
-                        // }
-                        if son == self.focus {
-                            let rect = response.rect;
-                            // 使用默认悬停颜色
-                            ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
-                        }
-                        if response.hovered() {
-                            let rect = response.rect;
-                            // 使用默认悬停颜色
-                            ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
-                        }
-                        // 如果文字被颜色覆盖，重新绘制文字
-                        if son == self.focus || response.hovered() {
-                            let rect = response.rect;
-                            ui.painter().text(
-                                rect.center(),
-                                Align2::CENTER_CENTER,
-                                move_data.san.clone(),
-                                egui::FontId::default(),
-                                ui.visuals().text_color(),
-                            );
-                        }
-                        if response.clicked() {
-                            ew_mtn.write(MoveToNodeEvent::new(son));
-                        }
-                        self.show_context_menu(son, &response, ew_dv);
-                        ui.add_sized([total_width * 0.40, 0.0], egui::Label::new("..."));
-                    });
-                },
-                PlayerOrder::Second => {
-                    ui.horizontal(|ui| {
-                        ui.add_sized([total_width * 0.15, 0.0], egui::Label::new(move_data.ply.to_string()));
-                        ui.add_sized([total_width * 0.40, 0.0], egui::Label::new("..."));
-                        let response = ui.add_sized(
-                            [total_width * 0.40, 0.0],
-                            egui::Label::new(move_data.san.clone()).sense(Sense::click()),
-                        );
-                        // if response.secondary_clicked() {
-                        //     self.context_menu = Some(son);
-                        // }
-                        if son == self.focus {
-                            let rect = response.rect;
-                            // 使用默认悬停颜色
-                            ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
-                        }
-                        if response.hovered() {
-                            let rect = response.rect;
-                            // 使用默认悬停颜色
-                            ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
-                        }
-                        // 如果文字被颜色覆盖，重新绘制文字
-                        if son == self.focus || response.hovered() {
-                            let rect = response.rect;
-                            ui.painter().text(
-                                rect.center(),
-                                Align2::CENTER_CENTER,
-                                move_data.san.clone(),
-                                egui::FontId::default(),
-                                ui.visuals().text_color(),
-                            );
-                        }
-                        if response.clicked() {
-                            ew_mtn.write(MoveToNodeEvent::new(son));
-                        }
-                        self.show_context_menu(son, &response, ew_dv);
-                    });
-                },
-            }
-            self.dfs_mainline(son, ui, ew_mtn, ew_dv);
+    // 棋钟的时间写在 %clk 注释中，聊天记录只保存在游戏树文本中
+    #[test]
+    fn clocks_survive_and_chat_is_dropped() {
+        let mut tree = GameTree::new(HequnBoard::default());
+        for (s, ms) in [("d4", 299_500), ("e5", 298_000)] {
+            let step = tree.board().read_step(s.to_string()).unwrap();
+            assert!(tree.try_move(step));
+            let focus = tree.focus();
+            tree.set_clock(focus, ms);
         }
+        tree.add_chat(ChatLine { time: 0, sender: String::from("a"), text: String::from("hi") });
+        let pgn = tree.to_pgn();
+        assert!(pgn.contains("[%clk 0:04:59.5]"));
+
+        let read = round_trip(&tree);
+        assert_eq!(read.clocks_at(read.mainline_end()), (Some(299_500), Some(298_000)));
+        assert!(read.chat().is_empty());
+        let text = GameTree::<HequnBoard>::from_string(tree.to_string()).unwrap();
+        assert_eq!(text.chat().len(), 1);
     }
 
-    pub fn display_egui(
-        &mut self, 
-        ui: &mut egui::Ui,
-        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
-        ew_dv: &mut EventWriter<DeleteVariationEvent>,
-    ) {
-        self.dfs_mainline(self.root, ui, ew_mtn, ew_dv);
+    // 超时、认输等在棋盘之外结束的对局读入后仍然是结束的
+    #[test]
+    fn off_board_result_survives_reload() {
+        let mut tree = GameTree::new(HequnBoard::default());
+        let step = tree.board().read_step(String::from("d4")).unwrap();
+        assert!(tree.try_move(step));
+        let text = GameTree::<HequnBoard>::from_string(tree.to_string()).unwrap();
+        assert!(!round_trip(&tree).is_over() && !text.is_over());
+
+        tree.set_result("1-0", "time forfeit");
+        let text = GameTree::<HequnBoard>::from_string(tree.to_string()).unwrap();
+        assert!(round_trip(&tree).is_over() && text.is_over());
+        assert_eq!(text.result(), "1-0");
     }
-}
 
-const PRE1: &str = "├─";
-const PRE2: &str = "└─";
-const PRE3: &str = "| ";
-const PRE4: &str = "  ";
\ No newline at end of file
+    #[test]
+    fn take_back_keeps_other_variations() {
+        let mut tree = GameTree::new(HequnBoard::default());
+        for s in ["d4", "e5", "f6"] {
+            let step = tree.board().read_step(s.to_string()).unwrap();
+            assert!(tree.try_move(step));
+        }
+        // 在第二步加入变着 c3
+        let d4 = son(&tree, tree.root, 0);
+        tree.focus = d4;
+        let step = tree.board().read_step(String::from("c3")).unwrap();
+        assert!(tree.try_move(step));
+        assert_eq!(tree.node_count(), 5);
+
+        assert!(tree.take_back(1));
+        assert_eq!(tree.focus(), son(&tree, tree.root, 0));
+        assert_eq!(tree.node_count(), 3);
+        let d4 = son(&tree, tree.root, 0);
+        assert_eq!(tree.nodes[d4].sons.len(), 1);
+        assert_eq!(tree.nodes[d4].sons[0].2.san, "c3");
+        assert!(!tree.take_back(2));
+    }
+}
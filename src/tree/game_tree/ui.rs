@@ -0,0 +1,439 @@
+// 游戏树在界面中的显示，以及通过事件跳转到节点和删除变着
+use bevy::prelude::*;
+use bevy_egui::egui::{self, text::Fonts, Align2, FontId, RichText, Sense};
+use crate::{general::*, tree::{annotation::Nag, game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}}};
+
+use super::{GameTree, MoveData};
+
+const PRE1: &str = "├─";
+const PRE2: &str = "└─";
+const PRE3: &str = "| ";
+const PRE4: &str = "  ";
+
+impl<B: Board> GameTree<B> {
+    pub fn move_to_start(&self, ew_mtn: &mut EventWriter<MoveToNodeEvent>) {
+        let mut target = self.focus;
+        while let Some(parent) = self.nodes[target].parent {
+            target = parent;
+        }
+        ew_mtn.write(MoveToNodeEvent { node_id: target });
+    }
+
+    pub fn move_backward(&self, ew_mtn: &mut EventWriter<MoveToNodeEvent>) {
+        let mut target = self.focus;
+        if let Some(parent) = self.nodes[target].parent {
+            target = parent;
+        }
+        ew_mtn.write(MoveToNodeEvent { node_id: target });
+    }
+
+    pub fn move_forward(&self, ew_mtn: &mut EventWriter<MoveToNodeEvent>) {
+        let mut target = self.focus;
+        if !self.nodes[target].sons.is_empty() {
+            target = self.nodes[target].sons[0].1;
+        }
+        ew_mtn.write(MoveToNodeEvent { node_id: target });
+    }
+
+    pub fn move_to_end(&self, ew_mtn: &mut EventWriter<MoveToNodeEvent>) {
+        let mut target = self.focus;
+        while !self.nodes[target].sons.is_empty() {
+            target = self.nodes[target].sons[0].1;
+        }
+        ew_mtn.write(MoveToNodeEvent { node_id: target });
+    }
+
+    pub fn handle_delete_variation(&mut self, e: &DeleteVariationEvent, ew: &mut EventWriter<UpdateBoard<B>>) {
+        if e.node_id == self.root {
+            warn!("Try to delete game tree root");
+            return;
+        }
+        // 如果原来的焦点被删除，将焦点移到根并更新棋盘。
+        if !self.delete_node(e.node_id) {
+            self.focus = 0;
+            ew.write(UpdateBoard::new(self.board()));
+        }
+    }
+
+    pub fn handle_move_to_node(&mut self, e: &MoveToNodeEvent, ew: &mut EventWriter<UpdateBoard<B>>) {
+        let node_id = e.node_id;
+        if node_id >= self.nodes.len() {
+            warn!("game_tree: try to move to a node that does not exist.");
+            return;
+        }
+        self.focus = node_id;
+        ew.write(UpdateBoard::new(self.board()));
+    }
+
+    fn show_context_menu(
+        &mut self,
+        current: usize,
+        response: &egui::Response,
+        ew_dv: &mut EventWriter<DeleteVariationEvent>,
+    ) {
+        egui::Popup::context_menu(response)
+            .show(|ui| {
+            ui.set_min_width(120.0);
+            
+            if ui.button("Promote Variation").clicked() {
+                if let Some(parent) = self.nodes[current].parent {
+                    let mut pos = 0;
+                    for (idx, (_, son_id, _)) in self.nodes[parent].sons.iter().enumerate() {
+                        if *son_id == current {
+                            pos = idx;
+                        }
+                    }
+                    if pos != 0 {
+                        self.nodes[parent].sons.swap(0, pos);
+                    }
+                }
+            }
+            
+            if ui.button("Set as mainline").clicked() {
+                let mut cur = current;
+                while cur != self.root {
+                    if let Some(parent) = self.nodes[cur].parent {
+                        let mut pos = 0;
+                        for (idx, (_, son_id, _)) in self.nodes[parent].sons.iter().enumerate() {
+                            if *son_id == cur {
+                                pos = idx;
+                            }
+                        }
+                        if pos != 0 {
+                            self.nodes[parent].sons.swap(0, pos);
+                        }
+                        cur = parent;
+                    } else {
+                        unreachable!()
+                    }
+                }
+            }
+            
+            if ui.button("Delete Variation").clicked() {
+                ew_dv.write(DeleteVariationEvent::new(current));
+            }
+
+            ui.menu_button("Glyph", |ui| {
+                for nag in Nag::ALL {
+                    if ui.selectable_label(self.nodes[current].nag == Some(nag), nag.symbol()).clicked() {
+                        self.nodes[current].nag = Some(nag);
+                    }
+                }
+                if ui.button("None").clicked() {
+                    self.nodes[current].nag = None;
+                }
+            });
+
+            if ui.button("Edit Comment").clicked() {
+                self.editing_comment = Some(current);
+            }
+            
+            if ui.button("Copy PGN").clicked() {
+                ui.ctx().copy_text(self.pgn(current));
+            }
+        });
+    }
+
+    // 支线中的着法，评注符号和注释都显示在着法后面
+    fn branch_label(&self, son: usize, move_data: &MoveData) -> String {
+        let node = &self.nodes[son];
+        let glyph = node.nag.map_or("", |nag| nag.symbol());
+        let mut label = match move_data.player {
+            PlayerOrder::First => format!("{}.{}{}", move_data.ply, move_data.san, glyph),
+            PlayerOrder::Second => format!("{}...{}{}", move_data.ply, move_data.san, glyph),
+        };
+        if !node.comment.is_empty() {
+            label = format!("{} {{{}}}", label, node.comment.replace('\n', " "));
+        }
+        label
+    }
+
+    fn get_text_width(s: &String, f: &Fonts, font_id: &FontId) -> f32 {
+        let mut res: f32 = 0.0;
+        for c in s.chars() {
+            res += f.glyph_width(font_id, c);
+        }
+        res
+    }
+
+    fn show_labels_horizontal(
+        &mut self, 
+        ui: &mut egui::Ui, 
+        prefix: String, 
+        labels: Vec<(String, usize)>,
+        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
+        ew_dv: &mut EventWriter<DeleteVariationEvent>,
+    ) {
+        let font_id = egui::FontId::default();
+        let mono_font = FontId::monospace(14.0);
+
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(prefix.clone()).font(mono_font.clone()));
+            for (_, (s, idx)) in labels.iter().enumerate() {
+                let new_width = ui.fonts(|f| Self::get_text_width(s, f, &font_id));
+                let response = ui.allocate_response(
+                    egui::Vec2::new(
+                        new_width,
+                        ui.text_style_height(&egui::TextStyle::Body),
+                    ),
+                    egui::Sense::click(), 
+                );
+                if *idx == self.focus {
+                    let rect = response.rect;
+                    // 使用默认悬停颜色
+                    ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
+                }
+                if response.hovered() {
+                    let rect = response.rect;
+                    // 使用默认悬停颜色
+                    ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
+                }
+                ui.painter().text(
+                    response.rect.left_center(),
+                    egui::Align2::LEFT_CENTER,
+                    s,
+                    font_id.clone(),
+                    ui.visuals().text_color(),
+                );
+                if response.clicked() {
+                    ew_mtn.write(MoveToNodeEvent::new(*idx));
+                }
+                self.show_context_menu(*idx, &response, ew_dv);
+            }
+        });
+    }
+
+    // 展示一些步，自动换行，换行前使用 header_prefix 作为前缀，换行后使用 prefix 作为前缀
+    fn show_labels(
+        &mut self, 
+        ui: &mut egui::Ui, 
+        header_prefix: String, 
+        prefix: String, 
+        labels: Vec<(String, usize)>,
+        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
+        ew_dv: &mut EventWriter<DeleteVariationEvent>,
+    ) {
+        let font_id = egui::FontId::default();
+        let mono_font = FontId::monospace(14.0);
+        let initial_width = ui.available_width();
+        let mut width = initial_width;
+        width -= ui.fonts(|f| Self::get_text_width(&header_prefix, f, &mono_font));
+        let mut last = 0;
+        let mut first_line = true;
+        for (idx, (s, _)) in labels.iter().enumerate() {
+            let new_width = ui.fonts(|f| Self::get_text_width(s, f, &font_id));
+            if width < new_width + ui.spacing().item_spacing.x {
+                self.show_labels_horizontal(
+                    ui, 
+                    if first_line { header_prefix.clone() } else { prefix.clone() }, 
+                    Vec::from(&labels[last..idx]), 
+                    ew_mtn,
+                    ew_dv,
+                );
+                last = idx;
+                first_line = false;
+                width = initial_width;
+                width -= ui.fonts(|f| Self::get_text_width(&prefix, f, &mono_font));
+            }
+            width -= new_width + ui.spacing().item_spacing.x;
+        }
+        self.show_labels_horizontal(
+            ui, 
+            if first_line { header_prefix.clone() } else { prefix.clone() }, 
+            Vec::from(&labels[last..]), 
+            ew_mtn,
+            ew_dv,
+        );
+    }
+
+    fn dfs_branch(
+        &mut self, 
+        current: usize, 
+        mut labels: Vec<(String, usize)>,
+        header_prefix: String,
+        prefix: String,
+        ui: &mut egui::Ui,
+        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
+        ew_dv: &mut EventWriter<DeleteVariationEvent>,
+    ) {
+        let son_num = self.nodes[current].sons.len();
+        if son_num > 1 {
+            self.show_labels(ui, header_prefix.clone(), prefix.clone(), labels, ew_mtn, ew_dv);
+
+            let new_header = format!("{prefix}{PRE1}");
+            let new_pre = format!("{prefix}{PRE3}");
+            let last_header = format!("{prefix}{PRE2}");
+            let last_pre = format!("{prefix}{PRE4}");
+
+            for i in 0..son_num {
+                let (_step, son, move_data) = &self.nodes[current].sons[i];
+                let son = *son;
+                let san = self.branch_label(son, move_data);
+                
+                self.dfs_branch(
+                    son,
+                    vec![(san, son)],
+                    if i == son_num - 1 { last_header.clone() } else { new_header.clone() },
+                    if i == son_num - 1 { last_pre.clone() } else { new_pre.clone() },
+                    ui,
+                    ew_mtn,
+                    ew_dv,
+                );
+            }
+        } else if son_num == 1 {
+            let (_step, son, move_data) = &self.nodes[current].sons[0];
+            let son = *son;
+            let san = self.branch_label(son, move_data);
+            self.dfs_branch(
+                son, 
+                {
+                    labels.push((san, son));
+                    labels
+                },
+                header_prefix, 
+                prefix, 
+                ui, 
+                ew_mtn,
+                ew_dv);
+        } else {
+            self.show_labels(ui, header_prefix, prefix, labels, ew_mtn, ew_dv);
+        }
+    }
+
+    fn dfs_mainline(
+        &mut self, 
+        current: usize,
+        ui: &mut egui::Ui,
+        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
+        ew_dv: &mut EventWriter<DeleteVariationEvent>,
+    ) {
+        let son_num = self.nodes[current].sons.len();
+        let total_width = ui.available_width();
+
+        if son_num >= 1 {
+            // 有支线时先展示支线
+            for i in 1..son_num {
+                let (_step, son, move_data) = &self.nodes[current].sons[i];
+                let son = *son;
+                let san = self.branch_label(son, move_data);
+                
+                self.dfs_branch(
+                    son,
+                    vec![(san, son)],
+                    if i == son_num - 1 { String::from(PRE2) } else { String::from(PRE1) },
+                    if i == son_num - 1 { String::from(PRE4) } else { String::from(PRE3) },
+                    ui,
+                    ew_mtn,
+                    ew_dv,
+                );
+            }
+            let (_step, son, move_data) = self.nodes[current].sons[0].clone();
+            let san = format!("{}{}", move_data.san, self.nodes[son].nag.map_or("", |nag| nag.symbol()));
+            match move_data.player {
+                PlayerOrder::First => {
+                    ui.horizontal(|ui| {
+                        ui.add_sized([total_width * 0.15, 0.0], egui::Label::new(move_data.ply.to_string()));
+                        let response = ui.add_sized(
+                            [total_width * 0.40, 0.0],
+                            egui::Label::new(san.clone()).sense(Sense::click()),
+                        );
+                        // if response.secondary_clicked() {
+                        //     self.context_menu = Some(son);
+                        // }
+                        if son == self.focus {
+                            let rect = response.rect;
+                            // 使用默认悬停颜色
+                            ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
+                        }
+                        if response.hovered() {
+                            let rect = response.rect;
+                            // 使用默认悬停颜色
+                            ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
+                        }
+                        // 如果文字被颜色覆盖，重新绘制文字
+                        if son == self.focus || response.hovered() {
+                            let rect = response.rect;
+                            ui.painter().text(
+                                rect.center(),
+                                Align2::CENTER_CENTER,
+                                san.clone(),
+                                egui::FontId::default(),
+                                ui.visuals().text_color(),
+                            );
+                        }
+                        if response.clicked() {
+                            ew_mtn.write(MoveToNodeEvent::new(son));
+                        }
+                        self.show_context_menu(son, &response, ew_dv);
+                        ui.add_sized([total_width * 0.40, 0.0], egui::Label::new("..."));
+                    });
+                },
+                PlayerOrder::Second => {
+                    ui.horizontal(|ui| {
+                        ui.add_sized([total_width * 0.15, 0.0], egui::Label::new(move_data.ply.to_string()));
+                        ui.add_sized([total_width * 0.40, 0.0], egui::Label::new("..."));
+                        let response = ui.add_sized(
+                            [total_width * 0.40, 0.0],
+                            egui::Label::new(san.clone()).sense(Sense::click()),
+                        );
+                        // if response.secondary_clicked() {
+                        //     self.context_menu = Some(son);
+                        // }
+                        if son == self.focus {
+                            let rect = response.rect;
+                            // 使用默认悬停颜色
+                            ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
+                        }
+                        if response.hovered() {
+                            let rect = response.rect;
+                            // 使用默认悬停颜色
+                            ui.painter().rect_filled(rect, 2.0, ui.visuals().widgets.hovered.bg_fill);
+                        }
+                        // 如果文字被颜色覆盖，重新绘制文字
+                        if son == self.focus || response.hovered() {
+                            let rect = response.rect;
+                            ui.painter().text(
+                                rect.center(),
+                                Align2::CENTER_CENTER,
+                                san.clone(),
+                                egui::FontId::default(),
+                                ui.visuals().text_color(),
+                            );
+                        }
+                        if response.clicked() {
+                            ew_mtn.write(MoveToNodeEvent::new(son));
+                        }
+                        self.show_context_menu(son, &response, ew_dv);
+                    });
+                },
+            }
+            if !self.nodes[son].comment.is_empty() {
+                ui.label(RichText::new(self.nodes[son].comment.clone()).italics().weak());
+            }
+            self.dfs_mainline(son, ui, ew_mtn, ew_dv);
+        }
+    }
+
+    pub fn display_egui(
+        &mut self, 
+        ui: &mut egui::Ui,
+        ew_mtn: &mut EventWriter<MoveToNodeEvent>,
+        ew_dv: &mut EventWriter<DeleteVariationEvent>,
+    ) {
+        if let Some(node) = self.editing_comment.filter(|node| *node < self.nodes.len()) {
+            ui.group(|ui| {
+                ui.label(if node == self.root { "Game comment:" } else { "Comment:" });
+                ui.text_edit_multiline(&mut self.nodes[node].comment);
+                if ui.button("Done").clicked() {
+                    self.editing_comment = None;
+                }
+            });
+        } else if ui.small_button("Edit game comment").clicked() {
+            self.editing_comment = Some(self.root);
+        }
+        if !self.nodes[self.root].comment.is_empty() {
+            ui.label(RichText::new(self.nodes[self.root].comment.clone()).italics().weak());
+        }
+        self.dfs_mainline(self.root, ui, ew_mtn, ew_dv);
+    }
+}
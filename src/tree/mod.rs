@@ -1,5 +1,8 @@
 /// 游戏树相关部分
 
 pub mod game_tree;
+#[cfg(feature = "gui")]
 pub mod game_tree_event;
-pub mod pgn;
\ No newline at end of file
+pub mod pgn;
+pub mod annotation;
+pub mod file;
\ No newline at end of file
@@ -1,18 +1,194 @@
-pub(super) fn parse_pgn(pgn: &str) -> Vec<String> {
-    pgn.split_whitespace()
-        .filter_map(|token| {
-            // 检查是否是 "{ply}.{step}" 格式
-            if let Some(dot_pos) = token.find('.') {
-                // 确保点号不在开头或结尾
-                if dot_pos > 0 && dot_pos < token.len() - 1 {
-                    // 返回点号后面的部分
-                    return Some(token[dot_pos + 1..].to_string());
-                }
-            } else {
-                return Some(token.to_string());
+use crate::{general::XinqiParseError, tree::annotation::Nag};
+
+pub(super) enum PgnToken {
+    Tag(String, String),
+    Move(String),
+    Nag(Nag),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Result(String),
+}
+
+// token 在 PGN 文本中的位置，行和列都从 1 开始
+#[derive(Clone, Copy)]
+pub(super) struct PgnPos {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl PgnPos {
+    pub fn error(self, field: &'static str, reason: impl Into<String>) -> XinqiParseError {
+        XinqiParseError::new(field, reason).at(self.line, self.column)
+    }
+
+    // 标签值或着法本身的错误，位置换成 token 在 PGN 中的位置，原来的列号放在说明中
+    pub fn locate(self, e: XinqiParseError, within: &str) -> XinqiParseError {
+        let reason = match e.column {
+            Some(column) => format!("{} (column {} of the {})", e.reason, column, within),
+            None => e.reason,
+        };
+        XinqiParseError::new(e.field, reason).at(self.line, self.column)
+    }
+}
+
+pub(super) const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+struct Lexer {
+    chars: Vec<char>,
+    index: usize,
+    pos: PgnPos,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    // 读到 end 为止，不包括 end 本身
+    fn take_until(&mut self, end: char) -> Option<String> {
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                c if c == end => return Some(s),
+                c => s.push(c),
+            }
+        }
+    }
+
+    // [Name "value"]，value 中可以用 \" 和 \\ 转义
+    fn tag(&mut self, start: PgnPos) -> Result<PgnToken, XinqiParseError> {
+        self.skip_whitespace();
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+            name.push(c);
+            self.next();
+        }
+        self.skip_whitespace();
+        if name.is_empty() || self.next() != Some('"') {
+            return Err(start.error("tag", "malformed tag pair"));
+        }
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some('\\') => value.extend(self.next()),
+                Some('"') => break,
+                Some(c) => value.push(c),
+                None => return Err(start.error("tag", "unterminated tag value")),
             }
-            
-            None
-        })
-        .collect()
-}
\ No newline at end of file
+        }
+        self.skip_whitespace();
+        if self.next() != Some(']') {
+            return Err(start.error("tag", "missing ']' after tag pair"));
+        }
+        Ok(PgnToken::Tag(name, value))
+    }
+}
+
+// 把 PGN 拆成标签、着法、评注符号、{...} 和 ; 注释、变着的括号以及结果。
+// 着法前的 "{ply}." 会被去掉，着法后的 !、? 等符号单独作为评注符号
+pub(super) fn parse_pgn(pgn: &str) -> Result<Vec<(PgnToken, PgnPos)>, XinqiParseError> {
+    let mut lexer = Lexer {
+        chars: pgn.chars().collect(),
+        index: 0,
+        pos: PgnPos { line: 1, column: 1 },
+    };
+    let mut tokens = Vec::new();
+    while let Some(c) = lexer.peek() {
+        let start = lexer.pos;
+        if c.is_whitespace() {
+            lexer.next();
+            continue;
+        }
+        match c {
+            '[' => {
+                lexer.next();
+                tokens.push((lexer.tag(start)?, start));
+            },
+            '{' => {
+                lexer.next();
+                let comment = lexer.take_until('}').ok_or_else(|| start.error("comment", "unterminated comment"))?;
+                tokens.push((PgnToken::Comment(comment.trim().to_string()), start));
+            },
+            ';' => {
+                lexer.next();
+                let mut comment = String::new();
+                while let Some(c) = lexer.peek().filter(|c| *c != '\n') {
+                    comment.push(c);
+                    lexer.next();
+                }
+                tokens.push((PgnToken::Comment(comment.trim().to_string()), start));
+            },
+            '(' => {
+                lexer.next();
+                tokens.push((PgnToken::VariationStart, start));
+            },
+            ')' => {
+                lexer.next();
+                tokens.push((PgnToken::VariationEnd, start));
+            },
+            _ => {
+                let mut token = String::new();
+                while let Some(c) = lexer.peek() {
+                    if c.is_whitespace() || "{}()[];".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    lexer.next();
+                }
+                parse_token(&token, start, &mut tokens)?;
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_token(token: &str, pos: PgnPos, tokens: &mut Vec<(PgnToken, PgnPos)>) -> Result<(), XinqiParseError> {
+    if RESULTS.contains(&token) {
+        tokens.push((PgnToken::Result(token.to_string()), pos));
+        return Ok(());
+    }
+
+    if let Some(code) = token.strip_prefix('$') {
+        let nag = code.parse().ok().and_then(Nag::from_code).ok_or_else(|| pos.error("NAG", format!("unknown NAG \"{}\"", token)))?;
+        tokens.push((PgnToken::Nag(nag), pos));
+        return Ok(());
+    }
+
+    // 检查是否是 "{ply}.{step}" 格式
+    let step = match token.rfind('.') {
+        // 只有回合数，没有着法
+        Some(dot_pos) if dot_pos == token.len() - 1 => return Ok(()),
+        Some(dot_pos) => &token[dot_pos + 1..],
+        None => token,
+    };
+
+    let san = step.trim_end_matches(['!', '?']);
+    if !san.is_empty() {
+        tokens.push((PgnToken::Move(san.to_string()), pos));
+    }
+    let glyph = &step[san.len()..];
+    if !glyph.is_empty() {
+        let nag = Nag::from_symbol(glyph).ok_or_else(|| pos.error("glyph", format!("unknown glyph \"{}\"", glyph)))?;
+        tokens.push((PgnToken::Nag(nag), pos));
+    }
+    Ok(())
+}
@@ -2,12 +2,20 @@ use bevy::{prelude::*, render::view::RenderLayers};
 use bevy_egui::{EguiGlobalSettings, EguiPlugin, EguiPrimaryContextPass, PrimaryEguiContext};
 
 use crate::{hequn::game::HequnGame, tree::game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}, ui::{
-        ui_game_tree::*, ui_hequn::*, ui_menu::*, ui_sl::*, ui_xingxiang::*, ui_zhandi::*
+        ui_ai::*, ui_analysis::*, ui_chat::*, ui_clock::*, ui_file::*, ui_game_tree::*, ui_hequn::*, ui_match::*, ui_menu::*, ui_replay::*, ui_sl::*, ui_sync::*, ui_xingxiang::*, ui_zhandi::*
     }, xingxiang::game::XingxiangGame, zhandi::game::ZhandiGame
 };
 
 pub mod ui_menu;
+pub mod ui_ai;
+pub mod ui_clock;
+pub mod ui_chat;
+pub mod ui_match;
+pub mod ui_analysis;
 pub mod ui_sl;
+pub mod ui_replay;
+pub mod ui_sync;
+pub mod ui_file;
 pub mod ui_game_tree;
 pub mod ui_hequn;
 pub mod ui_zhandi;
@@ -27,7 +35,59 @@ impl Plugin for UiPlugin {
         app.init_resource::<UiSlState::<HequnGame>>();
         app.init_resource::<UiSlState::<ZhandiGame>>();
         app.init_resource::<UiSlState::<XingxiangGame>>();
+        app.insert_resource(FileSettings::load());
+        app.init_resource::<Autosave::<HequnGame>>();
+        app.init_resource::<Autosave::<ZhandiGame>>();
+        app.init_resource::<Autosave::<XingxiangGame>>();
+        app.init_resource::<UiAiState::<HequnGame>>();
+        app.init_resource::<UiAiState::<ZhandiGame>>();
+        app.init_resource::<UiAiState::<XingxiangGame>>();
+        app.init_resource::<UiMatchState>();
+        app.init_resource::<UiReplayState>();
+        app.init_resource::<UiAnalysisState::<HequnGame>>();
+        app.init_resource::<UiAnalysisState::<ZhandiGame>>();
+        app.init_resource::<UiAnalysisState::<XingxiangGame>>();
         app.add_systems(Startup, ui_setup);
+        app.add_systems(
+            Update,
+            (
+                ui_ai_poll::<HequnGame>,
+                ui_ai_poll::<ZhandiGame>,
+                ui_ai_poll::<XingxiangGame>,
+                ai_autoplay::<HequnGame>,
+                ai_autoplay::<ZhandiGame>,
+                ai_autoplay::<XingxiangGame>,
+                clock_update::<HequnGame>,
+                clock_update::<ZhandiGame>,
+                clock_update::<XingxiangGame>,
+                sync_check::<HequnGame>,
+                sync_check::<ZhandiGame>,
+                sync_check::<XingxiangGame>,
+                spectate::<HequnGame>,
+                spectate::<ZhandiGame>,
+                spectate::<XingxiangGame>,
+                autosave::<HequnGame>,
+                autosave::<ZhandiGame>,
+                autosave::<XingxiangGame>,
+            )
+        );
+        app.add_systems(
+            Update,
+            (
+                record_chat::<HequnGame>,
+                record_chat::<ZhandiGame>,
+                record_chat::<XingxiangGame>,
+                sync_netlog_dir,
+            )
+        );
+        app.add_systems(
+            Last,
+            (
+                autosave_on_exit::<HequnGame>,
+                autosave_on_exit::<ZhandiGame>,
+                autosave_on_exit::<XingxiangGame>,
+            )
+        );
         app.add_systems(
             EguiPrimaryContextPass,
             (
@@ -38,11 +98,22 @@ impl Plugin for UiPlugin {
                 ui_sl::<HequnGame>,
                 ui_sl::<ZhandiGame>,
                 ui_sl::<XingxiangGame>,
+                ui_analysis::<HequnGame>,
+                ui_analysis::<ZhandiGame>,
+                ui_analysis::<XingxiangGame>,
+                ui_clock::<HequnGame>,
+                ui_clock::<ZhandiGame>,
+                ui_clock::<XingxiangGame>,
+                ui_match::<HequnGame>,
+                ui_match::<ZhandiGame>,
+                ui_match::<XingxiangGame>,
+                ui_chat,
                 ui_hequn,
                 ui_zhandi,
                 ui_xingxiang,
             )
         );
+        app.add_systems(EguiPrimaryContextPass, ui_replay);
     }
 }
 
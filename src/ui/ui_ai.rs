@@ -0,0 +1,413 @@
+use std::{marker::PhantomData, sync::Arc};
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bevy_tokio_tasks::TokioTasksRuntime;
+
+use crate::{ai::{mcts::MCTSAI, mctsv2::{MCTSConfig, MCTSv2, MoveSelection}, task::{AITask, AITaskPoll}, Heuristic, SearchLimit, SearchSignal, AI}, engine::client::ExternalEngine, general::{board::*, game::Game as GameTrait, AiEngine, AiSeat, Controller, PlayerOrder, Seats}, net::message::{try_move_and_send, SendRemoteStep}, ui::ui_menu::UiMenuState};
+
+// 每种棋正在后台进行的 AI 搜索
+#[derive(Resource)]
+pub struct UiAiState<G: GameTrait> {
+    task: Option<AITask<G::B>>,
+    searcher: Option<Arc<MCTSv2<G::B>>>, // 在多次搜索之间保留搜索树的 AI
+    engine: Option<Arc<ExternalEngine<G::B>>>, // 外部引擎，进程在多次搜索之间保留
+    error: String, // 上一次搜索失败的原因，出错后不再自动让 AI 走子
+    ponder_after_task: bool,
+    autoplay: bool, // 当前的搜索是替 AI 执掌的一方自动走子
+    pondering: Option<SearchSignal>,
+    pub ponder: bool,
+    pub paused: bool, // 暂停 AI 执掌的一方自动走子
+    pub config: MCTSConfig, // 这种棋的 MCTSv2 参数，"ai play" 和局面分析都使用它
+    _marker: PhantomData<G>,
+}
+
+impl<G: GameTrait> Default for UiAiState<G> {
+    fn default() -> Self {
+        Self {
+            task: None,
+            searcher: None,
+            engine: None,
+            error: String::new(),
+            ponder_after_task: false,
+            autoplay: false,
+            pondering: None,
+            ponder: false,
+            paused: false,
+            config: MCTSConfig::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<G: GameTrait> UiAiState<G> {
+    pub fn is_thinking(&self) -> bool {
+        self.task.is_some()
+    }
+
+    // 网络对局中不能替对方走子，对局超时等结束后不能在主线末端走子
+    fn can_start(&self, game: &mut G) -> bool {
+        let active = game.board().get_active_player();
+        self.task.is_none() && !game.board().end_game() && !game.seats().is_remote(active) && !game.is_over_here()
+    }
+
+    pub fn start<A>(&mut self, runtime: &TokioTasksRuntime, ai: A, game: &mut G, limit: SearchLimit)
+    where
+        A: AI<B = G::B> + Send + 'static,
+    {
+        if !self.can_start(game) {
+            return;
+        }
+        self.stop_pondering();
+        self.ponder_after_task = false;
+        self.autoplay = false;
+        self.error.clear();
+        let node = game.tree().focus();
+        self.task = Some(AITask::spawn(runtime.runtime(), ai, game.board().clone(), node, limit));
+    }
+
+    // 让外部引擎走子，命令改变时重新启动引擎
+    pub fn start_engine(&mut self, runtime: &TokioTasksRuntime, game: &mut G, command: &str, limit: SearchLimit) {
+        if !self.can_start(game) {
+            return;
+        }
+        self.stop_pondering();
+        self.ponder_after_task = false;
+        self.autoplay = false;
+        self.error.clear();
+        if self.engine.as_ref().is_none_or(|engine| engine.command != command) {
+            self.engine = Some(Arc::new(ExternalEngine::new(command.to_string())));
+        }
+        let engine = self.engine.clone().unwrap();
+        let node = game.tree().focus();
+        self.task = Some(AITask::spawn_engine(runtime.runtime(), engine, game.board().clone(), node, limit));
+    }
+
+    // 使用保留搜索树的 AI 进行搜索，走子后可以继续在对手回合思考
+    pub fn start_searcher(
+        &mut self,
+        runtime: &TokioTasksRuntime,
+        game: &mut G,
+        limit: SearchLimit,
+        threads: usize,
+        config: MCTSConfig,
+    )
+    where
+        G::B: Heuristic,
+    {
+        // 线程数或参数改变时重新创建 AI，此时不能复用之前的搜索树
+        if self.searcher.as_ref().is_some_and(|searcher| searcher.threads() != threads || *searcher.config() != config) {
+            self.stop_pondering();
+            self.searcher = None;
+        }
+        let searcher = self.searcher
+            .get_or_insert_with(|| Arc::new(MCTSv2::default().with_config(config).with_threads(threads)))
+            .clone();
+        self.start(runtime, searcher, game, limit);
+        self.ponder_after_task = self.task.is_some();
+    }
+
+    // 让 AI 执掌的一方按座位设置走子
+    fn start_seat(&mut self, runtime: &TokioTasksRuntime, game: &mut G, seat: AiSeat, ui_menu: &UiMenuState)
+    where
+        G::B: Heuristic,
+    {
+        match seat.engine {
+            AiEngine::WeakMCTS => {
+                let seed = self.config.seed;
+                self.start(runtime, MCTSAI::new().with_seed(seed), game, seat.limit);
+            },
+            AiEngine::MCTSv2 => {
+                let config = self.config;
+                self.start_searcher(runtime, game, seat.limit, ui_menu.ai_threads, config);
+            },
+            AiEngine::External => {
+                if ui_menu.engine_command.trim().is_empty() {
+                    self.error = String::from("no external engine command set");
+                } else {
+                    self.start_engine(runtime, game, &ui_menu.engine_command, seat.limit);
+                }
+            },
+        }
+        self.autoplay = self.task.is_some();
+    }
+
+    fn start_pondering(&mut self, runtime: &TokioTasksRuntime, board: G::B) {
+        let Some(searcher) = self.searcher.clone() else {
+            return;
+        };
+        if board.end_game() {
+            return;
+        }
+        let signal = searcher.start_ponder();
+        self.pondering = Some(signal.clone());
+        runtime.runtime().spawn_blocking(move || {
+            searcher.ponder(board, &signal);
+        });
+    }
+
+    fn stop_pondering(&mut self) {
+        if let Some(signal) = self.pondering.take() {
+            signal.stop();
+        }
+    }
+
+    // 显示思考中的状态和取消按钮，以及对手回合思考的状态
+    pub fn show_thinking(&mut self, ui: &mut egui::Ui) {
+        if !self.error.is_empty() {
+            ui.label(format!("AI failed: {}", self.error));
+            if ui.button("Retry").clicked() {
+                self.error.clear();
+            }
+        }
+        if let Some(task) = &self.task {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(format!("Thinking… {} simulations", task.simulations()));
+            });
+            if ui.button("Cancel").clicked() {
+                self.task = None;
+                // 否则 ai_autoplay 会马上重新开始搜索
+                if self.autoplay {
+                    self.paused = true;
+                }
+            }
+        }
+
+        ui.checkbox(&mut self.ponder, "Ponder on opponent's turn");
+        if let Some(signal) = &self.pondering {
+            if signal.is_stopped() {
+                self.pondering = None;
+            } else {
+                ui.label(format!("Pondering… {} simulations", signal.simulations()));
+                if ui.button("Stop pondering").clicked() {
+                    self.stop_pondering();
+                }
+            }
+        }
+        if !self.ponder {
+            self.stop_pondering();
+        }
+    }
+
+    // 设置双方由谁走子，对局中途也可以更换
+    pub fn show_seats(&mut self, ui: &mut egui::Ui, seats: &mut Seats) {
+        egui::CollapsingHeader::new("Players").show(ui, |ui| {
+            seats_ui(ui, seats);
+            if seats.has_ai() {
+                ui.checkbox(&mut self.paused, "Pause AI players");
+            }
+        });
+    }
+
+    // 调整 MCTSv2 的参数，下一次搜索时生效
+    pub fn show_config(&mut self, ui: &mut egui::Ui)
+    where
+        G::B: Heuristic,
+    {
+        let config = &mut self.config;
+        egui::CollapsingHeader::new("MCTS settings").show(ui, |ui| {
+            egui::Grid::new("mcts_config").show(ui, |ui| {
+                ui.label("Exploration");
+                ui.add(egui::DragValue::new(&mut config.exploration).range(0.0..=10.0).speed(0.01));
+                ui.end_row();
+
+                ui.label("Eval weight");
+                ui.add(egui::DragValue::new(&mut config.prior_weight).range(0.0..=1000.0).speed(0.1));
+                ui.end_row();
+
+                ui.label("Eval temperature");
+                ui.horizontal(|ui| {
+                    let mut custom = config.eval_temperature.is_some();
+                    ui.checkbox(&mut custom, "custom");
+                    match (custom, &mut config.eval_temperature) {
+                        (true, Some(t)) => {
+                            ui.add(egui::DragValue::new(t).range(0.1..=1000.0).speed(0.1));
+                        },
+                        (true, None) => config.eval_temperature = Some(G::B::TEMPERATURE),
+                        (false, _) => config.eval_temperature = None,
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Max rollout depth");
+                ui.horizontal(|ui| {
+                    let mut limited = config.max_rollout_depth.is_some();
+                    ui.checkbox(&mut limited, "limit");
+                    match (limited, &mut config.max_rollout_depth) {
+                        (true, Some(depth)) => {
+                            ui.add(egui::DragValue::new(depth).range(0..=1000));
+                        },
+                        (true, None) => config.max_rollout_depth = Some(20),
+                        (false, _) => config.max_rollout_depth = None,
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Final move");
+                ui.horizontal(|ui| {
+                    let mut sampling = match config.move_selection {
+                        MoveSelection::Temperature(t) => t,
+                        _ => 1.0,
+                    };
+                    egui::ComboBox::from_id_salt("move_selection")
+                        .selected_text(match config.move_selection {
+                            MoveSelection::MaxVisits => "Max visits",
+                            MoveSelection::MaxWinRate => "Max win rate",
+                            MoveSelection::Temperature(_) => "Sampling",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut config.move_selection, MoveSelection::MaxVisits, "Max visits");
+                            ui.selectable_value(&mut config.move_selection, MoveSelection::MaxWinRate, "Max win rate");
+                            ui.selectable_value(&mut config.move_selection, MoveSelection::Temperature(sampling), "Sampling");
+                        });
+                    if let MoveSelection::Temperature(_) = config.move_selection {
+                        ui.add(egui::DragValue::new(&mut sampling).range(0.01..=10.0).speed(0.01).prefix("t = "));
+                        config.move_selection = MoveSelection::Temperature(sampling);
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Seed");
+                ui.horizontal(|ui| {
+                    let mut fixed = config.seed.is_some();
+                    ui.checkbox(&mut fixed, "fixed");
+                    match (fixed, &mut config.seed) {
+                        (true, Some(seed)) => {
+                            ui.add(egui::DragValue::new(seed));
+                        },
+                        (true, None) => config.seed = Some(0),
+                        (false, _) => config.seed = None,
+                    }
+                });
+                ui.end_row();
+            });
+            if ui.button("Reset").clicked() {
+                *config = MCTSConfig::default();
+            }
+        });
+    }
+}
+
+// 搜索结束后，只有当游戏树焦点仍然是被搜索的局面时才走出这一步
+pub fn ui_ai_poll<G: GameTrait>(
+    mut ai_state: ResMut<UiAiState<G>>,
+    mut q_game: Query<&mut G>,
+    mut ew_remote: EventWriter<SendRemoteStep>,
+    runtime: Res<TokioTasksRuntime>,
+) {
+    let Ok(mut game) = q_game.single_mut() else {
+        ai_state.task = None;
+        ai_state.stop_pondering();
+        return;
+    };
+    let Some(task) = &mut ai_state.task else {
+        return;
+    };
+    match task.poll() {
+        AITaskPoll::Running => {},
+        AITaskPoll::Done(step) => {
+            if game.tree().focus() == task.node && game.board().write_fen() == task.fen {
+                // 网络对局中本地 AI 走出的一步也要发送给对方
+                try_move_and_send(&mut *game, step, &mut ew_remote);
+                ai_state.task = None;
+                if ai_state.ponder && ai_state.ponder_after_task {
+                    ai_state.start_pondering(&runtime, game.board().clone());
+                }
+            } else {
+                info!("AI: position changed during search, discard the result");
+                ai_state.task = None;
+            }
+        },
+        AITaskPoll::Failed(e) => {
+            warn!("AI: search task failed: {}", e);
+            ai_state.task = None;
+            ai_state.error = e;
+        },
+    }
+}
+
+// 轮到 AI 执掌的一方时自动走子
+pub fn ai_autoplay<G: GameTrait>(
+    mut ai_state: ResMut<UiAiState<G>>,
+    mut q_game: Query<&mut G>,
+    ui_menu: Res<UiMenuState>,
+    runtime: Res<TokioTasksRuntime>,
+)
+where
+    G::B: Heuristic,
+{
+    let Ok(mut game) = q_game.single_mut() else {
+        return;
+    };
+    let active = game.board().get_active_player();
+    let Some(seat) = game.seats().ai(active) else {
+        return;
+    };
+    // 只在游戏树的末端自动走子，复盘时不产生新的变着
+    if game.tree().is_last_board()
+        && !ai_state.is_thinking()
+        && !ai_state.paused
+        && ai_state.error.is_empty()
+    {
+        ai_state.start_seat(&runtime, &mut game, seat, &ui_menu);
+    }
+}
+
+// 编辑双方的座位，网络对局的对方只显示不能修改
+pub fn seats_ui(ui: &mut egui::Ui, seats: &mut Seats) {
+    egui::Grid::new("seats").show(ui, |ui| {
+        for (player, name) in [(PlayerOrder::First, "First"), (PlayerOrder::Second, "Second")] {
+            ui.label(name);
+            controller_ui(ui, name, seats.get_mut(player));
+            ui.end_row();
+        }
+    });
+}
+
+pub fn controller_ui(ui: &mut egui::Ui, id_salt: &str, controller: &mut Controller) {
+    if *controller == Controller::Remote {
+        ui.label("Remote");
+        return;
+    }
+    ui.vertical(|ui| {
+        let is_ai = matches!(controller, Controller::AI(_));
+        egui::ComboBox::from_id_salt(("controller", id_salt))
+            .selected_text(if is_ai { "AI" } else { "Human" })
+            .show_ui(ui, |ui| {
+                if ui.selectable_label(!is_ai, "Human").clicked() {
+                    *controller = Controller::Human;
+                }
+                if ui.selectable_label(is_ai, "AI").clicked() && !is_ai {
+                    *controller = Controller::AI(AiSeat::default());
+                }
+            });
+        let Controller::AI(seat) = controller else {
+            return;
+        };
+        egui::ComboBox::from_id_salt(("engine", id_salt))
+            .selected_text(match seat.engine {
+                AiEngine::WeakMCTS => "Weak MCTS",
+                AiEngine::MCTSv2 => "MCTSv2",
+                AiEngine::External => "External engine",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut seat.engine, AiEngine::WeakMCTS, "Weak MCTS");
+                ui.selectable_value(&mut seat.engine, AiEngine::MCTSv2, "MCTSv2");
+                ui.selectable_value(&mut seat.engine, AiEngine::External, "External engine");
+            });
+        ui.horizontal(|ui| match &mut seat.limit {
+            SearchLimit::Time(ms) => {
+                ui.add(egui::DragValue::new(ms).range(1..=u32::MAX).suffix(" ms"));
+                if ui.small_button("use simulations").clicked() {
+                    seat.limit = SearchLimit::Simulations(10000);
+                }
+            },
+            SearchLimit::Simulations(n) => {
+                ui.add(egui::DragValue::new(n).range(1..=u32::MAX).suffix(" sims"));
+                if ui.small_button("use time").clicked() {
+                    seat.limit = SearchLimit::Time(2000);
+                }
+            },
+        });
+    });
+}
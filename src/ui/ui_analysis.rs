@@ -0,0 +1,168 @@
+use std::{marker::PhantomData, sync::Arc};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_tokio_tasks::TokioTasksRuntime;
+
+use crate::{ai::{mctsv2::{MCTSConfig, MCTSv2}, task::AnalysisTask, Heuristic}, general::{board::*, game::Game as GameTrait}, graphics::overlay::BoardOverlay, ui::{ui_ai::UiAiState, ui_menu::UiMenuState}};
+
+// 棋盘上热力图的含义
+#[derive(PartialEq, Debug)]
+enum OverlayMode {
+    VisitShare, // 访问次数，相对于访问最多的着法
+    WinRate,
+}
+
+// 每种棋的局面分析，打开时持续分析游戏树焦点所在的局面
+#[derive(Resource)]
+pub struct UiAnalysisState<G: GameTrait> {
+    pub enabled: bool,
+    top_n: usize,
+    show_on_board: bool,
+    overlay_mode: OverlayMode,
+    searcher: Option<Arc<MCTSv2<G::B>>>,
+    task: Option<AnalysisTask<G::B>>,
+    _marker: PhantomData<G>,
+}
+
+impl<G: GameTrait> Default for UiAnalysisState<G> {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            top_n: 5,
+            show_on_board: false,
+            overlay_mode: OverlayMode::VisitShare,
+            searcher: None,
+            task: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<G: GameTrait> UiAnalysisState<G>
+where
+    G::B: Heuristic,
+{
+    // 游戏树焦点或 MCTS 参数变化时重新开始分析
+    fn update(&mut self, runtime: &TokioTasksRuntime, game: &mut G, config: MCTSConfig) {
+        if !self.enabled {
+            self.task = None;
+            return;
+        }
+        if self.searcher.as_ref().is_some_and(|searcher| *searcher.config() != config) {
+            self.task = None;
+            self.searcher = None;
+        }
+        let node = game.tree().focus();
+        let fen = game.board().write_fen();
+        if self.task.as_ref().is_some_and(|task| task.node == node && task.fen == fen) {
+            return;
+        }
+        // 先停止旧的分析，新的分析才能拿到搜索树
+        self.task = None;
+        let searcher = self.searcher
+            .get_or_insert_with(|| Arc::new(MCTSv2::default().with_config(config)))
+            .clone();
+        self.task = Some(AnalysisTask::spawn(runtime.runtime(), searcher, game.board().clone(), node));
+    }
+
+    // 把分析结果发布到棋盘上，只在内容变化时写入，避免每帧重绘
+    fn publish_overlay(&self, overlay: &mut ResMut<BoardOverlay<G::B>>) {
+        let (fen, moves) = match &self.task {
+            Some(task) if self.show_on_board => {
+                let stats = task.stats();
+                let max_visits = stats.first().map_or(1, |stat| stat.visits.max(1));
+                let moves: Vec<_> = stats.iter()
+                    .map(|stat| (stat.step, match self.overlay_mode {
+                        OverlayMode::VisitShare => stat.visits as f32 / max_visits as f32,
+                        OverlayMode::WinRate => stat.win_rate,
+                    }))
+                    .collect();
+                (task.fen.clone(), moves)
+            },
+            _ => (String::new(), Vec::new()),
+        };
+        if overlay.fen != fen || overlay.moves != moves {
+            overlay.fen = fen;
+            overlay.moves = moves;
+        }
+    }
+}
+
+pub fn ui_analysis<G: GameTrait>(
+    mut contexts: EguiContexts,
+    mut ui_menu: ResMut<UiMenuState>,
+    mut analysis: ResMut<UiAnalysisState<G>>,
+    ai_state: Res<UiAiState<G>>,
+    mut q_game: Query<&mut G>,
+    mut overlay: ResMut<BoardOverlay<G::B>>,
+    runtime: Res<TokioTasksRuntime>,
+) -> Result
+where
+    G::B: Heuristic,
+{
+    let ctx = contexts.ctx_mut()?;
+
+    let Ok(mut game) = q_game.single_mut() else {
+        analysis.task = None;
+        return Ok(())
+    };
+
+    if !ui_menu.analysis_window_open {
+        analysis.enabled = false;
+    }
+    analysis.update(&runtime, &mut game, ai_state.config);
+    analysis.publish_overlay(&mut overlay);
+
+    egui::Window::new("Analysis")
+        .open(&mut ui_menu.analysis_window_open)
+        .show(ctx, |ui| {
+            ui.checkbox(&mut analysis.enabled, "Analyze");
+            ui.horizontal(|ui| {
+                ui.label("Show top:");
+                ui.add(egui::DragValue::new(&mut analysis.top_n).range(1..=50));
+                ui.label("moves");
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut analysis.show_on_board, "Show on board");
+                ui.radio_value(&mut analysis.overlay_mode, OverlayMode::VisitShare, "Visits");
+                ui.radio_value(&mut analysis.overlay_mode, OverlayMode::WinRate, "Win rate");
+            });
+
+            let Some(task) = &analysis.task else {
+                return;
+            };
+
+            if game.board().end_game() {
+                ui.label("Game over");
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(format!("Analyzing… {} simulations", task.simulations()));
+            });
+
+            ui.separator();
+
+            // 胜率和估值都是当前行动方的
+            egui::Grid::new("analysis_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Move");
+                    ui.label("Visits");
+                    ui.label("Win rate");
+                    ui.label("Eval");
+                    ui.end_row();
+
+                    for stat in task.stats().iter().take(analysis.top_n) {
+                        ui.label(&stat.san);
+                        ui.label(format!("{}", stat.visits));
+                        ui.label(format!("{:.1}%", stat.win_rate * 100.0));
+                        ui.label(format!("{:.1}%", stat.evaluation * 100.0));
+                        ui.end_row();
+                    }
+                });
+        });
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{general::game::Game as GameTrait, net::{chat::*, session::{LocalProfile, Session}, NetState, SendNetMsgEvent}};
+
+// 连接状态和双方的身份
+#[derive(SystemParam)]
+pub struct ChatSession<'w> {
+    net_state: Res<'w, NetState>,
+    session: Option<Res<'w, Session>>,
+    profile: Res<'w, LocalProfile>,
+}
+
+// 网络对局中的聊天窗口，观众不能发言所以不显示
+pub fn ui_chat(
+    mut contexts: EguiContexts,
+    chat_session: ChatSession,
+    mut log: ResMut<ChatLog>,
+    mut input: Local<String>,
+    mut ew_net: EventWriter<SendNetMsgEvent>,
+    mut ew_chat: EventWriter<ChatEvent>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+    let ChatSession { net_state, session, profile } = chat_session;
+
+    let connected = match *net_state {
+        NetState::Connected(_, _) => true,
+        NetState::Reconnecting(_, _) => false,
+        _ => return Ok(()),
+    };
+    if session.as_ref().is_some_and(|session| session.spectator) {
+        return Ok(())
+    }
+
+    egui::Window::new("Chat").default_width(240.0).show(ctx, |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in &log.lines {
+                    ui.label(format!("[{}] {}: {}", format_time(line.time), line.sender, line.text));
+                }
+            });
+        ui.horizontal(|ui| {
+            let response = ui.text_edit_singleline(&mut *input);
+            let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let send = ui.add_enabled(connected, egui::Button::new("Send")).clicked() || (enter && connected);
+            if send && !input.trim().is_empty() {
+                let text = input.trim().to_string();
+                input.clear();
+                send_chat(text, &profile, &mut log, &mut ew_net, &mut ew_chat);
+                response.request_focus();
+            }
+        });
+    });
+
+    Ok(())
+}
+
+// 对局进行时把聊天消息记录到游戏树中，随游戏树一起保存
+pub fn record_chat<G: GameTrait>(
+    mut q_game: Query<&mut G>,
+    mut er_chat: EventReader<ChatEvent>,
+) {
+    let Ok(mut game) = q_game.single_mut() else {
+        er_chat.clear();
+        return;
+    };
+    for event in er_chat.read() {
+        game.tree().add_chat(event.line.clone());
+    }
+}
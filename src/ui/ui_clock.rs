@@ -0,0 +1,213 @@
+use std::time::Instant;
+use bevy::prelude::*;
+use bevy_egui::{egui::{self, RichText}, EguiContexts};
+
+use crate::{general::{board::*, format_clock, game::Game as GameTrait, PlayerOrder, TimeControl}, net::message::{ClockMessage, ReceiveRemoteClock, SendRemoteClock}};
+
+// 网络对局中对方的时间用完后再等这么久才判负，给对方的超时消息留出网络延迟的余量
+const REMOTE_GRACE_MS: u32 = 2000;
+
+fn to_player(player_order: bool) -> PlayerOrder {
+    if player_order { PlayerOrder::First } else { PlayerOrder::Second }
+}
+
+// 棋钟跟随主线：主线增加一步时按钟并把走子一方剩余的时间记录到游戏树中，超时判负
+pub fn clock_update<G: GameTrait>(
+    mut q_game: Query<&mut G>,
+    mut er_clock: EventReader<ReceiveRemoteClock>,
+    mut ew_clock: EventWriter<SendRemoteClock>,
+    mut pending: Local<Vec<ClockMessage>>, // 先于着法到达的时间同步
+) {
+    let Ok(mut game) = q_game.single_mut() else {
+        er_clock.clear();
+        pending.clear();
+        return;
+    };
+    pending.extend(er_clock.read().map(|event| event.message.clone()));
+    if game.tree().is_over() {
+        pending.clear();
+        return;
+    }
+
+    let now = Instant::now();
+    let plies = game.tree().mainline_plies();
+    let end = game.tree().mainline_end();
+    let board = game.tree().mainline_board();
+    let active = board.get_active_player();
+    let seats = *game.seats();
+    let Some(clock) = game.clock().as_mut() else {
+        pending.clear();
+        return;
+    };
+
+    let mut record = None;
+    let mut flagged = None;
+    if plies > clock.plies {
+        if let Some(mover) = clock.running() {
+            if seats.is_remote(mover) {
+                // 对方的时间等待对方的同步消息
+                clock.start(active, now);
+            } else {
+                match clock.press(active, now) {
+                    Some(time) => {
+                        record = Some(time.ms);
+                        // 以走子一方的时间为准
+                        if seats.has_remote() {
+                            let (first, second) = clock.times();
+                            ew_clock.write(SendRemoteClock { message: ClockMessage::Sync { plies, first, second } });
+                        }
+                    },
+                    None => flagged = Some(mover),
+                }
+            }
+        }
+        clock.plies = plies;
+    } else if plies < clock.plies {
+        clock.plies = plies;
+        clock.start(active, now);
+    }
+
+    if board.end_game() {
+        clock.stop(now);
+    } else if clock.running().is_none() && flagged.is_none() {
+        clock.start(active, now);
+    }
+
+    pending.retain(|message| match *message {
+        ClockMessage::Sync { plies: synced, first, second } => {
+            if synced == clock.plies {
+                clock.sync(first, second, now);
+                record = Some(match active {
+                    PlayerOrder::First => second.ms,
+                    PlayerOrder::Second => first.ms,
+                });
+            }
+            synced > clock.plies
+        },
+        ClockMessage::Timeout { player_order } => {
+            flagged = Some(to_player(player_order));
+            false
+        },
+    });
+
+    if flagged.is_none()
+        && let Some((player, overdue)) = clock.overdue(now)
+        && (!seats.is_remote(player) || overdue >= REMOTE_GRACE_MS)
+    {
+        flagged = Some(player);
+        if seats.has_remote() {
+            ew_clock.write(SendRemoteClock { message: ClockMessage::Timeout { player_order: player == PlayerOrder::First } });
+        }
+    }
+
+    if let Some(player) = flagged {
+        clock.flag(player);
+    }
+    if let Some(ms) = record {
+        game.tree().set_clock(end, ms);
+    }
+    if let Some(player) = flagged {
+        info!("clock: {:?} lost on time", player);
+        let result = match player {
+            PlayerOrder::First => "0-1",
+            PlayerOrder::Second => "1-0",
+        };
+        game.tree().set_result(result, "time forfeit");
+    }
+}
+
+// 计时对局显示双方的棋钟，复盘时还显示当前局面记录的时间
+pub fn ui_clock<G: GameTrait>(
+    mut contexts: EguiContexts,
+    mut q_game: Query<&mut G>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+
+    let Ok(mut game) = q_game.single_mut() else {
+        return Ok(())
+    };
+    let focus = game.tree().focus();
+    let at_end = focus == game.tree().mainline_end();
+    let recorded = game.tree().clocks_at(focus);
+    let Some(clock) = game.clock().as_ref() else {
+        return Ok(())
+    };
+
+    let now = Instant::now();
+    egui::Window::new("Clock").show(ctx, |ui| {
+        ui.label(format!("Time control: {}", clock.control));
+        for (player, name) in [(PlayerOrder::First, "First"), (PlayerOrder::Second, "Second")] {
+            let text = match clock.time(player, now) {
+                Some(time) => format!("{}: {}", name, time),
+                None => format!("{}: time out", name),
+            };
+            if clock.running() == Some(player) {
+                ui.label(RichText::new(text).strong().size(20.0));
+            } else {
+                ui.label(RichText::new(text).size(20.0));
+            }
+        }
+        if !at_end {
+            let show = |ms: Option<u32>| ms.map_or(String::from("-"), format_clock);
+            ui.label(format!("At this move: First {}, Second {}", show(recorded.0), show(recorded.1)));
+        }
+    });
+    if clock.running().is_some() {
+        ctx.request_repaint();
+    }
+
+    Ok(())
+}
+
+// 选择新对局的计时规则，时间以秒为单位编辑
+pub fn time_control_ui(ui: &mut egui::Ui, control: &mut Option<TimeControl>) {
+    let name = |control: &Option<TimeControl>| match control {
+        None => "No clock",
+        Some(TimeControl::SuddenDeath { .. }) => "Sudden death",
+        Some(TimeControl::Fischer { .. }) => "Fischer",
+        Some(TimeControl::Byoyomi { .. }) => "Byo-yomi",
+        Some(TimeControl::PerMove { .. }) => "Per move",
+    };
+    let choices = [
+        None,
+        Some(TimeControl::SuddenDeath { main_ms: 300_000 }),
+        Some(TimeControl::Fischer { main_ms: 300_000, increment_ms: 5_000 }),
+        Some(TimeControl::Byoyomi { main_ms: 600_000, period_ms: 30_000, periods: 3 }),
+        Some(TimeControl::PerMove { move_ms: 30_000 }),
+    ];
+    egui::ComboBox::from_label("Clock")
+        .selected_text(name(control))
+        .show_ui(ui, |ui| {
+            for choice in choices {
+                if ui.selectable_label(name(control) == name(&choice), name(&choice)).clicked() && name(control) != name(&choice) {
+                    *control = choice;
+                }
+            }
+        });
+
+    let seconds = |ui: &mut egui::Ui, label: &str, ms: &mut u32, min: f64| {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            let mut s = *ms as f64 / 1000.0;
+            ui.add(egui::DragValue::new(&mut s).range(min..=36000.0).suffix(" s"));
+            *ms = (s * 1000.0).round() as u32;
+        });
+    };
+    match control {
+        None => {},
+        Some(TimeControl::SuddenDeath { main_ms }) => seconds(ui, "Main time:", main_ms, 1.0),
+        Some(TimeControl::Fischer { main_ms, increment_ms }) => {
+            seconds(ui, "Main time:", main_ms, 1.0);
+            seconds(ui, "Increment:", increment_ms, 0.0);
+        },
+        Some(TimeControl::Byoyomi { main_ms, period_ms, periods }) => {
+            seconds(ui, "Main time:", main_ms, 0.0);
+            seconds(ui, "Period:", period_ms, 1.0);
+            ui.horizontal(|ui| {
+                ui.label("Periods:");
+                ui.add(egui::DragValue::new(periods).range(1..=100));
+            });
+        },
+        Some(TimeControl::PerMove { move_ms }) => seconds(ui, "Per move:", move_ms, 1.0),
+    }
+}
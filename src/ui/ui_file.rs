@@ -0,0 +1,180 @@
+use std::{fs, marker::PhantomData, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{general::{board::*, game::Game as GameTrait}, tree::file::FileFormat};
+
+// 保存在工作目录下的文件设置
+const SETTINGS_FILE: &str = "xinqi_files.json";
+const RECENT_FILES_MAX: usize = 10;
+// 每种棋保留的自动保存文件数
+const AUTOSAVE_KEEP: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecentFile {
+    pub game: String,
+    pub path: String,
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileSettings {
+    pub games_dir: String,
+    pub recent: Vec<RecentFile>,
+}
+
+impl Default for FileSettings {
+    fn default() -> Self {
+        Self {
+            games_dir: String::from("games"),
+            recent: Vec::new(),
+        }
+    }
+}
+
+impl FileSettings {
+    pub fn load() -> Self {
+        fs::read_to_string(SETTINGS_FILE)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|s| fs::write(SETTINGS_FILE, s).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("failed to save file settings: {}", e);
+        }
+    }
+
+    pub fn autosave_dir(&self) -> PathBuf {
+        Path::new(&self.games_dir).join("autosave")
+    }
+
+    // 网络会话的记录
+    pub fn netlog_dir(&self) -> PathBuf {
+        Path::new(&self.games_dir).join("netlog")
+    }
+
+    pub fn add_recent(&mut self, game: &str, path: &Path) {
+        let path = path.display().to_string();
+        self.recent.retain(|f| !(f.game == game && f.path == path));
+        self.recent.insert(0, RecentFile { game: game.to_string(), path });
+        self.recent.truncate(RECENT_FILES_MAX);
+        self.save();
+    }
+
+    pub fn recent_of<'a>(&'a self, game: &'a str) -> impl Iterator<Item = &'a RecentFile> {
+        self.recent.iter().filter(move |f| f.game == game)
+    }
+}
+
+// 目录中所有能够读取的文件，按文件名排序
+pub fn list_game_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && FileFormat::from_path(path).is_some())
+        .collect();
+    files.sort();
+    files
+}
+
+// 每走一步都把游戏树保存到自动保存目录。新开对局或载入游戏树时换一个新文件，之前的文件不会被覆盖
+#[derive(Resource)]
+pub struct Autosave<G: GameTrait> {
+    entity: Option<Entity>,
+    node_count: usize,
+    file: Option<PathBuf>,
+    _marker: PhantomData<G>,
+}
+
+impl<G: GameTrait> Default for Autosave<G> {
+    fn default() -> Self {
+        Self {
+            entity: None,
+            node_count: 0,
+            file: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<G: GameTrait> Autosave<G> {
+    pub fn new_session(&mut self) {
+        self.node_count = 0;
+        self.file = None;
+    }
+
+    fn write(&mut self, game: &mut G, settings: &FileSettings) {
+        // 只有初始局面时不保存
+        if game.tree().node_count() <= 1 {
+            return;
+        }
+        let file = match &self.file {
+            Some(file) => file.clone(),
+            None => {
+                let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+                let file = settings.autosave_dir().join(format!("{}-{}.tree", G::B::NAME, millis));
+                self.file = Some(file.clone());
+                prune_autosaves::<G::B>(&settings.autosave_dir());
+                file
+            },
+        };
+        if let Err(e) = game.tree().save_file(&file) {
+            warn!("autosave to {} failed: {}", file.display(), e);
+        }
+    }
+}
+
+// 删除最旧的自动保存文件，为新文件留出位置
+fn prune_autosaves<B: Board>(dir: &Path) {
+    let prefix = format!("{}-", B::NAME);
+    let files: Vec<PathBuf> = list_game_files(dir)
+        .into_iter()
+        .filter(|path| path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with(&prefix)))
+        .collect();
+    if files.len() >= AUTOSAVE_KEEP {
+        for path in &files[..=files.len() - AUTOSAVE_KEEP] {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+pub fn autosave<G: GameTrait>(
+    mut q_game: Query<(Entity, &mut G)>,
+    mut autosave: ResMut<Autosave<G>>,
+    settings: Res<FileSettings>,
+) {
+    let Ok((entity, mut game)) = q_game.single_mut() else {
+        autosave.entity = None;
+        return;
+    };
+    if autosave.entity != Some(entity) {
+        autosave.entity = Some(entity);
+        autosave.new_session();
+    }
+    let node_count = game.tree().node_count();
+    if node_count != autosave.node_count {
+        autosave.node_count = node_count;
+        autosave.write(&mut game, &settings);
+    }
+}
+
+pub fn autosave_on_exit<G: GameTrait>(
+    mut er_exit: EventReader<AppExit>,
+    mut q_game: Query<&mut G>,
+    mut autosave: ResMut<Autosave<G>>,
+    settings: Res<FileSettings>,
+) {
+    if er_exit.read().next().is_none() {
+        return;
+    }
+    if let Ok(mut game) = q_game.single_mut() {
+        autosave.write(&mut game, &settings);
+    }
+}
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use bevy_egui::{egui::{self, Ui, Grid}, EguiContexts};
 
-use crate::{general::game::Game as GameTrait, tree::game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}, ui::ui_menu::*};
+use crate::{general::game::Game as GameTrait, net::chat::format_time, tree::game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}, ui::ui_menu::*};
 
 pub fn ui_game_tree<G>(
     mut contexts: EguiContexts,
@@ -28,6 +28,16 @@ where
                     game.tree().display_egui(ui, &mut ew_mtn, &mut ew_dv);
                 });
 
+            // 网络对局中记录的聊天
+            let chat = game.tree().chat();
+            if !chat.is_empty() {
+                egui::CollapsingHeader::new(format!("Chat ({})", chat.len())).show(ui, |ui| {
+                    for line in chat {
+                        ui.label(format!("[{}] {}: {}", format_time(line.time), line.sender, line.text));
+                    }
+                });
+            }
+
             ui.separator();
 
             egui::TopBottomPanel::bottom("button_panel")
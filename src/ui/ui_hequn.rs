@@ -1,14 +1,18 @@
 use bevy::prelude::*;
 use bevy_egui::{egui::{self, Color32}, EguiContexts};
+use bevy_tokio_tasks::TokioTasksRuntime;
 
 use crate::{
-    ai::{mcts::MCTSAI, mctsv2::MCTSv2, AI}, general::Board, hequn::{ai::{evaluate, quick_move}, game::HequnGame, general::HequnStep}, ui::ui_menu::UiMenuState
+    ai::mcts::MCTSAI, general::{Board, Game, PlayerOrder}, hequn::{game::HequnGame, general::{HequnPhase, HequnPiece, HequnStep}}, net::message::SendRemoteStep, ui::{ui_ai::UiAiState, ui_menu::UiMenuState}
 };
 
 pub fn ui_hequn(
     mut contexts: EguiContexts,
     mut ui_menu: ResMut<UiMenuState>,
     mut q_hequn: Query<&mut HequnGame>,
+    mut ew_remote: EventWriter<SendRemoteStep>,
+    mut ai_state: ResMut<UiAiState<HequnGame>>,
+    runtime: Res<TokioTasksRuntime>,
 ) -> Result {
     let ctx = contexts.ctx_mut()?;
 
@@ -16,38 +20,68 @@ pub fn ui_hequn(
         return Ok(())
     };
 
-    let ai_time_limit_ms = ui_menu.ai_time_limit_ms;
+    let ai_limit = ui_menu.ai_limit();
+    let ai_threads = ui_menu.ai_threads;
+    let engine_command = ui_menu.engine_command.clone();
 
     egui::Window::new("Hequn")
         .open(&mut ui_menu.hequn_window_open)
         .show(ctx, |ui| {
             ui.label(hequn.board.game_info());
 
+            let black_player = match hequn.board.player_of(HequnPiece::Black) {
+                PlayerOrder::First => "First",
+                PlayerOrder::Second => "Second",
+            };
+            let white_player = match hequn.board.player_of(HequnPiece::White) {
+                PlayerOrder::First => "First",
+                PlayerOrder::Second => "Second",
+            };
+
             ui.horizontal(|ui| {
                 ui.colored_label(Color32::from_rgb(249, 106, 226), "■");
-                ui.label(format!("Black: {}", hequn.board.black_score));
+                ui.label(format!("Black ({}): {}", black_player, hequn.board.black_score));
             });
 
             ui.horizontal(|ui| {
                 ui.colored_label(Color32::from_rgb(127, 246, 244), "■");
-                ui.label(format!("White: {}", hequn.board.white_score));
+                ui.label(format!("White ({}): {}", white_player, hequn.board.white_score));
             });
 
-            if ui.button("Pass").clicked() {
-                hequn.try_move(HequnStep::Pass);
+            if hequn.board.phase == HequnPhase::Choose {
+                ui.horizontal(|ui| {
+                    if ui.button("Choose Black").clicked() {
+                        hequn.try_local_move(HequnStep::Choose(HequnPiece::Black), &mut ew_remote);
+                    }
+                    if ui.button("Choose White").clicked() {
+                        hequn.try_local_move(HequnStep::Choose(HequnPiece::White), &mut ew_remote);
+                    }
+                });
             }
 
-            if ui.button("weak ai play").clicked() {
-                let ai = MCTSAI::new();
-                let ai_step = ai.play(hequn.board.clone(), ai_time_limit_ms);
-                hequn.try_move(ai_step);
+            if ui.button("Pass").clicked() {
+                hequn.try_local_move(HequnStep::Pass, &mut ew_remote);
             }
 
-            if ui.button("ai play").clicked() {
-                let ai = MCTSv2::new(evaluate, quick_move);
-                let ai_step = ai.play(hequn.board.clone(), ai_time_limit_ms);
-                hequn.try_move(ai_step);
+            if !ai_state.is_thinking() {
+                if ui.button("weak ai play").clicked() {
+                    let seed = ai_state.config.seed;
+                    ai_state.start(&runtime, MCTSAI::new().with_seed(seed), &mut hequn, ai_limit);
+                }
+
+                if ui.button("ai play").clicked() {
+                    let config = ai_state.config;
+                    ai_state.start_searcher(&runtime, &mut hequn, ai_limit, ai_threads, config);
+                }
+
+                if !engine_command.trim().is_empty() && ui.button("engine play").clicked() {
+                    ai_state.start_engine(&runtime, &mut hequn, &engine_command, ai_limit);
+                }
             }
+
+            ai_state.show_thinking(ui);
+            ai_state.show_seats(ui, hequn.seats());
+            ai_state.show_config(ui);
         });
 
     Ok(())
@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{general::{board::*, game::Game as GameTrait, PlayerOrder, UpdateBoard}, net::message::{GameAction, ReceiveRemoteAction, SendRemoteAction}};
+
+// 网络对局中等待回复的请求，主线变化后作废
+#[derive(Resource, Default)]
+pub struct UiMatchState {
+    game: Option<Entity>,
+    plies: usize,
+    draw_sent: bool,
+    draw_received: bool,
+    takeback_sent: Option<usize>,
+    takeback_received: Option<usize>,
+}
+
+impl UiMatchState {
+    fn clear_offers(&mut self) {
+        self.draw_sent = false;
+        self.draw_received = false;
+        self.takeback_sent = None;
+        self.takeback_received = None;
+    }
+}
+
+fn loss(player: PlayerOrder) -> &'static str {
+    match player {
+        PlayerOrder::First => "0-1",
+        PlayerOrder::Second => "1-0",
+    }
+}
+
+// 悔棋后双方的焦点都移到主线上剩下的最后一步
+fn take_back<G: GameTrait>(game: &mut G, plies: usize, ew_update: &mut EventWriter<UpdateBoard<G::B>>) {
+    if game.tree().take_back(plies) {
+        ew_update.write(UpdateBoard::new(game.tree().board()));
+    }
+}
+
+// 网络对局中的认输、提和与悔棋，结果记录在游戏树的标签中
+pub fn ui_match<G: GameTrait>(
+    mut contexts: EguiContexts,
+    mut state: ResMut<UiMatchState>,
+    mut q_game: Query<(Entity, &mut G)>,
+    mut er_action: EventReader<ReceiveRemoteAction>,
+    mut ew_action: EventWriter<SendRemoteAction>,
+    mut ew_update: EventWriter<UpdateBoard<G::B>>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+
+    let Ok((entity, mut game)) = q_game.single_mut() else {
+        er_action.clear();
+        return Ok(())
+    };
+    let Some(local) = game.seats().local_player() else {
+        er_action.clear();
+        return Ok(())
+    };
+    if state.game != Some(entity) {
+        *state = UiMatchState { game: Some(entity), ..default() };
+    }
+    let plies = game.tree().mainline_plies();
+    if plies != state.plies {
+        state.plies = plies;
+        state.clear_offers();
+    }
+    let ended = game.tree().is_over() || game.tree().mainline_board().end_game();
+
+    for event in er_action.read() {
+        info!("match: receive {:?}", event.action);
+        match event.action {
+            GameAction::Resign if !ended => game.tree().set_result(loss(local.flip()), "resignation"),
+            GameAction::OfferDraw if !ended => state.draw_received = true,
+            GameAction::AcceptDraw if state.draw_sent && !ended => game.tree().set_result("1/2-1/2", "agreement"),
+            GameAction::DeclineDraw => state.draw_sent = false,
+            GameAction::RequestTakeback { plies } if !ended => state.takeback_received = Some(plies),
+            GameAction::AcceptTakeback { plies } if state.takeback_sent == Some(plies) && !ended => take_back(&mut *game, plies, &mut ew_update),
+            GameAction::DeclineTakeback => state.takeback_sent = None,
+            _ => {},
+        }
+    }
+    let ended = game.tree().is_over() || game.tree().mainline_board().end_game();
+
+    egui::Window::new("Match").show(ctx, |ui| {
+        let result = game.tree().result();
+        if ended {
+            let termination = game.tree().tag("Termination").map_or(String::new(), |t| format!(" ({})", t));
+            ui.label(format!("Game over: {}{}", result, termination));
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Resign").clicked() {
+                game.tree().set_result(loss(local), "resignation");
+                ew_action.write(SendRemoteAction { action: GameAction::Resign });
+            }
+            if ui.add_enabled(!state.draw_sent, egui::Button::new("Offer draw")).clicked() {
+                state.draw_sent = true;
+                ew_action.write(SendRemoteAction { action: GameAction::OfferDraw });
+            }
+            let takeback = game.tree().takeback_plies(local);
+            if ui.add_enabled(takeback.is_some() && state.takeback_sent.is_none(), egui::Button::new("Request takeback")).clicked()
+                && let Some(plies) = takeback
+            {
+                state.takeback_sent = Some(plies);
+                ew_action.write(SendRemoteAction { action: GameAction::RequestTakeback { plies } });
+            }
+        });
+        if state.draw_sent {
+            ui.label("Draw offered, waiting for the opponent…");
+        }
+        if state.takeback_sent.is_some() {
+            ui.label("Takeback requested, waiting for the opponent…");
+        }
+
+        if state.draw_received {
+            ui.horizontal(|ui| {
+                ui.label("Opponent offers a draw");
+                if ui.button("Accept").clicked() {
+                    state.draw_received = false;
+                    game.tree().set_result("1/2-1/2", "agreement");
+                    ew_action.write(SendRemoteAction { action: GameAction::AcceptDraw });
+                }
+                if ui.button("Decline").clicked() {
+                    state.draw_received = false;
+                    ew_action.write(SendRemoteAction { action: GameAction::DeclineDraw });
+                }
+            });
+        }
+        if let Some(plies) = state.takeback_received {
+            ui.horizontal(|ui| {
+                ui.label("Opponent asks to take back their last move");
+                if ui.button("Accept").clicked() {
+                    state.takeback_received = None;
+                    take_back(&mut *game, plies, &mut ew_update);
+                    ew_action.write(SendRemoteAction { action: GameAction::AcceptTakeback { plies } });
+                }
+                if ui.button("Decline").clicked() {
+                    state.takeback_received = None;
+                    ew_action.write(SendRemoteAction { action: GameAction::DeclineTakeback });
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
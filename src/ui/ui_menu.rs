@@ -2,12 +2,25 @@ use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 
 use crate::{
-    general::PlayerOrder, hequn::game::{EndHequnGame, HequnGame}, net::{message::{Message, ReceiveRemoteStep, SendRemoteStep}, NetCommand, NetState, ReceiveNetMsgEvent, SendNetMsgEvent}, xingxiang::game::{EndXingxiangGame, XingxiangGame}, zhandi::game::{EndZhandiGame, ZhandiGame}
+    ai::SearchLimit, general::{Controller, Game as _, PlayerOrder, Seats, TimeControl, XinqiParseError}, hequn::{game::{EndHequnGame, HequnGame}, general::HequnBoard}, net::{auth::{RejectedPeers, RoomKey}, lobby::LobbyRooms, message::{GameRules, GameStart, Message}, session::{LocalProfile, Session, GAMES}, spectate::Spectators, NetCommand, NetState, ReceiveNetMsgEvent, SendNetMsgEvent}, ui::{ui_ai::{controller_ui, seats_ui}, ui_clock::time_control_ui}, xingxiang::{game::{EndXingxiangGame, XingxiangGame}, general::XingxiangBoard}, zhandi::{game::{EndZhandiGame, ZhandiGame}, general::{ZhandiBoard, DEFAULT_KOMI}}
 };
 
+#[derive(Clone)]
 struct GameRequest {
     game_name: String, 
     player_order: bool, 
+    time_control: Option<TimeControl>,
+    start: GameStart,
+    rules: GameRules,
+}
+
+// 网络对局邀请的开始局面
+#[derive(PartialEq, Debug)]
+enum StartFrom {
+    Initial,
+    CurrentGame, // 正在进行的对局的游戏树，例如继续一盘没有下完的棋
+    Fen,
+    Moves,
 }
 
 #[derive(Resource)]
@@ -17,14 +30,34 @@ pub struct UiMenuState {
     pub running_game: Option<Game>,
     pub sl_window_open: bool,
     pub tree_window_open: bool,
+    pub analysis_window_open: bool,
     pub hequn_window_open: bool,
     pub zhandi_window_open: bool,
     pub xingxiang_window_open: bool,
+    pub replay_window_open: bool,
+    pub replay_tree: Option<(String, String)>, // 回放窗口要求打开的 (游戏名, 游戏树)
     pub ai_time_limit_ms: u32,
+    pub ai_simulations: u32,
+    pub ai_count_simulations: bool, // 按模拟次数而不是时间限制 AI 每步的搜索，配合固定种子可以复现
+    pub ai_threads: usize,
+    pub engine_command: String, // 外部引擎的启动命令
+    seats: Seats, // 新对局双方由谁走子
+    net_local: Controller, // 网络对局中本地一方由谁走子
+    time_control: Option<TimeControl>, // 新对局和发出的网络对局邀请使用的计时规则
+    hequn_swap_rule: bool,
+    zhandi_komi: usize,
+    start_from: StartFrom, // 发出的网络对局邀请从哪里开始
+    start_text: String, // 开始局面的 FEN 或者以空格分隔的着法
+    invitation_error: Option<String>, // 发出的邀请中局面或着法不合法
     
     local_addr: String,
     remote_addr: String,
-    game_request: Option<GameRequest>, // 暂存对方发来的开始对局请求
+    lobby_addr: String,
+    password: String, // 房间密码，监听时要求对方输入，连接时发送证明
+    encrypt: bool, // 监听时要求加密连接
+    require_encryption: bool, // 连接时要求对方加密
+    game_request: Option<(GameRequest, Option<String>)>, // 暂存对方发来的开始对局请求，以及不能在本地开始的原因
+    rematch: Option<GameRequest>, // 交换先后手再下一盘时发出的邀请
 }
 
 impl Default for UiMenuState {
@@ -35,13 +68,43 @@ impl Default for UiMenuState {
             running_game: None,
             sl_window_open: false, 
             tree_window_open: false, 
+            analysis_window_open: false,
             hequn_window_open: false,
             zhandi_window_open: false,
             xingxiang_window_open: false,
+            replay_window_open: false,
+            replay_tree: None,
             ai_time_limit_ms: 2000,
+            ai_simulations: 10000,
+            ai_count_simulations: false,
+            ai_threads: 1,
+            engine_command: String::new(),
+            seats: Seats::default(),
+            net_local: Controller::Human,
+            time_control: None,
+            hequn_swap_rule: false,
+            zhandi_komi: DEFAULT_KOMI,
+            start_from: StartFrom::Initial,
+            start_text: String::new(),
+            invitation_error: None,
             local_addr: String::from("0.0.0.0:18386"),
             remote_addr: String::from("123.123.123.123:18386"),
+            lobby_addr: String::from("123.123.123.123:18387"),
+            password: String::new(),
+            encrypt: false,
+            require_encryption: false,
             game_request: None,
+            rematch: None,
+        }
+    }
+}
+
+impl UiMenuState {
+    pub fn ai_limit(&self) -> SearchLimit {
+        if self.ai_count_simulations {
+            SearchLimit::Simulations(self.ai_simulations)
+        } else {
+            SearchLimit::Time(self.ai_time_limit_ms)
         }
     }
 }
@@ -70,8 +133,12 @@ pub fn ui_menu(
     mut ew_nc: EventWriter<NetCommand>,
     mut er_net: EventReader<ReceiveNetMsgEvent>,
     mut ew_net: EventWriter<SendNetMsgEvent>,
-    mut er_step: EventReader<SendRemoteStep>,
-    mut ew_step: EventWriter<ReceiveRemoteStep>,
+    mut profile: ResMut<LocalProfile>,
+    session: Option<Res<Session>>,
+    (spectators, rejected): (Res<Spectators>, Res<RejectedPeers>),
+    lobby_rooms: Res<LobbyRooms>,
+    mut watched: Local<bool>, // 观看期间已经开始了观看的对局
+    mut q_games: (Query<&mut HequnGame>, Query<&mut ZhandiGame>, Query<&mut XingxiangGame>),
 ) -> Result {
     let ctx = contexts.ctx_mut()?;
 
@@ -86,39 +153,113 @@ pub fn ui_menu(
                 running_game,
                 sl_window_open,
                 tree_window_open,
+                analysis_window_open,
                 hequn_window_open,
                 zhandi_window_open,
                 xingxiang_window_open,
+                replay_window_open,
+                replay_tree,
                 ai_time_limit_ms,
+                ai_simulations,
+                ai_count_simulations,
+                ai_threads,
+                engine_command,
+                seats,
+                net_local,
+                time_control,
+                hequn_swap_rule,
+                zhandi_komi,
+                start_from,
+                start_text,
+                invitation_error,
                 local_addr,
                 remote_addr,
+                lobby_addr,
+                password,
+                encrypt,
+                require_encryption,
                 game_request,
+                rematch,
             } = &mut *ui_state;
 
             let mut disconnected = false;
             let mut connected = false;
+            let mut watching = false;
 
             match *net_state {
                 NetState::Disconnected => {
                     disconnected = true;
                     ui.label("Net State: Disconnected");
+                    if let Some(error) = session.as_ref().and_then(|session| session.error.as_ref()) {
+                        ui.label(format!("Last connection: {}", error));
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Your name: ");
+                        ui.text_edit_singleline(&mut profile.name);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Password: ");
+                        ui.add(egui::TextEdit::singleline(password).password(true).hint_text("none"));
+                    });
+                    // 加密需要密码，由监听的一方选择，连接的一方可以拒绝不加密的房间
+                    ui.add_enabled(!password.is_empty(), egui::Checkbox::new(encrypt, "Encrypt when listening"));
+                    ui.add_enabled(!password.is_empty(), egui::Checkbox::new(require_encryption, "Require encryption when connecting"));
+                    let key = RoomKey {
+                        password: password.clone(),
+                        encrypted: *encrypt,
+                        require_encryption: *require_encryption && !password.is_empty(),
+                    };
                     ui.horizontal(|ui| {
                         ui.label("Listen to: ");
                         ui.text_edit_singleline(local_addr);
                     });
                     if ui.button("Listen").clicked() {
-                        ew_nc.write(NetCommand::Listen(local_addr.clone()));
+                        ew_nc.write(NetCommand::Listen(local_addr.clone(), key.clone()));
                     }
                     ui.horizontal(|ui| {
                         ui.label("Connect to: ");
                         ui.text_edit_singleline(remote_addr);
                     });
-                    if ui.button("Connect").clicked() {
-                        ew_nc.write(NetCommand::Connect(remote_addr.clone()));
+                    ui.horizontal(|ui| {
+                        if ui.button("Connect").clicked() {
+                            ew_nc.write(NetCommand::Connect(remote_addr.clone(), key.clone()));
+                        }
+                        if ui.button("Watch").clicked() {
+                            ew_nc.write(NetCommand::Watch(remote_addr.clone(), key.clone()));
+                        }
+                    });
+
+                    // 通过大厅找到对方，不需要交换地址
+                    ui.horizontal(|ui| {
+                        ui.label("Lobby: ");
+                        ui.text_edit_singleline(lobby_addr);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Open room").clicked() {
+                            ew_nc.write(NetCommand::OpenRoom { lobby: lobby_addr.clone(), host: profile.name.clone(), game: format!("{:?}", game), key: key.clone() });
+                        }
+                        if ui.button("Refresh rooms").clicked() {
+                            ew_nc.write(NetCommand::ListRooms(lobby_addr.clone()));
+                        }
+                    });
+                    if let Some(error) = &lobby_rooms.error {
+                        ui.colored_label(egui::Color32::RED, format!("Lobby: {}", error));
+                    }
+                    for room in &lobby_rooms.rooms {
+                        ui.horizontal(|ui| {
+                            let locked = if room.locked { ", password" } else { "" };
+                            ui.label(format!("#{} {}: {} ({} connected{})", room.id, room.host, room.game, room.guests, locked));
+                            if ui.button("Join").clicked() {
+                                ew_nc.write(NetCommand::JoinRoom { lobby: lobby_addr.clone(), room: room.id, spectator: false, key: key.clone() });
+                            }
+                            if ui.button("Watch").clicked() {
+                                ew_nc.write(NetCommand::JoinRoom { lobby: lobby_addr.clone(), room: room.id, spectator: true, key: key.clone() });
+                            }
+                        });
                     }
                     *game_request = None;
                 },
-                NetState::Listening(addr, _) => {
+                NetState::Listening(ref addr, _) => {
                     ui.label(format!("Net State: Listening to {}", addr));
                     if ui.button("Disconnect").clicked() {
                         ew_nc.write(NetCommand::Disconnect);
@@ -126,24 +267,84 @@ pub fn ui_menu(
                     *game_request = None;
                 },
                 NetState::Connected(addr, _) => {
-                    connected = true;
+                    if session.as_ref().is_some_and(|session| session.spectator) {
+                        watching = true;
+                    } else {
+                        connected = true;
+                    }
                     ui.label(format!("Net State: Connected to {}", addr));
                     if ui.button("Disconnect").clicked() {
                         ew_nc.write(NetCommand::Disconnect);
                     }
+                    if let Some(session) = &session {
+                        match &session.peer {
+                            Some(peer) if session.spectator => ui.label(format!("Watching {} ({})", peer.name, peer.app)),
+                            Some(peer) => ui.label(format!("Opponent: {} ({})", peer.name, peer.app)),
+                            None => ui.label("Waiting for the opponent's handshake…"),
+                        };
+                        if session.encrypted {
+                            ui.label("Connection encrypted");
+                        }
+                        if let Some(error) = &session.error {
+                            ui.colored_label(egui::Color32::RED, error);
+                        }
+                        if let Some(notice) = &session.notice {
+                            ui.label(notice);
+                        }
+                    }
+                },
+                NetState::Reconnecting(ref addr, _) => {
+                    // 对局保留，重新连上后继续
+                    if session.as_ref().is_some_and(|session| session.host) {
+                        ui.label(format!("Net State: Opponent disconnected, waiting on {}…", addr));
+                    } else {
+                        ui.label(format!("Net State: Connection lost, reconnecting to {}…", addr));
+                    }
+                    ui.spinner();
+                    if ui.button("Give up").clicked() {
+                        ew_nc.write(NetCommand::Disconnect);
+                    }
                 },
             }
 
+            if !spectators.is_empty() {
+                let names: Vec<String> = spectators.iter().map(|spectator| {
+                    if spectator.name.is_empty() { spectator.addr.to_string() } else { spectator.name.clone() }
+                }).collect();
+                ui.label(format!("Spectators: {}", names.join(", ")));
+            }
+            // 监听时拒绝的连接，例如密码错误
+            if !disconnected {
+                for (addr, reason) in &rejected.list {
+                    ui.colored_label(egui::Color32::RED, format!("Rejected {}: {}", addr, reason));
+                }
+            }
+
             ui.separator();
 
+            // 回放窗口要求打开的对局只在没有连接时接受，双方都由本地走子
+            let replay = replay_tree.take();
             if disconnected {
+                if let Some((game_name, tree)) = replay {
+                    let request = GameRequest { game_name, player_order: true, time_control: None, start: GameStart::Tree(tree), rules: GameRules::default() };
+                    *hequn_window_open = false;
+                    *zhandi_window_open = false;
+                    *xingxiang_window_open = false;
+                    match start_game(running_game, &request, Seats::default(), &mut commands, &mut ew_end_hequn, &mut ew_end_zhandi, &mut ew_end_xingxiang) {
+                        Ok(()) => {
+                            *tree_window_open = true;
+                            match running_game {
+                                Some(Game::Hequn(_)) => *hequn_window_open = true,
+                                Some(Game::Zhandi(_)) => *zhandi_window_open = true,
+                                Some(Game::Xingxiang(_)) => *xingxiang_window_open = true,
+                                None => {},
+                            }
+                        },
+                        Err(e) => warn!("cannot open the replayed game: {}", e),
+                    }
+                }
 
-                ui.label(format!("Now playing: {}", match running_game {
-                    Some(Game::Hequn(_)) => "Hequn",
-                    Some(Game::Zhandi(_)) => "Zhandi",
-                    Some(Game::Xingxiang(_)) => "Xingxiang",
-                    None => "None",
-                }));
+                ui.label(format!("Now playing: {}", running_game_name(running_game)));
 
                 ui.separator();
 
@@ -155,10 +356,17 @@ pub fn ui_menu(
                         ui.selectable_value(game, GameTitle::Xingxiang, "Xingxiang");
                     });
 
+                rules_ui(ui, game, hequn_swap_rule, zhandi_komi);
+
+                ui.label("Players:");
+                seats_ui(ui, seats);
+                time_control_ui(ui, time_control);
+
                 // 这种实现方式有可能导致同一帧存在两个游戏实体，尽管它们不会在同一帧被绘制
                 if ui.button("Start New Game").clicked() {
                     *sl_window_open = false;
                     *tree_window_open = false;
+                    *analysis_window_open = false;
                     *hequn_window_open = false;
                     *zhandi_window_open = false;
                     *xingxiang_window_open = false;
@@ -180,18 +388,20 @@ pub fn ui_menu(
                     }
                     match game {
                         GameTitle::Hequn => {
+                            let board = if *hequn_swap_rule { HequnBoard::swap_opening() } else { HequnBoard::default() };
                             *running_game = Some(Game::Hequn(commands.spawn((
-                                HequnGame::new(None),
+                                HequnGame::with_board(board, *seats).with_time_control(*time_control),
                             )).id()));
                         },
                         GameTitle::Zhandi => {
+                            let board = ZhandiBoard::default().with_komi(*zhandi_komi);
                             *running_game = Some(Game::Zhandi(commands.spawn((
-                                ZhandiGame::new(None),
+                                ZhandiGame::with_board(board, *seats).with_time_control(*time_control),
                             )).id()));
                         },
                         GameTitle::Xingxiang => {
                             *running_game = Some(Game::Xingxiang(commands.spawn((
-                                XingxiangGame::new(None),
+                                XingxiangGame::new(*seats).with_time_control(*time_control),
                             )).id()));
                         },
                     }
@@ -201,6 +411,8 @@ pub fn ui_menu(
 
                 ui.checkbox(sl_window_open, "show SL window");
                 ui.checkbox(tree_window_open, "show game tree");
+                ui.checkbox(analysis_window_open, "show analysis");
+                ui.checkbox(replay_window_open, "show replay");
                 match running_game {
                     Some(Game::Hequn(_)) => {
                         ui.checkbox(hequn_window_open, "show hequn game");
@@ -217,15 +429,39 @@ pub fn ui_menu(
                 ui.separator();
                 
                 ui.horizontal(|ui| {
-                    ui.label("Set AI time limit:");
-                    ui.add(egui::DragValue::new(ai_time_limit_ms));
-                    ui.label("ms");
+                    ui.label("AI budget:");
+                    ui.radio_value(ai_count_simulations, false, "Time");
+                    ui.radio_value(ai_count_simulations, true, "Simulations");
+                });
+
+                if *ai_count_simulations {
+                    ui.horizontal(|ui| {
+                        ui.label("Set AI simulations:");
+                        ui.add(egui::DragValue::new(ai_simulations).range(1..=u32::MAX));
+                    });
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.label("Set AI time limit:");
+                        ui.add(egui::DragValue::new(ai_time_limit_ms));
+                        ui.label("ms");
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Set AI threads:");
+                    ui.add(egui::DragValue::new(ai_threads).range(1..=64));
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("External engine:");
+                    ui.add(egui::TextEdit::singleline(engine_command).hint_text("xinqi --engine"));
                 });
 
             } // if disconnected
 
             if connected {
                 *tree_window_open = false;
+                *analysis_window_open = false;
                 *hequn_window_open = false;
                 *zhandi_window_open = false;
                 *xingxiang_window_open = false;
@@ -234,29 +470,24 @@ pub fn ui_menu(
                     info!("receive message from net: {}", event.message);
                     let Ok(message) = serde_json::from_str(&event.message) else { continue; };
                     match message {
-                        Message::CreateNewGame { game_name, player_order } => {
-                            *game_request = Some(GameRequest {
-                                game_name,
-                                player_order,
-                            });
-                        },
-                        Message::AcceptCreateNewGame { game_name, player_order } => {
-                            let remote_play = Some(if player_order { PlayerOrder::First } else { PlayerOrder::Second });
-                            start_game(running_game, &game_name, remote_play, &mut commands, &mut ew_end_hequn, &mut ew_end_zhandi, &mut ew_end_xingxiang);
+                        Message::CreateNewGame { game_name, player_order, time_control, start, rules } => {
+                            let request = GameRequest { game_name, player_order, time_control, start, rules };
+                            let problem = check_start(&request).err().map(|e| e.to_string());
+                            *game_request = Some((request, problem));
                         },
-                        Message::Step(step) => {
-                            ew_step.write(ReceiveRemoteStep { step });
+                        Message::AcceptCreateNewGame { game_name, player_order, time_control, start, rules } => {
+                            let seats = Seats::remote(if player_order { PlayerOrder::First } else { PlayerOrder::Second }, *net_local);
+                            let request = GameRequest { game_name, player_order, time_control, start, rules };
+                            if let Err(e) = start_game(running_game, &request, seats, &mut commands, &mut ew_end_hequn, &mut ew_end_zhandi, &mut ew_end_xingxiang) {
+                                warn!("cannot start the accepted game: {}", e);
+                            }
+                            *rematch = Some(request);
                         },
+                        // 对局中的消息由 route_game_messages 处理
+                        _ => {},
                     }
                 }
 
-                for event in er_step.read() {
-                    // info!("send step: {}", event.step);
-                    let message = Message::Step(event.step.clone());
-                    let message = serde_json::to_string(&message).unwrap();
-                    ew_net.write(SendNetMsgEvent { message });
-                }
-
                 egui::ComboBox::from_label("Choose a game")
                     .selected_text(format!("{:?}", game)) // use debug trait
                     .show_ui(ui, |ui| {
@@ -272,46 +503,212 @@ pub fn ui_menu(
                         ui.selectable_value(order, PlayerOrder::Second, "Second");
                     });
 
-                if ui.button("Send Remote Game Invitation").clicked() {
-                    let message = Message::CreateNewGame { 
-                        game_name: format!("{:?}", game), 
+                ui.horizontal(|ui| {
+                    ui.label("You play as:");
+                    controller_ui(ui, "net_local", net_local);
+                });
+
+                time_control_ui(ui, time_control);
+
+                egui::ComboBox::from_label("Start from")
+                    .selected_text(format!("{:?}", start_from))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(start_from, StartFrom::Initial, "Initial");
+                        ui.selectable_value(start_from, StartFrom::CurrentGame, "CurrentGame");
+                        ui.selectable_value(start_from, StartFrom::Fen, "Fen");
+                        ui.selectable_value(start_from, StartFrom::Moves, "Moves");
+                    });
+                match start_from {
+                    StartFrom::Initial | StartFrom::Moves => rules_ui(ui, game, hequn_swap_rule, zhandi_komi),
+                    StartFrom::CurrentGame => { ui.label(format!("Continue the current game: {}", running_game_name(running_game))); },
+                    StartFrom::Fen => {},
+                }
+                match start_from {
+                    StartFrom::Fen => { ui.add(egui::TextEdit::singleline(start_text).hint_text("FEN")); },
+                    StartFrom::Moves => { ui.add(egui::TextEdit::multiline(start_text).hint_text("moves separated by spaces")); },
+                    _ => {},
+                }
+
+                // 继续当前对局时棋的种类由当前对局决定
+                let game_name = match start_from {
+                    StartFrom::CurrentGame => running_game_name(running_game).to_string(),
+                    _ => format!("{:?}", game),
+                };
+                let supported = session.as_ref().is_none_or(|session| session.peer_supports(&game_name));
+                if !supported {
+                    ui.label("The opponent cannot play this game");
+                }
+                if ui.add_enabled(supported, egui::Button::new("Send Remote Game Invitation")).clicked() {
+                    let start = match start_from {
+                        StartFrom::Initial => GameStart::Initial,
+                        StartFrom::CurrentGame => current_tree(running_game, &mut q_games).map_or(GameStart::Initial, GameStart::Tree),
+                        StartFrom::Fen => GameStart::Fen(start_text.trim().to_string()),
+                        StartFrom::Moves => GameStart::Moves(start_text.split_whitespace().map(String::from).collect()),
+                    };
+                    let request = GameRequest {
+                        game_name,
                         player_order: *order == PlayerOrder::First,
+                        time_control: *time_control,
+                        start,
+                        rules: GameRules { hequn_swap_rule: *hequn_swap_rule, zhandi_komi: *zhandi_komi },
                     };
-                    let message = serde_json::to_string(&message).unwrap();
-                    ew_net.write(SendNetMsgEvent { message });
+                    // 局面或着法不合法时不发出邀请
+                    match check_start(&request) {
+                        Ok(()) => {
+                            *invitation_error = None;
+                            send_request(&mut ew_net, &request);
+                        },
+                        Err(e) => *invitation_error = Some(e.to_string()),
+                    }
+                }
+                if let Some(error) = invitation_error {
+                    ui.colored_label(egui::Color32::RED, format!("Invalid start: {}", error));
                 }
 
-                if let Some(request) = game_request {
+                if let Some(request) = rematch {
+                    if ui.button("Rematch (swap colors)").clicked() {
+                        send_request(&mut ew_net, request);
+                    }
+                }
+
+                if let Some((request, problem)) = game_request {
                     ui.label(format!("Receive Remote Game Invitation: {}", request.game_name));
                     ui.label(format!("Your Order: {}", if request.player_order { "Second" } else { "First" }));
-                    if ui.button("Accept").clicked() {
+                    ui.label(format!("Clock: {}", request.time_control.map_or(String::from("none"), |control| control.to_string())));
+                    ui.label(format!("Start: {}", request.start.describe()));
+                    if matches!(request.start, GameStart::Initial | GameStart::Moves(_)) {
+                        ui.label(format!("Rules: {}", request.rules.describe(&request.game_name)));
+                    }
+                    if let Some(problem) = problem {
+                        ui.colored_label(egui::Color32::RED, format!("Cannot start this game: {}", problem));
+                    }
+                    if ui.add_enabled(problem.is_none(), egui::Button::new("Accept")).clicked() {
                         let message = Message::AcceptCreateNewGame { 
                             game_name: request.game_name.clone(), 
                             player_order: !request.player_order, 
+                            time_control: request.time_control,
+                            start: request.start.clone(),
+                            rules: request.rules.clone(),
                         };
                         let message = serde_json::to_string(&message).unwrap();
                         ew_net.write(SendNetMsgEvent { message });
-                        let remote_play = Some(if request.player_order { PlayerOrder::First } else { PlayerOrder::Second });
-                        start_game(running_game, &request.game_name, remote_play, &mut commands, &mut ew_end_hequn, &mut ew_end_zhandi, &mut ew_end_xingxiang);
+                        let seats = Seats::remote(if request.player_order { PlayerOrder::First } else { PlayerOrder::Second }, *net_local);
+                        if let Err(e) = start_game(running_game, request, seats, &mut commands, &mut ew_end_hequn, &mut ew_end_zhandi, &mut ew_end_xingxiang) {
+                            warn!("cannot start the accepted game: {}", e);
+                        }
+                        *rematch = Some(request.clone());
                     }
                 }
 
                 ui.checkbox(sl_window_open, "show SL window");
             } // if connected
+
+            if watching {
+                *tree_window_open = false;
+                *analysis_window_open = false;
+                *hequn_window_open = false;
+                *zhandi_window_open = false;
+                *xingxiang_window_open = false;
+
+                // 观众第一次收到游戏树或者收到另一种棋的游戏树时开始一盘双方都由对方执掌的对局，再请求对方重新发送游戏树
+                for event in er_net.read() {
+                    let Ok(Message::SyncTree { game: board_name, .. }) = serde_json::from_str(&event.message) else { continue; };
+                    // 游戏树中是棋盘的名字，开始对局用菜单中的游戏名
+                    let Some(game_name) = GAMES.into_iter().find(|game| game.eq_ignore_ascii_case(&board_name)) else { continue; };
+                    if !*watched || running_game_name(running_game) != game_name {
+                        *watched = true;
+                        let seats = Seats { first: Controller::Remote, second: Controller::Remote };
+                        let request = GameRequest { game_name: game_name.to_string(), player_order: true, time_control: None, start: GameStart::Initial, rules: GameRules::default() };
+                        if let Err(e) = start_game(running_game, &request, seats, &mut commands, &mut ew_end_hequn, &mut ew_end_zhandi, &mut ew_end_xingxiang) {
+                            warn!("cannot start watching: {}", e);
+                        }
+                        let message = serde_json::to_string(&Message::SyncRequest).unwrap();
+                        ew_net.write(SendNetMsgEvent { message });
+                    }
+                }
+
+                ui.label("Watching as a spectator");
+                ui.checkbox(sl_window_open, "show SL window");
+            } else {
+                *watched = false;
+            } // if watching
         });
 
     Ok(())
 }
 
+fn running_game_name(running_game: &Option<Game>) -> &'static str {
+    match running_game {
+        Some(Game::Hequn(_)) => "Hequn",
+        Some(Game::Zhandi(_)) => "Zhandi",
+        Some(Game::Xingxiang(_)) => "Xingxiang",
+        None => "None",
+    }
+}
+
+// 本地的新对局和网络对局邀请中的规则选项
+fn rules_ui(ui: &mut egui::Ui, game: &GameTitle, hequn_swap_rule: &mut bool, zhandi_komi: &mut usize) {
+    match game {
+        GameTitle::Hequn => { ui.checkbox(hequn_swap_rule, "Three-move swap opening"); },
+        GameTitle::Zhandi => {
+            ui.horizontal(|ui| {
+                ui.label("Komi:");
+                ui.add(egui::DragValue::new(zhandi_komi).range(0..=60));
+            });
+        },
+        GameTitle::Xingxiang => {},
+    }
+}
+
+fn current_tree(running_game: &Option<Game>, q_games: &mut (Query<&mut HequnGame>, Query<&mut ZhandiGame>, Query<&mut XingxiangGame>)) -> Option<String> {
+    match *running_game {
+        Some(Game::Hequn(entity)) => q_games.0.get_mut(entity).ok().map(|mut game| game.tree().to_string()),
+        Some(Game::Zhandi(entity)) => q_games.1.get_mut(entity).ok().map(|mut game| game.tree().to_string()),
+        Some(Game::Xingxiang(entity)) => q_games.2.get_mut(entity).ok().map(|mut game| game.tree().to_string()),
+        None => None,
+    }
+}
+
+fn send_request(ew_net: &mut EventWriter<SendNetMsgEvent>, request: &GameRequest) {
+    let message = Message::CreateNewGame {
+        game_name: request.game_name.clone(),
+        player_order: request.player_order,
+        time_control: request.time_control,
+        start: request.start.clone(),
+        rules: request.rules.clone(),
+    };
+    let message = serde_json::to_string(&message).unwrap();
+    ew_net.write(SendNetMsgEvent { message });
+}
+
+pub fn hequn_initial(rules: &GameRules) -> HequnBoard {
+    if rules.hequn_swap_rule { HequnBoard::swap_opening() } else { HequnBoard::default() }
+}
+
+pub fn zhandi_initial(rules: &GameRules) -> ZhandiBoard {
+    ZhandiBoard::default().with_komi(rules.zhandi_komi)
+}
+
+// 检查对局能否按邀请中的开始局面和规则开始
+fn check_start(request: &GameRequest) -> Result<(), XinqiParseError> {
+    match request.game_name.as_str() {
+        "Hequn" => request.start.build_tree(hequn_initial(&request.rules)).map(|_| ()),
+        "Zhandi" => request.start.build_tree(zhandi_initial(&request.rules)).map(|_| ()),
+        "Xingxiang" => request.start.build_tree(XingxiangBoard::default()).map(|_| ()),
+        name => Err(XinqiParseError::new("game", format!("unknown game \"{}\"", name))),
+    }
+}
+
 fn start_game(
     running_game: &mut Option<Game>,
-    game_name: &String,
-    remote_play: Option<PlayerOrder>,
+    request: &GameRequest,
+    seats: Seats,
     commands: &mut Commands,
     ew_end_hequn: &mut EventWriter<EndHequnGame>,
     ew_end_zhandi: &mut EventWriter<EndZhandiGame>,
     ew_end_xingxiang: &mut EventWriter<EndXingxiangGame>,
-) {
+) -> Result<(), XinqiParseError> {
+    check_start(request)?;
     match running_game {
         Some(old_game) => {
             match old_game {
@@ -328,22 +725,27 @@ fn start_game(
         },
         None => {},
     }
-    match game_name.as_str() {
+    let time_control = request.time_control;
+    match request.game_name.as_str() {
         "Hequn" => {
+            let tree = request.start.build_tree(hequn_initial(&request.rules))?;
             *running_game = Some(Game::Hequn(commands.spawn((
-                HequnGame::new(remote_play),
+                HequnGame::with_tree(tree, seats).with_time_control(time_control),
             )).id()));
         },
         "Zhandi" => {
+            let tree = request.start.build_tree(zhandi_initial(&request.rules))?;
             *running_game = Some(Game::Zhandi(commands.spawn((
-                ZhandiGame::new(remote_play),
+                ZhandiGame::with_tree(tree, seats).with_time_control(time_control),
             )).id()));
         },
         "Xingxiang" => {
+            let tree = request.start.build_tree(XingxiangBoard::default())?;
             *running_game = Some(Game::Xingxiang(commands.spawn((
-                XingxiangGame::new(remote_play),
+                XingxiangGame::with_tree(tree, seats).with_time_control(time_control),
             )).id()));
         },
         _ => { *running_game = None; },
     }
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,479 @@
+// 网络会话记录的回放：逐条查看收发的消息，重建到某一条为止的对局，并检查每一步的局面哈希
+use std::path::{Path, PathBuf};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    general::{board::*, PlayerOrder},
+    hequn::general::HequnBoard,
+    net::{chat::format_time, message::{ClockMessage, GameAction, GameRules, GameStart, Message}, record::*, session::fen_hash},
+    tree::{file::FileFormat, game_tree::{ChatLine, GameTree}},
+    ui::{ui_file::FileSettings, ui_menu::{hequn_initial, zhandi_initial, UiMenuState}},
+    xingxiang::general::XingxiangBoard,
+    zhandi::general::ZhandiBoard,
+};
+
+// 消息列表中每条消息最多显示的字符数
+const PREVIEW_CHARS: usize = 80;
+
+#[derive(Resource)]
+pub struct UiReplayState {
+    logs: Vec<PathBuf>,
+    logs_dirty: bool, // 需要重新读取目录
+    path: Option<PathBuf>,
+    entries: Vec<LogEntry>,
+    position: usize, // 回放到第几条记录（包括这一条）
+    replay: Option<Result<Replay, String>>, // 回放到 position 的结果，position 改变时重新计算
+    message: String,
+}
+
+impl Default for UiReplayState {
+    fn default() -> Self {
+        Self {
+            logs: Vec::new(),
+            logs_dirty: true,
+            path: None,
+            entries: Vec::new(),
+            position: 0,
+            replay: None,
+            message: String::new(),
+        }
+    }
+}
+
+// 回放得到的对局
+struct Replay {
+    game: String,
+    plies: usize,
+    fen: String,
+    result: String,
+    tree: String,
+    notes: Vec<String>, // 回放中发现的问题，例如局面哈希不一致
+}
+
+// 记录保存在对局目录下，目录改变时跟着改变
+pub fn sync_netlog_dir(settings: Res<FileSettings>, mut recording: ResMut<NetRecording>) {
+    let dir = settings.netlog_dir();
+    if recording.dir != dir {
+        recording.dir = dir;
+    }
+}
+
+pub fn ui_replay(
+    mut contexts: EguiContexts,
+    mut ui_menu: ResMut<UiMenuState>,
+    mut state: ResMut<UiReplayState>,
+    recording: Res<NetRecording>,
+    settings: Res<FileSettings>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+
+    if !ui_menu.replay_window_open {
+        state.logs_dirty = true;
+        return Ok(());
+    }
+    let state = &mut *state;
+    if state.logs_dirty {
+        state.logs = list_logs(&recording.dir);
+        state.logs_dirty = false;
+    }
+    if state.replay.is_none() && !state.entries.is_empty() {
+        state.replay = Some(replay(&state.entries, state.position));
+    }
+
+    let mut open = true;
+    let mut load_path: Option<PathBuf> = None;
+    let mut open_tree = None;
+
+    egui::Window::new("Replay")
+        .open(&mut open)
+        .default_width(480.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("Logs in {}", recording.dir.display()));
+                if ui.button("Refresh").clicked() {
+                    state.logs_dirty = true;
+                }
+            });
+            egui::ScrollArea::vertical()
+                .id_salt("replay_logs")
+                .max_height(100.0)
+                .show(ui, |ui| {
+                    if state.logs.is_empty() {
+                        ui.label("no recorded sessions");
+                    }
+                    for path in &state.logs {
+                        let name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string());
+                        let selected = state.path.as_ref() == Some(path);
+                        if ui.selectable_label(selected, name).clicked() {
+                            load_path = Some(path.clone());
+                        }
+                    }
+                });
+            if !state.message.is_empty() {
+                ui.label(state.message.clone());
+            }
+            if state.entries.is_empty() {
+                return;
+            }
+
+            ui.separator();
+
+            let last = state.entries.len() - 1;
+            let mut position = state.position;
+            ui.horizontal(|ui| {
+                if ui.button("|<").clicked() {
+                    position = 0;
+                }
+                if ui.button("<").clicked() {
+                    position = position.saturating_sub(1);
+                }
+                if ui.button(">").clicked() {
+                    position = (position + 1).min(last);
+                }
+                if ui.button(">|").clicked() {
+                    position = last;
+                }
+                ui.add(egui::Slider::new(&mut position, 0..=last));
+            });
+            egui::ScrollArea::vertical()
+                .id_salt("replay_entries")
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for (i, entry) in state.entries.iter().enumerate() {
+                        let response = ui.selectable_label(i == position, describe(entry));
+                        if response.clicked() {
+                            position = i;
+                        }
+                        if i == position && position != state.position {
+                            response.scroll_to_me(None);
+                        }
+                    }
+                });
+            if position != state.position {
+                state.position = position;
+                state.replay = None;
+            }
+
+            ui.separator();
+
+            match &state.replay {
+                Some(Ok(replay)) => {
+                    ui.label(format!("{}, {} moves, result {}", replay.game, replay.plies, replay.result));
+                    ui.label(format!("FEN: {}", replay.fen));
+                    for note in &replay.notes {
+                        ui.colored_label(egui::Color32::YELLOW, note);
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Open in game tree").clicked() {
+                            open_tree = Some((replay.game.clone(), replay.tree.clone()));
+                        }
+                        if ui.button("Export PGN").clicked() {
+                            state.message = export(replay, state.path.as_deref(), &settings);
+                        }
+                    });
+                },
+                Some(Err(e)) => {
+                    ui.label(e);
+                },
+                None => {},
+            }
+        });
+
+    if let Some(path) = load_path {
+        match read_log(&path) {
+            Ok(entries) => {
+                state.position = entries.len().saturating_sub(1);
+                state.entries = entries;
+                state.message = String::new();
+            },
+            Err(e) => {
+                state.entries = Vec::new();
+                state.message = format!("{}: {}", path.display(), e);
+            },
+        }
+        state.path = Some(path);
+        state.replay = None;
+    }
+    if let Some(tree) = open_tree {
+        ui_menu.replay_tree = Some(tree);
+    }
+    ui_menu.replay_window_open = open;
+
+    Ok(())
+}
+
+// 消息列表中的一行
+fn describe(entry: &LogEntry) -> String {
+    let time = format_time(entry.time / 1000);
+    let text = match &entry.event {
+        LogEvent::Start { role, target } => format!("start as {} ({})", role, target),
+        LogEvent::Connected { peer, encrypted } => {
+            format!("connected to {}{}", peer, if *encrypted { ", encrypted" } else { "" })
+        },
+        LogEvent::Sent { message } => format!("> {}", message),
+        LogEvent::Received { message } => format!("< {}", message),
+        LogEvent::Closed { reason } => format!("closed: {}", reason),
+    };
+    let mut line = format!("[{}] {}", time, text);
+    if let Some((i, _)) = line.char_indices().nth(PREVIEW_CHARS) {
+        line.truncate(i);
+        line.push_str("...");
+    }
+    line
+}
+
+// 从最后一次开始对局（观众是最后一次收到游戏树）的地方回放到第 upto 条记录
+fn replay(entries: &[LogEntry], upto: usize) -> Result<Replay, String> {
+    let messages: Vec<(usize, u64, bool, Message)> = entries.iter()
+        .take(upto + 1)
+        .enumerate()
+        .filter_map(|(i, entry)| entry.message().map(|(sent, message)| (i, entry.time, sent, message)))
+        .collect();
+    let start = messages.iter().rposition(|(_, _, _, message)| matches!(message, Message::AcceptCreateNewGame { .. }))
+        .or_else(|| messages.iter().rposition(|(_, _, _, message)| matches!(message, Message::SyncTree { .. })))
+        .ok_or("no game has started yet")?;
+
+    // 双方的名字，用于聊天记录
+    let mut names = (String::from("Me"), String::from("Opponent"));
+    for (_, _, sent, message) in &messages {
+        if let Message::Hello(hello) = message {
+            if *sent { names.0 = hello.name.clone() } else { names.1 = hello.name.clone() }
+        }
+    }
+
+    let (game, start_from, rules, local_first) = match &messages[start] {
+        (_, _, sent, Message::AcceptCreateNewGame { game_name, player_order, start, rules, .. }) => {
+            (game_name.clone(), start.clone(), rules.clone(), Some(*sent == *player_order))
+        },
+        (_, _, _, Message::SyncTree { game, tree }) => (game.clone(), GameStart::Tree(tree.clone()), GameRules::default(), None),
+        _ => unreachable!(),
+    };
+    let rest = &messages[start + 1..];
+    let build_error = |e| format!("failed to start the game: {}", e);
+    // 开始对局的请求用菜单中的游戏名，游戏树用棋盘的名字
+    match game.to_lowercase().as_str() {
+        HequnBoard::NAME => {
+            let tree = start_from.build_tree(hequn_initial(&rules)).map_err(build_error)?;
+            Ok(replay_game("Hequn", tree, rest, local_first, &names))
+        },
+        ZhandiBoard::NAME => {
+            let tree = start_from.build_tree(zhandi_initial(&rules)).map_err(build_error)?;
+            Ok(replay_game("Zhandi", tree, rest, local_first, &names))
+        },
+        XingxiangBoard::NAME => {
+            let tree = start_from.build_tree(XingxiangBoard::default()).map_err(build_error)?;
+            Ok(replay_game("Xingxiang", tree, rest, local_first, &names))
+        },
+        _ => Err(format!("unknown game {}", game)),
+    }
+}
+
+fn loss(player: PlayerOrder) -> &'static str {
+    match player {
+        PlayerOrder::First => "0-1",
+        PlayerOrder::Second => "1-0",
+    }
+}
+
+// 按对局中的处理方式依次执行着法、认输、和棋、悔棋、超时和聊天
+fn replay_game<B: Board>(
+    game: &str,
+    mut tree: GameTree<B>,
+    messages: &[(usize, u64, bool, Message)],
+    local_first: Option<bool>,
+    names: &(String, String),
+) -> Replay {
+    let mut notes = Vec::new();
+    for (entry, time, sent, message) in messages {
+        match message {
+            Message::Step { step, ply, hash } => {
+                let plies = tree.mainline_plies();
+                // 重新连接后补发的着法可能已经走过
+                if *ply <= plies {
+                    continue;
+                }
+                if *ply > plies + 1 {
+                    notes.push(format!("entry {}: move {} arrived after move {}", entry, ply, plies));
+                }
+                tree.focus_mainline_end();
+                let played = tree.board().read_step(step.clone()).map(|step| tree.try_move(step));
+                match played {
+                    Ok(true) => {
+                        if fen_hash(&tree.board().write_fen()) != *hash {
+                            notes.push(format!("entry {}: positions differ after move {}", entry, ply));
+                        }
+                    },
+                    _ => notes.push(format!("entry {}: move {} \"{}\" could not be played", entry, ply, step)),
+                }
+            },
+            Message::SyncTree { tree: synced, .. } => {
+                match GameTree::<B>::from_string(synced.clone()) {
+                    Ok(synced) => {
+                        tree = synced;
+                        tree.focus_mainline_end();
+                        notes.push(format!("entry {}: game tree resynchronized", entry));
+                    },
+                    Err(e) => notes.push(format!("entry {}: invalid game tree: {}", entry, e)),
+                }
+            },
+            Message::Action(action) => {
+                let Some(local_first) = local_first else { continue; };
+                let local = if local_first { PlayerOrder::First } else { PlayerOrder::Second };
+                match action {
+                    GameAction::Resign => {
+                        let player = if *sent { local } else { local.flip() };
+                        tree.set_result(loss(player), "resignation");
+                    },
+                    GameAction::AcceptDraw => tree.set_result("1/2-1/2", "agreement"),
+                    GameAction::AcceptTakeback { plies } if !tree.take_back(*plies) => {
+                        notes.push(format!("entry {}: takeback to move {} failed", entry, plies));
+                    },
+                    _ => {},
+                }
+            },
+            Message::Clock(ClockMessage::Timeout { player_order }) => {
+                let player = if *player_order { PlayerOrder::First } else { PlayerOrder::Second };
+                tree.set_result(loss(player), "time forfeit");
+            },
+            Message::Chat { text } => {
+                let sender = if *sent { names.0.clone() } else { names.1.clone() };
+                tree.add_chat(ChatLine { time: time / 1000, sender, text: text.clone() });
+            },
+            _ => {},
+        }
+    }
+    tree.focus_mainline_end();
+    Replay {
+        game: game.to_string(),
+        plies: tree.mainline_plies(),
+        fen: tree.board().write_fen(),
+        result: tree.result(),
+        tree: tree.to_string(),
+        notes,
+    }
+}
+
+// 回放得到的对局保存为对局目录下和记录同名的 PGN 文件
+fn export(replay: &Replay, log: Option<&Path>, settings: &FileSettings) -> String {
+    let stem = log.and_then(|log| log.file_stem()).map_or(String::from("replay"), |stem| stem.to_string_lossy().to_string());
+    let path = Path::new(&settings.games_dir).join(stem).with_extension(FileFormat::Pgn.extension());
+    let saved = match replay.game.as_str() {
+        "Hequn" => GameTree::<HequnBoard>::from_string(replay.tree.clone()).map_err(|e| e.to_string()).and_then(|tree| tree.save_file(&path)),
+        "Zhandi" => GameTree::<ZhandiBoard>::from_string(replay.tree.clone()).map_err(|e| e.to_string()).and_then(|tree| tree.save_file(&path)),
+        "Xingxiang" => GameTree::<XingxiangBoard>::from_string(replay.tree.clone()).map_err(|e| e.to_string()).and_then(|tree| tree.save_file(&path)),
+        game => Err(format!("unknown game {}", game)),
+    };
+    match saved {
+        Ok(()) => format!("saved to {}", path.display()),
+        Err(e) => format!("{}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: u64, sent: bool, message: Message) -> LogEntry {
+        let message = serde_json::to_string(&message).unwrap();
+        let event = if sent { LogEvent::Sent { message } } else { LogEvent::Received { message } };
+        LogEntry { time, event }
+    }
+
+    // 在 tree 上走一步，返回对应的 Step 消息
+    fn step(tree: &mut GameTree<HequnBoard>, s: &str) -> Message {
+        let step = tree.board().read_step(s.to_string()).unwrap();
+        assert!(tree.try_move(step));
+        Message::Step { step: s.to_string(), ply: tree.mainline_plies(), hash: fen_hash(&tree.board().write_fen()) }
+    }
+
+    fn new_game(player_order: bool) -> Message {
+        Message::AcceptCreateNewGame {
+            game_name: String::from("Hequn"),
+            player_order,
+            time_control: None,
+            start: GameStart::Initial,
+            rules: GameRules::default(),
+        }
+    }
+
+    #[test]
+    fn replays_the_last_new_game() {
+        let mut played = GameTree::new(HequnBoard::default());
+        let d4 = step(&mut played, "d4");
+        let e5 = step(&mut played, "e5");
+        let old = GameTree::new(HequnBoard::default()).to_string();
+        let entries = vec![
+            LogEntry { time: 0, event: LogEvent::Start { role: String::from("host"), target: String::from("127.0.0.1:0") } },
+            entry(1_000, true, Message::SyncTree { game: String::from("hequn"), tree: old }),
+            // 本地发出的同意请求中 player_order 为真，本地执先手
+            entry(2_000, true, new_game(true)),
+            entry(3_000, true, d4),
+            entry(4_000, false, Message::Step { step: String::from("e5"), ply: 2, hash: 0 }),
+            entry(5_000, false, e5),
+            entry(6_000, false, Message::Chat { text: String::from("hi") }),
+            entry(7_000, true, Message::Action(GameAction::Resign)),
+        ];
+        assert!(replay(&entries, 1).is_ok());
+        assert!(replay(&entries, 0).is_err());
+
+        let before_resign = replay(&entries, 6).unwrap();
+        assert_eq!(before_resign.result, "*");
+
+        let replayed = replay(&entries, entries.len() - 1).unwrap();
+        assert_eq!(replayed.game, "Hequn");
+        assert_eq!(replayed.plies, 2);
+        assert_eq!(replayed.fen, played.board().write_fen());
+        assert_eq!(replayed.result, "0-1");
+        // 哈希错误的那一步被记下，重复的第二步被跳过
+        assert_eq!(replayed.notes, vec![String::from("entry 4: positions differ after move 2")]);
+        let tree = GameTree::<HequnBoard>::from_string(replayed.tree).unwrap();
+        assert_eq!(tree.chat().len(), 1);
+        assert_eq!(tree.chat()[0].sender, "Opponent");
+        assert_eq!(tree.tag("Termination"), Some("resignation"));
+    }
+
+    #[test]
+    fn takebacks_draws_and_timeouts() {
+        let mut played = GameTree::new(HequnBoard::default());
+        let moves: Vec<Message> = ["d4", "e5", "f6"].into_iter().map(|s| step(&mut played, s)).collect();
+        // 对方发出的同意请求中 player_order 为真，本地执后手
+        let mut entries = vec![entry(0, false, new_game(true))];
+        entries.extend(moves.into_iter().enumerate().map(|(i, m)| entry(i as u64, i % 2 == 1, m)));
+        entries.push(entry(10, true, Message::Action(GameAction::AcceptTakeback { plies: 1 })));
+        entries.push(entry(11, false, Message::Action(GameAction::AcceptTakeback { plies: 5 })));
+        entries.push(entry(12, false, Message::Action(GameAction::Resign)));
+
+        let replayed = replay(&entries, entries.len() - 1).unwrap();
+        assert_eq!(replayed.plies, 1);
+        assert_eq!(replayed.notes, vec![String::from("entry 5: takeback to move 5 failed")]);
+        // 对方执先手认输
+        assert_eq!(replayed.result, "0-1");
+
+        entries.truncate(5);
+        entries.push(entry(12, true, Message::Action(GameAction::AcceptDraw)));
+        assert_eq!(replay(&entries, 5).unwrap().result, "1/2-1/2");
+
+        entries.truncate(5);
+        entries.push(entry(12, false, Message::Clock(ClockMessage::Timeout { player_order: false })));
+        assert_eq!(replay(&entries, 5).unwrap().result, "1-0");
+    }
+
+    #[test]
+    fn sync_tree_starts_a_game_without_a_new_game() {
+        let mut played = GameTree::new(HequnBoard::default());
+        step(&mut played, "d4");
+        let synced = played.to_string();
+        let e5 = step(&mut played, "e5");
+        let entries = vec![
+            entry(0, false, Message::SyncTree { game: String::from("hequn"), tree: synced }),
+            entry(1, false, e5),
+            // 没有开始对局的请求时不知道哪一方是本地，忽略认输
+            entry(2, true, Message::Action(GameAction::Resign)),
+        ];
+        let replayed = replay(&entries, 2).unwrap();
+        assert_eq!(replayed.plies, 2);
+        assert_eq!(replayed.result, "*");
+        assert!(replayed.notes.is_empty());
+    }
+}
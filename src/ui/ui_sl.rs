@@ -1,8 +1,8 @@
-use std::marker::PhantomData;
-use bevy::prelude::*;
+use std::{marker::PhantomData, path::{Path, PathBuf}};
+use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_egui::{egui, EguiContexts};
 use crate::{
-    general::{board::*, game::Game as GameTrait}, net::NetState, tree::{game_tree::GameTree, game_tree_event::MoveToNodeEvent}, ui::ui_menu::UiMenuState
+    general::{board::*, game::Game as GameTrait}, net::NetState, tree::{file::FileFormat, game_tree::GameTree, game_tree_event::MoveToNodeEvent}, ui::{ui_file::*, ui_menu::UiMenuState}
 };
 
 #[derive(Resource)]
@@ -13,6 +13,11 @@ pub struct UiSlState<G: GameTrait> {
     load_pgn_error: String,
     load_tree: String, 
     load_tree_error: String,
+    file_name: String,
+    file_message: String,
+    files: Vec<PathBuf>,
+    autosaves: Vec<PathBuf>,
+    files_dirty: bool, // 需要重新读取目录
     _marker: PhantomData<G>,
 }
 
@@ -25,11 +30,23 @@ impl<G: GameTrait> Default for UiSlState<G> {
             load_pgn_error: Default::default(), 
             load_tree: Default::default(), 
             load_tree_error: Default::default(), 
+            file_name: Default::default(),
+            file_message: Default::default(),
+            files: Vec::new(),
+            autosaves: Vec::new(),
+            files_dirty: true,
             _marker: PhantomData,
         }
     }
 }
 
+// 文件设置和自动保存
+#[derive(SystemParam)]
+pub struct SlFiles<'w, G: GameTrait> {
+    settings: ResMut<'w, FileSettings>,
+    autosave: ResMut<'w, Autosave<G>>,
+}
+
 pub fn ui_sl<G: GameTrait>(
     mut contexts: EguiContexts,
     mut ui_menu: ResMut<UiMenuState>,
@@ -37,8 +54,10 @@ pub fn ui_sl<G: GameTrait>(
     mut q_game: Query<&mut G>,
     mut ew_mtn: EventWriter<MoveToNodeEvent>,
     net_state: Res<NetState>,
+    files: SlFiles<G>,
 ) -> Result {
     let ctx = contexts.ctx_mut()?;
+    let SlFiles { mut settings, mut autosave } = files;
 
     let Ok(mut game) = q_game.single_mut() else {
         return Ok(())
@@ -48,8 +67,18 @@ pub fn ui_sl<G: GameTrait>(
         NetState::Disconnected => true,
         NetState::Listening(_, _) => false,
         NetState::Connected(_, _) => false,
+        NetState::Reconnecting(_, _) => false,
     };
 
+    if ui_sl.files_dirty && ui_menu.sl_window_open {
+        ui_sl.files = list_game_files(Path::new(&settings.games_dir));
+        ui_sl.autosaves = list_game_files(&settings.autosave_dir());
+        ui_sl.autosaves.reverse();
+        ui_sl.files_dirty = false;
+    }
+
+    let mut load_path: Option<PathBuf> = None;
+
     egui::Window::new("Save & Load")
         .open(&mut ui_menu.sl_window_open)
         .show(ctx, |ui| {
@@ -58,6 +87,7 @@ pub fn ui_sl<G: GameTrait>(
                     let tree = GameTree::<G::B>::new(G::B::default());
                     *game.tree() = tree;
                     game.tree().move_to_start(&mut ew_mtn);
+                    autosave.new_session();
                 }
 
                 ui.separator();
@@ -71,6 +101,23 @@ pub fn ui_sl<G: GameTrait>(
                 ctx.copy_text(game.tree().to_string());
             }
 
+            if ui.button("Copy PGN").clicked() {
+                ctx.copy_text(game.tree().to_pgn());
+            }
+
+            egui::CollapsingHeader::new("PGN tags").show(ui, |ui| {
+                egui::Grid::new("pgn_tags").show(ui, |ui| {
+                    for name in ["Event", "Date", "Black", "White"] {
+                        ui.label(name);
+                        let mut value = game.tree().tag(name).unwrap_or_default().to_string();
+                        if ui.text_edit_singleline(&mut value).changed() {
+                            game.tree().set_tag(name, value);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
             if disconnected {
                 ui.horizontal(|ui| {
                     ui.label("Load FEN: ");
@@ -85,13 +132,15 @@ pub fn ui_sl<G: GameTrait>(
                 });
                 ui.horizontal(|ui| {
                     if ui.button("Load").clicked() {
-                        if let Some(board) = G::B::read_fen(ui_sl.load_fen.clone()) {
-                            let tree = GameTree::<G::B>::new(board);
-                            *game.tree() = tree;
-                            game.tree().move_to_start(&mut ew_mtn);
-                            ui_sl.load_fen_error = String::new();
-                        } else {
-                            ui_sl.load_fen_error = "invalid FEN".to_string();
+                        match G::B::read_fen(ui_sl.load_fen.clone()) {
+                            Ok(board) => {
+                                let tree = GameTree::<G::B>::new(board);
+                                *game.tree() = tree;
+                                game.tree().move_to_start(&mut ew_mtn);
+                                autosave.new_session();
+                                ui_sl.load_fen_error = String::new();
+                            },
+                            Err(e) => ui_sl.load_fen_error = e.to_string(),
                         }
                     }
                     ui.label(ui_sl.load_fen_error.clone());
@@ -110,12 +159,14 @@ pub fn ui_sl<G: GameTrait>(
                 });
                 ui.horizontal(|ui| {
                     if ui.button("Load").clicked() {
-                        if let Some(tree) = GameTree::<G::B>::from_string(ui_sl.load_tree.clone()) {
-                            *game.tree() = tree;
-                            game.tree().move_to_start(&mut ew_mtn);
-                            ui_sl.load_tree_error = String::new();
-                        } else {
-                            ui_sl.load_tree_error = "invalid tree text".to_string();
+                        match GameTree::<G::B>::from_string(ui_sl.load_tree.clone()) {
+                            Ok(tree) => {
+                                *game.tree() = tree;
+                                game.tree().move_to_start(&mut ew_mtn);
+                                autosave.new_session();
+                                ui_sl.load_tree_error = String::new();
+                            },
+                            Err(e) => ui_sl.load_tree_error = e.to_string(),
                         }
                     }
                     ui.label(ui_sl.load_tree_error.clone());
@@ -123,19 +174,111 @@ pub fn ui_sl<G: GameTrait>(
 
                 ui.horizontal(|ui| {
                     ui.label("Load PGN: ");
-                    ui.text_edit_singleline(&mut ui_sl.load_pgn);
+                    egui::ScrollArea::vertical().id_salt("load_pgn").show(ui, |ui| {
+                        ui.add_sized(
+                            ui.available_size(),
+                            egui::TextEdit::multiline(&mut ui_sl.load_pgn)
+                                .desired_width(f32::INFINITY)
+                                .code_editor(),
+                        );
+                    });
                 });
-                if ui.button("Load").clicked() {
-                    if let Some(tree) = GameTree::<G::B>::from_pgn(ui_sl.load_pgn.clone()) {
-                        *game.tree() = tree;
-                        game.tree().move_to_start(&mut ew_mtn);
-                        ui_sl.load_pgn_error = String::new();
-                    } else {
-                        ui_sl.load_pgn_error = "invalid PGN".to_string();
+                ui.horizontal(|ui| {
+                    if ui.button("Load").clicked() {
+                        match GameTree::<G::B>::from_pgn(ui_sl.load_pgn.clone()) {
+                            Ok(tree) => {
+                                *game.tree() = tree;
+                                game.tree().move_to_start(&mut ew_mtn);
+                                autosave.new_session();
+                                ui_sl.load_pgn_error = String::new();
+                            },
+                            Err(e) => ui_sl.load_pgn_error = e.to_string(),
+                        }
                     }
+                    ui.label(ui_sl.load_pgn_error.clone());
+                });
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Games directory: ");
+                if ui.text_edit_singleline(&mut settings.games_dir).lost_focus() {
+                    settings.save();
+                    ui_sl.files_dirty = true;
                 }
+            });
+            ui.horizontal(|ui| {
+                ui.label("File name: ");
+                ui.text_edit_singleline(&mut ui_sl.file_name);
+            });
+            ui.horizontal(|ui| {
+                for (label, format) in [("Save tree", FileFormat::Tree), ("Save FEN", FileFormat::Fen), ("Save PGN", FileFormat::Pgn)] {
+                    if ui.button(label).clicked() {
+                        if ui_sl.file_name.trim().is_empty() {
+                            ui_sl.file_message = "empty file name".to_string();
+                            continue;
+                        }
+                        let path = Path::new(&settings.games_dir)
+                            .join(ui_sl.file_name.trim())
+                            .with_extension(format.extension());
+                        ui_sl.file_message = match game.tree().save_file(&path) {
+                            Ok(()) => {
+                                settings.add_recent(G::B::NAME, &path);
+                                format!("saved to {}", path.display())
+                            },
+                            Err(e) => format!("{}: {}", path.display(), e),
+                        };
+                        ui_sl.files_dirty = true;
+                    }
+                }
+            });
+            if !ui_sl.file_message.is_empty() {
+                ui.label(ui_sl.file_message.clone());
+            }
+
+            if disconnected {
+                if ui.button("Refresh").clicked() {
+                    ui_sl.files_dirty = true;
+                }
+                egui::CollapsingHeader::new("Files").show(ui, |ui| {
+                    for path in &ui_sl.files {
+                        let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string());
+                        if ui.button(name).clicked() {
+                            load_path = Some(path.clone());
+                        }
+                    }
+                });
+                egui::CollapsingHeader::new("Recent files").show(ui, |ui| {
+                    for file in settings.recent_of(G::B::NAME) {
+                        if ui.button(&file.path).clicked() {
+                            load_path = Some(PathBuf::from(&file.path));
+                        }
+                    }
+                });
+                egui::CollapsingHeader::new("Autosaves").show(ui, |ui| {
+                    for path in &ui_sl.autosaves {
+                        let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string());
+                        if ui.button(name).clicked() {
+                            load_path = Some(path.clone());
+                        }
+                    }
+                });
             }
         });
 
+    if let Some(path) = load_path {
+        ui_sl.file_message = match GameTree::<G::B>::from_file(&path) {
+            Ok(tree) => {
+                *game.tree() = tree;
+                game.tree().move_to_start(&mut ew_mtn);
+                autosave.new_session();
+                settings.add_recent(G::B::NAME, &path);
+                format!("loaded {}", path.display())
+            },
+            Err(e) => format!("{}: {}", path.display(), e),
+        };
+    }
+
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,153 @@
+use std::time::{Duration, Instant};
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{general::{board::*, game::Game as GameTrait, UpdateBoard}, net::{message::{Message, ReceiveRemoteStep}, session::{fen_hash, Session}, spectate::Spectators, ReceiveNetMsgEvent, SendNetMsgEvent}, tree::game_tree::GameTree};
+
+// 收到对方的一步后这么久仍未走到主线上，认为双方局面不一致
+const STEP_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn send(ew_net: &mut EventWriter<SendNetMsgEvent>, message: Message) {
+    let message = serde_json::to_string(&message).unwrap();
+    ew_net.write(SendNetMsgEvent { message });
+}
+
+// 同步检查收发的网络消息和更新棋盘的事件
+#[derive(SystemParam)]
+pub struct SyncEvents<'w, 's, G: GameTrait> {
+    er_step: EventReader<'w, 's, ReceiveRemoteStep>,
+    er_net: EventReader<'w, 's, ReceiveNetMsgEvent>,
+    ew_net: EventWriter<'w, SendNetMsgEvent>,
+    ew_update: EventWriter<'w, UpdateBoard<<G as GameTrait>::B>>,
+}
+
+// 用对方发来的步数和局面哈希检查双方的主线是否一致，不一致时以监听一方的游戏树为准重新同步。
+// 断线重连后双方交换主线的步数，并补发对方缺少的着法
+pub fn sync_check<G: GameTrait>(
+    mut q_game: Query<(Entity, &mut G)>,
+    session: Option<ResMut<Session>>,
+    events: SyncEvents<G>,
+    mut pending: Local<Vec<(usize, u64, Instant)>>, // 等待检查的 (步数, 哈希, 收到的时间)
+    mut checked_game: Local<Option<Entity>>,
+) {
+    let SyncEvents { mut er_step, mut er_net, mut ew_net, mut ew_update } = events;
+    let (Ok((entity, mut game)), Some(mut session)) = (q_game.single_mut(), session) else {
+        er_step.clear();
+        er_net.clear();
+        pending.clear();
+        return;
+    };
+    if *checked_game != Some(entity) {
+        *checked_game = Some(entity);
+        pending.clear();
+    }
+
+    if session.resuming {
+        session.resuming = false;
+        send(&mut ew_net, Message::Resume { plies: game.tree().mainline_plies() });
+    }
+
+    let now = Instant::now();
+    pending.extend(er_step.read().map(|event| (event.ply, event.hash, now)));
+
+    let mut desync = None;
+    pending.retain(|&(ply, hash, received)| {
+        match game.tree().mainline_board_at(ply) {
+            Some(board) => {
+                if fen_hash(&board.write_fen()) != hash {
+                    desync = Some(format!("positions differ after move {}", ply));
+                }
+                false
+            },
+            None if now.duration_since(received) > STEP_TIMEOUT => {
+                desync = Some(format!("move {} from the opponent could not be played", ply));
+                false
+            },
+            None => true,
+        }
+    });
+
+    if let Some(reason) = desync {
+        warn!("sync: {}", reason);
+        session.error = Some(format!("Desync: {}, resynchronizing", reason));
+        pending.clear();
+        if session.host {
+            send(&mut ew_net, Message::SyncTree { game: G::B::NAME.to_string(), tree: game.tree().to_string() });
+        } else {
+            send(&mut ew_net, Message::SyncRequest);
+        }
+    }
+
+    for event in er_net.read() {
+        let Ok(message) = serde_json::from_str(&event.message) else { continue; };
+        match message {
+            Message::Resume { plies } => {
+                let steps = game.tree().mainline_steps_after(plies);
+                info!("sync: opponent resumes at move {}, replay {} moves", plies, steps.len());
+                for (i, (step, board)) in steps.into_iter().enumerate() {
+                    send(&mut ew_net, Message::Step { step, ply: plies + i + 1, hash: fen_hash(&board.write_fen()) });
+                }
+            },
+            Message::SyncRequest if session.host => {
+                send(&mut ew_net, Message::SyncTree { game: G::B::NAME.to_string(), tree: game.tree().to_string() });
+            },
+            Message::SyncTree { game: name, tree } if !session.host && name == G::B::NAME => {
+                match GameTree::<G::B>::from_string(tree) {
+                    Ok(mut tree) => {
+                        tree.focus_mainline_end();
+                        let board = tree.board();
+                        *game.tree() = tree;
+                        ew_update.write(UpdateBoard::new(board));
+                        pending.clear();
+                        session.error = None;
+                        session.notice = Some(String::from("Game tree resynchronized with the host"));
+                    },
+                    Err(e) => {
+                        session.error = Some(format!("Failed to resynchronize: {}", e));
+                    },
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+// 监听的一方把对局转播给观众：新加入的观众收到完整的游戏树，之后主线每增加一步发送这一步，
+// 主线被改写（例如悔棋）或者对局结束时重新发送游戏树
+pub fn spectate<G: GameTrait>(
+    mut q_game: Query<(Entity, &mut G)>,
+    mut spectators: ResMut<Spectators>,
+    mut last: Local<Option<(Entity, usize, u64, String)>>, // 上次转播时的 (对局, 主线步数, 主线最后局面的哈希, 结果)
+) {
+    let Ok((entity, mut game)) = q_game.single_mut() else {
+        *last = None;
+        return;
+    };
+    if spectators.is_empty() {
+        *last = None;
+        return;
+    }
+
+    let tree = game.tree();
+    let plies = tree.mainline_plies();
+    let hash = fen_hash(&tree.mainline_board().write_fen());
+    let result = if tree.is_over() { tree.result() } else { String::new() };
+    match last.take() {
+        Some((game, old_plies, old_hash, old_result)) if game == entity && old_result == result => {
+            let extended = plies > old_plies
+                && tree.mainline_board_at(old_plies).is_some_and(|board| fen_hash(&board.write_fen()) == old_hash);
+            if extended {
+                for (i, (step, board)) in tree.mainline_steps_after(old_plies).into_iter().enumerate() {
+                    spectators.broadcast(&Message::Step { step, ply: old_plies + i + 1, hash: fen_hash(&board.write_fen()) });
+                }
+            } else if plies != old_plies || hash != old_hash {
+                spectators.request_all();
+            }
+        },
+        _ => spectators.request_all(),
+    }
+    *last = Some((entity, plies, hash, result));
+
+    if spectators.needs_tree() {
+        spectators.send_tree(&Message::SyncTree { game: G::B::NAME.to_string(), tree: tree.to_string() });
+    }
+}
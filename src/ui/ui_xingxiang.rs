@@ -1,12 +1,15 @@
 use bevy::prelude::*;
 use bevy_egui::{egui::{self}, EguiContexts};
+use bevy_tokio_tasks::TokioTasksRuntime;
 
-use crate::{ai::{mcts::MCTSAI, mctsv2::MCTSv2, AI}, general::Board, xingxiang::{game::XingxiangGame, ai::*}, ui::ui_menu::UiMenuState};
+use crate::{ai::mcts::MCTSAI, general::{Board, Game}, xingxiang::game::XingxiangGame, ui::{ui_ai::UiAiState, ui_menu::UiMenuState}};
 
 pub fn ui_xingxiang(
     mut contexts: EguiContexts,
     mut ui_menu: ResMut<UiMenuState>,
     mut q_xingxiang: Query<&mut XingxiangGame>,
+    mut ai_state: ResMut<UiAiState<XingxiangGame>>,
+    runtime: Res<TokioTasksRuntime>,
 ) -> Result {
     let ctx = contexts.ctx_mut()?;
 
@@ -14,7 +17,9 @@ pub fn ui_xingxiang(
         return Ok(())
     };
 
-    let ai_time_limit_ms = ui_menu.ai_time_limit_ms;
+    let ai_limit = ui_menu.ai_limit();
+    let ai_threads = ui_menu.ai_threads;
+    let engine_command = ui_menu.engine_command.clone();
 
     egui::Window::new("Xingxiang")
         .open(&mut ui_menu.xingxiang_window_open)
@@ -23,17 +28,25 @@ pub fn ui_xingxiang(
 
             ui.label(format!("Now is turn {}", xingxiang.board.get_fullmove()));
 
-            if ui.button("weak ai play").clicked() {
-                let ai = MCTSAI::new();
-                let ai_step = ai.play(xingxiang.board.clone(), ai_time_limit_ms);
-                xingxiang.try_move(ai_step);
-            }
+            if !ai_state.is_thinking() {
+                if ui.button("weak ai play").clicked() {
+                    let seed = ai_state.config.seed;
+                    ai_state.start(&runtime, MCTSAI::new().with_seed(seed), &mut xingxiang, ai_limit);
+                }
+
+                if ui.button("ai play").clicked() {
+                    let config = ai_state.config;
+                    ai_state.start_searcher(&runtime, &mut xingxiang, ai_limit, ai_threads, config);
+                }
 
-            if ui.button("ai play").clicked() {
-                let ai = MCTSv2::new(evaluate, quick_move);
-                let ai_step = ai.play(xingxiang.board.clone(), ai_time_limit_ms);
-                xingxiang.try_move(ai_step);
+                if !engine_command.trim().is_empty() && ui.button("engine play").clicked() {
+                    ai_state.start_engine(&runtime, &mut xingxiang, &engine_command, ai_limit);
+                }
             }
+
+            ai_state.show_thinking(ui);
+            ai_state.show_seats(ui, xingxiang.seats());
+            ai_state.show_config(ui);
         });
 
     Ok(())
@@ -1,12 +1,15 @@
 use bevy::prelude::*;
 use bevy_egui::{egui::{self, Color32}, EguiContexts};
+use bevy_tokio_tasks::TokioTasksRuntime;
 
-use crate::{ai::{mcts::MCTSAI, mctsv2::MCTSv2, AI}, general::Board, ui::ui_menu::UiMenuState, zhandi::{game::ZhandiGame, ai::*}};
+use crate::{ai::mcts::MCTSAI, general::{Board, Game}, ui::{ui_ai::UiAiState, ui_menu::UiMenuState}, zhandi::game::ZhandiGame};
 
 pub fn ui_zhandi(
     mut contexts: EguiContexts,
     mut ui_menu: ResMut<UiMenuState>,
     mut q_zhandi: Query<&mut ZhandiGame>,
+    mut ai_state: ResMut<UiAiState<ZhandiGame>>,
+    runtime: Res<TokioTasksRuntime>,
 ) -> Result {
     let ctx = contexts.ctx_mut()?;
 
@@ -14,7 +17,9 @@ pub fn ui_zhandi(
         return Ok(())
     };
 
-    let ai_time_limit_ms = ui_menu.ai_time_limit_ms;
+    let ai_limit = ui_menu.ai_limit();
+    let ai_threads = ui_menu.ai_threads;
+    let engine_command = ui_menu.engine_command.clone();
 
     egui::Window::new("Zhandi")
         .open(&mut ui_menu.zhandi_window_open)
@@ -28,20 +33,28 @@ pub fn ui_zhandi(
 
             ui.horizontal(|ui| {
                 ui.colored_label(Color32::from_rgb(127, 246, 244), "■");
-                ui.label(format!("White: {}", zhandi.board.white_score));
+                ui.label(format!("White: {} (komi {})", zhandi.board.white_score, zhandi.board.komi));
             });
 
-            if ui.button("weak ai play").clicked() {
-                let ai = MCTSAI::new();
-                let ai_step = ai.play(zhandi.board.clone(), ai_time_limit_ms);
-                zhandi.try_move(ai_step);
-            }
+            if !ai_state.is_thinking() {
+                if ui.button("weak ai play").clicked() {
+                    let seed = ai_state.config.seed;
+                    ai_state.start(&runtime, MCTSAI::new().with_seed(seed), &mut zhandi, ai_limit);
+                }
+
+                if ui.button("ai play").clicked() {
+                    let config = ai_state.config;
+                    ai_state.start_searcher(&runtime, &mut zhandi, ai_limit, ai_threads, config);
+                }
 
-            if ui.button("ai play").clicked() {
-                let ai = MCTSv2::new(evaluate, quick_move);
-                let ai_step = ai.play(zhandi.board.clone(), ai_time_limit_ms);
-                zhandi.try_move(ai_step);
+                if !engine_command.trim().is_empty() && ui.button("engine play").clicked() {
+                    ai_state.start_engine(&runtime, &mut zhandi, &engine_command, ai_limit);
+                }
             }
+
+            ai_state.show_thinking(ui);
+            ai_state.show_seats(ui, zhandi.seats());
+            ai_state.show_config(ui);
         });
 
     Ok(())
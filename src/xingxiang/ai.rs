@@ -1,6 +1,6 @@
-use crate::{general::Board, xingxiang::{general::*, utils::*}};
+use crate::{ai::Heuristic, general::Board, xingxiang::{general::*, utils::*}};
 
-pub fn evaluate(board: &XingxiangBoard) -> f32 {
+pub fn evaluate_with(board: &XingxiangBoard, temperature: f32) -> f32 {
     let mut res = 0.0;
     let player = board.active_player;
     for x in 0..BOARD_SIZE_I {
@@ -37,10 +37,10 @@ pub fn evaluate(board: &XingxiangBoard) -> f32 {
         }
     }
     // 把目差估计转换为（先手方）胜率估计
-    1.0 / (1.0 + (-res / TEMERATURE).exp())
+    1.0 / (1.0 + (-res / temperature).exp())
 }
 
-const TEMERATURE: f32 = 20.0;
+pub const TEMPERATURE: f32 = 20.0;
 
 pub fn quick_move(board: &XingxiangBoard) -> Vec<XingxiangStep> {
     let all_move = board.all_move();
@@ -124,4 +124,16 @@ pub fn quick_move(board: &XingxiangBoard) -> Vec<XingxiangStep> {
         }
         return res;
     }
-}
\ No newline at end of file
+}
+
+impl Heuristic for XingxiangBoard {
+    const TEMPERATURE: f32 = TEMPERATURE;
+
+    fn evaluate_with(&self, temperature: f32) -> f32 {
+        evaluate_with(self, temperature)
+    }
+
+    fn quick_move(&self) -> Vec<XingxiangStep> {
+        quick_move(self)
+    }
+}
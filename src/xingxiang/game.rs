@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 
 use crate::{
-    general::*, graphics::{entity::{CellCom, Shape}, interaction::{ClickEvent, DragEvent}}, net::message::{ReceiveRemoteStep, SendRemoteStep}, tree::game_tree::GameTree, xingxiang::{draw::*, general::*, utils::*}
+    general::*, graphics::{entity::{CellCom, Shape}, interaction::{ClickEvent, DragEvent}, overlay::*}, net::message::{try_move_and_send, ReceiveRemoteStep, SendRemoteStep}, tree::game_tree::GameTree, xingxiang::{draw::*, general::*, utils::*}
 };
 
 enum GameState {
@@ -23,15 +23,21 @@ pub struct XingxiangGame {
     new_piece: Entity,
     dark_overlay: Entity,
     promotion_choices: Vec<Entity>,
+    overlay: Vec<Entity>,
 
-    remote_play: Option<PlayerOrder>, 
+    seats: Seats,
+    clock: Option<Clock>,
 }
 
 impl XingxiangGame {
-    pub fn new(remote_play: Option<PlayerOrder>) -> Self {
+    pub fn new(seats: Seats) -> Self {
+        Self::with_tree(GameTree::new(XingxiangBoard::default()), seats)
+    }
+    // 从游戏树的焦点处继续对局
+    pub fn with_tree(tree: GameTree<XingxiangBoard>, seats: Seats) -> Self {
         Self {
-            board: XingxiangBoard::default(),
-            tree: GameTree::new(XingxiangBoard::default()),
+            board: tree.board(),
+            tree,
             rect: Rect::from_center_size(Vec2::ZERO, Vec2::new(500.0, 500.0)),
             cells: vec![vec![Entity::PLACEHOLDER; BOARD_SIZE_J]; BOARD_SIZE_I],
             pieces: vec![vec![Entity::PLACEHOLDER; BOARD_SIZE_J]; BOARD_SIZE_I],
@@ -41,13 +47,9 @@ impl XingxiangGame {
             new_piece: Entity::PLACEHOLDER,
             dark_overlay: Entity::PLACEHOLDER,
             promotion_choices: Vec::new(),
-            remote_play,
-        }
-    }
-    pub fn try_move(&mut self, step: XingxiangStep) {
-        if self.tree.try_move(step) {
-            self.updated = false;
-            self.board = self.tree.board();
+            overlay: Vec::new(),
+            seats,
+            clock: None,
         }
     }
 }
@@ -62,6 +64,21 @@ impl Game for XingxiangGame {
     fn board(&self) -> &Self::B {
         &self.board
     }
+
+    fn seats(&mut self) -> &mut Seats {
+        &mut self.seats
+    }
+
+    fn clock(&mut self) -> &mut Option<Clock> {
+        &mut self.clock
+    }
+
+    fn try_move(&mut self, step: XingxiangStep) {
+        if self.tree.try_move(step) {
+            self.updated = false;
+            self.board = self.tree.board();
+        }
+    }
 }
 
 fn piece_sprite(p: XingxiangPiece, cell_size: Vec2, textures: &XingxiangTextureAssets) -> Sprite {
@@ -133,10 +150,7 @@ fn draw(
             };
             game.pieces[x][y] = piece;
 
-            let clickable = match game.remote_play {
-                Some(remote_player) => remote_player != game.board.get_active_player(),
-                None => true,
-            };
+            let clickable = game.accepts_local_input();
             let cell = commands.spawn((
                 CellCom {
                     shape: Shape::Rect { rect: Rect::from_center_size(leftdown + Vec2::new(x as f32 * dx, y as f32 * dy), cell_size) },
@@ -205,6 +219,35 @@ fn draw(
     }
 }
 
+// 在落子位置上显示 AI 候选着法的热度，最佳着法除了标出落子位置，还用半透明的棋子显示变化的目标
+fn draw_overlay(
+    commands: &mut Commands,
+    textures: &XingxiangTextureAssets,
+    game: &mut XingxiangGame,
+    overlay: &BoardOverlay<XingxiangBoard>,
+) {
+    let moves = overlay.moves_for(&game.board);
+    let leftdown = game.rect.center() - game.rect.size() * (7.0 / 16.0);
+    let cell_size = game.rect.size() / 8.0;
+    let (dx, dy) = (cell_size.x, cell_size.y);
+    let center_of = |(x, y): (usize, usize)| leftdown + Vec2::new(x as f32 * dx, y as f32 * dy);
+    for (pos, heat) in heat_per_cell(moves, |step| Some(step.pos)) {
+        game.overlay.push(spawn_heat_cell(commands, center_of(pos), cell_size, heat));
+    }
+    if let Some((step, _)) = moves.first() {
+        game.overlay.push(spawn_best_marker(commands, center_of(step.pos), cell_size));
+        if let Some((target, p)) = step.change {
+            let mut sprite = piece_sprite(p, cell_size, textures);
+            sprite.color = Color::srgba(1.0, 1.0, 1.0, 0.6);
+            game.overlay.push(commands.spawn((
+                sprite,
+                Transform::from_translation(center_of(target).extend(2.5)),
+            )).id());
+            game.overlay.push(spawn_best_marker(commands, center_of(target), cell_size * 0.6));
+        }
+    }
+}
+
 fn clear(
     commands: &mut Commands,
     game: &mut XingxiangGame,
@@ -244,9 +287,13 @@ fn update(
     er_remote: &mut EventReader<ReceiveRemoteStep>,
     ew_remote: &mut EventWriter<SendRemoteStep>,
     textures: &XingxiangTextureAssets,
+    overlay: &Res<BoardOverlay<XingxiangBoard>>,
 ) {
     if !game.board.end {
         for event in er_click.read() {
+            if !game.accepts_local_input() {
+                break;
+            }
             for x in 0..BOARD_SIZE_I {
                 for y in 0..BOARD_SIZE_J {
                     if game.cells[x][y] == event.cell {
@@ -263,10 +310,7 @@ fn update(
                                         pos: (x1, y1),
                                         change: None,
                                     };
-                                    if game.remote_play.is_some() {
-                                        ew_remote.write(SendRemoteStep { step: game.board.write_step(step).unwrap() });
-                                    }
-                                    game.try_move(step);
+                                    try_move_and_send(game, step, ew_remote);
                                     game.state = GameState::S1;
                                 } else {
                                     game.updated = false;
@@ -290,10 +334,7 @@ fn update(
                                         pos: (x1, y1),
                                         change: Some(((x2, y2), p)),
                                     };
-                                    if game.remote_play.is_some() {
-                                        ew_remote.write(SendRemoteStep { step: game.board.write_step(step).unwrap() });
-                                    }
-                                    game.try_move(step);
+                                    try_move_and_send(game, step, ew_remote);
                                     game.state = GameState::S1;
                                 } else {
                                     game.updated = false;
@@ -306,14 +347,12 @@ fn update(
             }
         }
 
-        if let Some(remote_player) = game.remote_play {
-            for event in er_remote.read() {
-                if game.board.get_active_player() == remote_player.flip() {
-                    break;
-                }
-                if let Some(step) = game.board.read_step(event.step.clone()) {
-                    game.try_move(step);
-                }
+        for event in er_remote.read() {
+            if !game.accepts_remote_input() {
+                break;
+            }
+            if let Ok(step) = game.board.read_step(event.step.clone()) {
+                game.try_move(step);
             }
         }
     }
@@ -324,6 +363,11 @@ fn update(
         game.state = GameState::S1;
     }
 
+    if !game.updated || overlay.is_changed() {
+        despawn_overlay(commands, &mut game.overlay);
+        draw_overlay(commands, textures, game, overlay);
+    }
+
     if !game.updated {
         clear(commands, game);
         draw(commands, textures, game);
@@ -340,9 +384,10 @@ pub fn xingxiang_update(
     mut er_remote: EventReader<ReceiveRemoteStep>,
     mut ew_remote: EventWriter<SendRemoteStep>,
     textures: Res<XingxiangTextureAssets>,
+    overlay: Res<BoardOverlay<XingxiangBoard>>,
 ) {
     for mut game in q_game.iter_mut() {
-        update(&mut commands, &mut game, &mut er_drag, &mut er_click, &mut er_update, &mut er_remote, &mut ew_remote, &textures);
+        update(&mut commands, &mut game, &mut er_drag, &mut er_click, &mut er_update, &mut er_remote, &mut ew_remote, &textures, &overlay);
     }
 }
 
@@ -361,6 +406,7 @@ pub fn handle_end_xingxiang_game(
         if let Ok(mut game) = q_game.get_mut(event.game_entity) {
             // 先清理所有子实体
             clear(&mut commands, &mut game);
+            despawn_overlay(&mut commands, &mut game.overlay);
             
             // 然后删除游戏实体本身
             commands.entity(event.game_entity).despawn();
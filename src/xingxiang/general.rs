@@ -22,6 +22,103 @@ impl XingxiangBoard {
     pub fn valid_pos1(&self, (x, y): (usize, usize)) -> bool {
         valid_coordinate(x, y) && self.pieces[x][y].is_none_or(|p| p.color == self.active_player)
     }
+    // 与 try_move 相同，但在这一步不合法时给出原因
+    pub fn checked_move(&self, step: XingxiangStep) -> Result<Self, &'static str> {
+        if self.end {
+            return Err("the game is over")
+        }
+        let mut pieces = self.pieces.clone();
+        let (x, y) = step.pos;
+        if !valid_coordinate(x, y) {
+            return Err("the cell is outside the board")
+        }
+        if pieces[x][y].is_some_and(|p| p.color == self.active_player.flip()) {
+            return Err("the cell holds an opponent's piece")
+        }
+        pieces[x][y] = Some(XingxiangPiece {
+            role: XingxiangPieceRole::Pawn,
+            color: self.active_player,
+        });
+        if let Some(((xp, yp), target)) = step.change {
+            if !valid_coordinate(xp, yp) {
+                return Err("the promoted cell is outside the board")
+            }
+            if target.color == self.active_player.flip() {
+                return Err("the promoted piece has the opponent's color")
+            }
+            if pieces[xp][yp].is_none_or(|p| p.color == self.active_player.flip()) {
+                return Err("there is no own piece on the promoted cell")
+            }
+            
+            let offsets = if target.role == XingxiangPieceRole::Pawn {
+                return Err("a piece cannot be promoted to a pawn");
+            } else {
+                target.role.offsets()
+            };
+            let d = diff((x, y), (xp, yp));
+            if !offsets.contains(&d) && d != (0, 0) {
+                return Err("the promoted piece does not reach the placed pawn")
+            }
+            if can_promote(&pieces, (xp, yp), target.role, self.active_player) {
+                // 如果生成王，则先将之前的王降为普通棋子
+                if target.role == XingxiangPieceRole::King {
+                    for p in pieces.iter_mut().flatten().flatten() {
+                        if p.color == self.active_player && p.role == XingxiangPieceRole::King {
+                            p.role = XingxiangPieceRole::Pawn;
+                        }
+                    }
+                }
+                pieces[xp][yp] = Some(target);
+                // 吃子
+                for offset in &offsets {
+                    if let Some((xpp, ypp)) = add_offset((xp, yp), *offset)
+                        && pieces[xpp][ypp].is_some_and(|p| p.color == self.active_player.flip())
+                    {
+                        try_eat(&mut pieces, (xpp, ypp), self.active_player);
+                    }
+                }
+            } else {
+                return Err("the promoted piece is not supported by three own pieces")
+            }
+        }
+        // 对家尝试吃王
+        if let Some(kp) = find_king_pos(&pieces, self.active_player) {
+            if can_eat_king(&pieces, kp, self.active_player.flip()) {
+                return Ok(Self {
+                    pieces,
+                    active_player: self.active_player.flip(),
+                    end: true,
+                    winner: Some(self.active_player.flip()),
+                    fullmove: match self.active_player {
+                        XingxiangPieceColor::Black => self.fullmove,
+                        XingxiangPieceColor::White => self.fullmove + 1,
+                    },
+                });
+            }
+        } else if self.fullmove >= 8 { // 第八回合后没有王直接判负，感谢 AI 发现的 bug (之前是只在等于时判断)
+            return Ok(Self { 
+                pieces, 
+                active_player: self.active_player.flip(), 
+                end: true, 
+                winner: Some(self.active_player.flip()),
+                fullmove: match self.active_player {
+                    XingxiangPieceColor::Black => self.fullmove,
+                    XingxiangPieceColor::White => self.fullmove + 1,
+                }, 
+            });
+        }
+        Ok(Self { 
+            pieces, 
+            active_player: self.active_player.flip(), 
+            end: false, 
+            winner: None, 
+            fullmove: match self.active_player {
+                XingxiangPieceColor::Black => self.fullmove,
+                XingxiangPieceColor::White => self.fullmove + 1,
+            }, 
+        })
+    }
+
     pub fn promotion_choices(&self, pos: (usize, usize), pos_pro: (usize, usize)) -> Vec<XingxiangPiece> {
         let mut res = Vec::new();
 
@@ -124,101 +221,10 @@ pub fn can_eat_king(pieces: &Vec<Vec<Option<XingxiangPiece>>>, king_pos: (usize,
 impl Board for XingxiangBoard {
     type S = XingxiangStep;
 
+    const NAME: &'static str = "xingxiang";
+
     fn try_move(&self, step: Self::S) -> Option<Self> where Self: Sized {
-        if self.end {
-            return None
-        }
-        let mut pieces = self.pieces.clone();
-        let (x, y) = step.pos;
-        if !valid_coordinate(x, y) {
-            return None 
-        }
-        if pieces[x][y].is_some_and(|p| p.color == self.active_player.flip()) {
-            return None 
-        }
-        pieces[x][y] = Some(XingxiangPiece {
-            role: XingxiangPieceRole::Pawn,
-            color: self.active_player,
-        });
-        if let Some(((xp, yp), target)) = step.change {
-            if !valid_coordinate(xp, yp) || target.color == self.active_player.flip() {
-                return None 
-            }
-            if pieces[xp][yp].is_none_or(|p| p.color == self.active_player.flip()) {
-                return None 
-            }
-            
-            let offsets = if target.role == XingxiangPieceRole::Pawn {
-                return None;
-            } else {
-                target.role.offsets()
-            };
-            let d = diff((x, y), (xp, yp));
-            if !offsets.contains(&d) && d != (0, 0) {
-                return None 
-            }
-            if can_promote(&pieces, (xp, yp), target.role, self.active_player) {
-                // 如果生成王，则先将之前的王降为普通棋子
-                if target.role == XingxiangPieceRole::King {
-                    for xpp in 0..BOARD_SIZE_I {
-                        for ypp in 0..BOARD_SIZE_J {
-                            if let Some(p) = &mut pieces[xpp][ypp] {
-                                if p.color == self.active_player && p.role == XingxiangPieceRole::King {
-                                    p.role = XingxiangPieceRole::Pawn;
-                                }
-                            }
-                        }
-                    }
-                }
-                pieces[xp][yp] = Some(target);
-                // 吃子
-                for offset in &offsets {
-                    if let Some((xpp, ypp)) = add_offset((xp, yp), *offset) {
-                        if pieces[xpp][ypp].is_some_and(|p| p.color == self.active_player.flip()) {
-                            try_eat(&mut pieces, (xpp, ypp), self.active_player);
-                        }
-                    }
-                }
-            } else {
-                return None
-            }
-        }
-        // 对家尝试吃王
-        if let Some(kp) = find_king_pos(&pieces, self.active_player) {
-            if can_eat_king(&pieces, kp, self.active_player.flip()) {
-                return Some(Self {
-                    pieces,
-                    active_player: self.active_player.flip(),
-                    end: true,
-                    winner: Some(self.active_player.flip()),
-                    fullmove: match self.active_player {
-                        XingxiangPieceColor::Black => self.fullmove,
-                        XingxiangPieceColor::White => self.fullmove + 1,
-                    },
-                });
-            }
-        } else if self.fullmove >= 8 { // 第八回合后没有王直接判负，感谢 AI 发现的 bug (之前是只在等于时判断)
-            return Some(Self { 
-                pieces, 
-                active_player: self.active_player.flip(), 
-                end: true, 
-                winner: Some(self.active_player.flip()),
-                fullmove: match self.active_player {
-                    XingxiangPieceColor::Black => self.fullmove,
-                    XingxiangPieceColor::White => self.fullmove + 1,
-                }, 
-            });
-        }
-        return Some(Self { 
-            pieces, 
-            active_player: self.active_player.flip(), 
-            end: false, 
-            winner: None, 
-            fullmove: match self.active_player {
-                XingxiangPieceColor::Black => self.fullmove,
-                XingxiangPieceColor::White => self.fullmove + 1,
-            }, 
-        });
+        self.checked_move(step).ok()
     }
 
     fn all_move(&self) -> Vec<Self::S> {
@@ -317,103 +323,68 @@ impl Board for XingxiangBoard {
         Some(format!("{}{}{}", (b'a' + x as u8) as char, y + 1, change))
     }
 
-    fn read_step(&self, s: String) -> Option<Self::S> {
-        if self.end {
-            return None;
-        }
-
-        let mut chars = s.chars();
-        
-        // 解析位置部分 (如 "a1")
-        if let Some(first_char) = chars.next() {
-            let first_char = first_char as u8;
-            if first_char >= b'a' && first_char < b'a' + BOARD_SIZE_I as u8 {
-                let x = (first_char - b'a') as usize;
-                let num_str: String = chars.by_ref().take_while(|c| c.is_digit(10)).collect();
-                if let Ok(y) = num_str.parse::<usize>() {
-                    if y == 0 { return None }
-                    let y = y - 1;
-                    if y < BOARD_SIZE_J {
-                        // 检查是否有变化部分
-                        let remaining: String = chars.collect();
-                        if remaining.is_empty() {
-                            // 只有位置，没有变化
-                            return Some(XingxiangStep {
-                                pos: (x, y),
-                                change: None,
-                            });
-                        } else {
-                            // 解析变化部分 (如 "Ra2")
-                            if remaining.len() >= 3 {
-                                let role_char = remaining.chars().next().unwrap();
-                                let role = match role_char {
-                                    'R' | 'r' => XingxiangPieceRole::Rook,
-                                    'N' | 'n' => XingxiangPieceRole::Knight,
-                                    'B' | 'b' => XingxiangPieceRole::Bishop,
-                                    'K' | 'k' => XingxiangPieceRole::King,
-                                    _ => return None,
-                                };
-                                
-                                let mut change_chars = remaining[1..].chars();
-                                if let Some(change_first_char) = change_chars.next() {
-                                    let change_first_char = change_first_char as u8;
-                                    if change_first_char >= b'a' && change_first_char < b'a' + BOARD_SIZE_I as u8 {
-                                        let xp = (change_first_char - b'a') as usize;
-                                        let change_num_str: String = change_chars.collect();
-                                        if let Ok(yp) = change_num_str.parse::<usize>() {
-                                            if yp == 0 { return None }
-                                            let yp = yp - 1;
-                                            if yp < BOARD_SIZE_J {
-                                                let color = if role_char.is_uppercase() {
-                                                    XingxiangPieceColor::White
-                                                } else {
-                                                    XingxiangPieceColor::Black
-                                                };
-                                                
-                                                return Some(XingxiangStep {
-                                                    pos: (x, y),
-                                                    change: Some(((xp, yp), XingxiangPiece {
-                                                        role,
-                                                        color,
-                                                    })),
-                                                });
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+    fn read_step(&self, s: String) -> Result<Self::S, XinqiParseError> {
+        // 位置部分 (如 "a1")
+        let read_pos = |chars: &mut std::iter::Peekable<std::str::Chars>, part: &str| -> Result<(usize, usize), XinqiParseError> {
+            let x = match chars.next() {
+                Some(c) if c >= 'a' && c < (b'a' + BOARD_SIZE_I as u8) as char => (c as u8 - b'a') as usize,
+                _ => return Err(invalid_step(&s, format!("expected a column letter in the {}", part))),
+            };
+            let mut num_str = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                num_str.push(c);
             }
-        }
+            match num_str.parse::<usize>() {
+                Ok(y) if (1..=BOARD_SIZE_J).contains(&y) => Ok((x, y - 1)),
+                _ => Err(invalid_step(&s, format!("expected a row number from 1 to {} in the {}", BOARD_SIZE_J, part))),
+            }
+        };
 
-        None
+        let mut chars = s.chars().peekable();
+        let pos = read_pos(&mut chars, "placed pawn")?;
+        // 变化部分 (如 "Ra2")，大写为白方，小写为黑方
+        let change = match chars.next() {
+            None => None,
+            Some(role_char) => {
+                let role = match role_char {
+                    'R' | 'r' => XingxiangPieceRole::Rook,
+                    'N' | 'n' => XingxiangPieceRole::Knight,
+                    'B' | 'b' => XingxiangPieceRole::Bishop,
+                    'K' | 'k' => XingxiangPieceRole::King,
+                    _ => return Err(invalid_step(&s, format!("unknown piece '{}'", role_char))),
+                };
+                let color = if role_char.is_uppercase() {
+                    XingxiangPieceColor::White
+                } else {
+                    XingxiangPieceColor::Black
+                };
+                let pos_pro = read_pos(&mut chars, "promotion")?;
+                if chars.next().is_some() {
+                    return Err(invalid_step(&s, "unexpected characters after the promotion"));
+                }
+                Some((pos_pro, XingxiangPiece { role, color }))
+            },
+        };
+        let step = XingxiangStep { pos, change };
+        self.checked_move(step).map_err(|reason| invalid_step(&s, format!("illegal move, {}", reason)))?;
+        Ok(step)
     }
 
-    fn read_fen(s: String) -> Option<Self> where Self: Sized {
-        if !s.starts_with("[xingxiang] ") {
-            return None;
-        }
-        
-        let parts: Vec<&str> = s.split_whitespace().collect();
+    fn read_fen(s: String) -> Result<Self, XinqiParseError> where Self: Sized {
+        let parts = split_with_columns(&s);
+        check_fen_header(&parts, Self::NAME)?;
         // 应该有4个部分: [xingxiang], pieces, active_player, fullmove
         if parts.len() != 4 {
-            return None;
+            return Err(XinqiParseError::new("fields", format!("expected 4 fields, found {}", parts.len())));
         }
         
         // 解析pieces部分
-        let pieces_str = parts[1];
-        let pieces_rows: Vec<&str> = pieces_str.split('/').collect();
-        if pieces_rows.len() != BOARD_SIZE_I {
-            return None;
-        }
+        let (pieces_column, pieces_str) = parts[1];
+        let pieces_rows = split_rows(pieces_str, pieces_column);
+        check_rows(&pieces_rows, "pieces", pieces_column, BOARD_SIZE_I, BOARD_SIZE_J)?;
         
         let mut pieces: Vec<Vec<Option<XingxiangPiece>>> = vec![vec![None; BOARD_SIZE_I]; BOARD_SIZE_J];
-        for (i, row_str) in pieces_rows.iter().enumerate() {
-            if row_str.len() != BOARD_SIZE_J {
-                return None;
-            }
+        for (i, (row_column, row_str)) in pieces_rows.iter().enumerate() {
             for (j, ch) in row_str.chars().enumerate() {
                 pieces[i][j] = match ch {
                     'P' => Some(XingxiangPiece { role: XingxiangPieceRole::Pawn, color: XingxiangPieceColor::White }),
@@ -427,26 +398,26 @@ impl Board for XingxiangBoard {
                     'b' => Some(XingxiangPiece { role: XingxiangPieceRole::Bishop, color: XingxiangPieceColor::Black }),
                     'k' => Some(XingxiangPiece { role: XingxiangPieceRole::King, color: XingxiangPieceColor::Black }),
                     '-' => None,
-                    _ => return None, // 无效字符
+                    _ => return Err(invalid_char("pieces", ch, row_column + j)),
                 };
             }
         }
         
         // 解析当前玩家
-        let active_player = match parts[2] {
+        let active_player = match parts[2].1 {
             "b" => XingxiangPieceColor::Black,
             "w" => XingxiangPieceColor::White,
-            _ => return None,
+            p => return Err(XinqiParseError::new("active player", format!("expected \"b\" or \"w\", found \"{}\"", p)).at_column(parts[2].0)),
         };
         
         // 解析回合数
-        let fullmove = match parts[3].parse::<usize>() {
-            Ok(n) => n,
-            Err(_) => return None,
+        let fullmove = match parts[3].1.parse::<usize>() {
+            Ok(n) if n >= 1 => n,
+            _ => return Err(XinqiParseError::new("fullmove", format!("expected a positive number, found \"{}\"", parts[3].1)).at_column(parts[3].0)),
         };
         
         // 创建并返回游戏状态实例
-        Some(Self {
+        Ok(Self {
             pieces,
             active_player,
             end: false,
@@ -476,4 +447,44 @@ impl Board for XingxiangBoard {
 
         format!("[xingxiang] {} {} {}", pieces, active_player, self.fullmove)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 把 FEN 中第 index 个空格分隔的部分换成 value，返回新的 FEN 和这一部分的列号
+    fn replace_field(fen: &str, index: usize, value: &str) -> (String, usize) {
+        let mut fields: Vec<&str> = fen.split(' ').collect();
+        let column = fields[..index].iter().map(|f| f.len() + 1).sum::<usize>() + 1;
+        fields[index] = value;
+        (fields.join(" "), column)
+    }
+
+    #[test]
+    fn parse_errors_name_the_field_and_column() {
+        let fen = XingxiangBoard::default().write_fen();
+        // 棋子部分第四行的第五格
+        let index = fen.find(' ').unwrap() + 1 + 3 * (BOARD_SIZE_J + 1) + 4;
+        let mut bad = fen.clone();
+        bad.replace_range(index..index + 1, "x");
+        let error = XingxiangBoard::read_fen(bad).err().unwrap();
+        assert_eq!((error.field, error.column), ("pieces", Some(index + 1)));
+        assert!(error.reason.contains("'x'"));
+
+        let (bad, column) = replace_field(&fen, 2, "r");
+        let error = XingxiangBoard::read_fen(bad).err().unwrap();
+        assert_eq!((error.field, error.column), ("active player", Some(column)));
+
+        let (bad, column) = replace_field(&fen, 3, "0");
+        let error = XingxiangBoard::read_fen(bad).err().unwrap();
+        assert_eq!((error.field, error.column), ("fullmove", Some(column)));
+
+        let board = XingxiangBoard::default();
+        for (step, reason) in [("z1", "column letter in the placed pawn"), ("a9", "row number"), ("a1Q", "unknown piece 'Q'"), ("a1Rz", "column letter in the promotion"), ("a1Ra1x", "after the promotion")] {
+            let error = board.read_step(step.to_string()).err().unwrap();
+            assert_eq!(error.field, "step");
+            assert!(error.reason.contains(reason), "{}: {}", step, error.reason);
+        }
+    }
+}
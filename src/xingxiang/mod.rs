@@ -1,15 +1,22 @@
+#[cfg(feature = "gui")]
 use bevy::prelude::*;
-use crate::{general::UpdateBoard, graphics::XinqiGraphicsPlugin, tree::game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}};
+#[cfg(feature = "gui")]
+use crate::{general::UpdateBoard, graphics::{overlay::BoardOverlay, XinqiGraphicsPlugin}, tree::game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}};
+#[cfg(feature = "gui")]
 use crate::xingxiang::{general::*, game::*, draw::*};
 
 pub mod general;
+#[cfg(feature = "gui")]
 pub mod game;
 pub mod ai;
 mod utils;
+#[cfg(feature = "gui")]
 mod draw;
 
+#[cfg(feature = "gui")]
 pub struct XingxiangPlugin;
 
+#[cfg(feature = "gui")]
 impl Plugin for XingxiangPlugin {
     fn build(&self, app: &mut App) {
         if !app.is_plugin_added::<XinqiGraphicsPlugin>() {
@@ -17,12 +24,14 @@ impl Plugin for XingxiangPlugin {
         }
         app.add_event::<EndXingxiangGame>();
         app.add_event::<UpdateBoard<XingxiangBoard>>();
+        app.init_resource::<BoardOverlay<XingxiangBoard>>();
         app.add_systems(Startup, xingxiang_setup);
         app.add_systems(Update, (handle_end_xingxiang_game, xingxiang_update).chain());
         app.add_systems(Update, handle_xingxiang_tree_events);
     }
 }
 
+#[cfg(feature = "gui")]
 fn handle_xingxiang_tree_events(
     mut q_xingxiang: Query<&mut XingxiangGame>,
     mut ew_update_board: EventWriter<UpdateBoard<XingxiangBoard>>,
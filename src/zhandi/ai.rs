@@ -1,6 +1,6 @@
-use crate::zhandi::{general::*, utils::*};
+use crate::{ai::Heuristic, zhandi::{general::*, utils::*}};
 
-pub fn evaluate(board: &ZhandiBoard) -> f32 {
+pub fn evaluate_with(board: &ZhandiBoard, temperature: f32) -> f32 {
     let mut res = 0.0;
     for x in 0..BOARD_DIAMETER {
         for y in 0..BOARD_DIAMETER {
@@ -25,10 +25,10 @@ pub fn evaluate(board: &ZhandiBoard) -> f32 {
         }
     }
     // 把目差估计转换为（先手方）胜率估计
-    1.0 / (1.0 + (-res / TEMERATURE).exp())
+    1.0 / (1.0 + (-res / temperature).exp())
 }
 
-const TEMERATURE: f32 = 10.0;
+pub const TEMPERATURE: f32 = 10.0;
 
 pub fn quick_move(board: &ZhandiBoard) -> Vec<ZhandiStep> {
     let mut step_results = Vec::new();
@@ -111,4 +111,16 @@ pub fn quick_move(board: &ZhandiBoard) -> Vec<ZhandiStep> {
                 .collect();
         },
     }
-}
\ No newline at end of file
+}
+
+impl Heuristic for ZhandiBoard {
+    const TEMPERATURE: f32 = TEMPERATURE;
+
+    fn evaluate_with(&self, temperature: f32) -> f32 {
+        evaluate_with(self, temperature)
+    }
+
+    fn quick_move(&self) -> Vec<ZhandiStep> {
+        quick_move(self)
+    }
+}
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 
 use crate::{
-    general::*, graphics::{entity::{CellCom, Shape}, interaction::{ClickEvent, DragEvent}}, net::message::{ReceiveRemoteStep, SendRemoteStep}, tree::game_tree::GameTree, zhandi::{draw::ZhandiTextureAssets, general::*, utils::*}
+    general::*, graphics::{entity::{CellCom, Shape}, interaction::{ClickEvent, DragEvent}, overlay::*}, net::message::{try_move_and_send, ReceiveRemoteStep, SendRemoteStep}, tree::game_tree::GameTree, zhandi::{draw::ZhandiTextureAssets, general::*, utils::*}
 };
 
 #[derive(Component)]
@@ -12,28 +12,30 @@ pub struct ZhandiGame {
     cells: Vec<Vec<Entity>>,
     pieces: Vec<Vec<Entity>>,
     background: Entity,
+    overlay: Vec<Entity>,
     updated: bool,
 
-    remote_play: Option<PlayerOrder>, 
+    seats: Seats,
+    clock: Option<Clock>,
 }
 
 impl ZhandiGame {
-    pub fn new(remote_play: Option<PlayerOrder>) -> Self {
+    pub fn with_board(board: ZhandiBoard, seats: Seats) -> Self {
+        Self::with_tree(GameTree::new(board), seats)
+    }
+    // 从游戏树的焦点处继续对局
+    pub fn with_tree(tree: GameTree<ZhandiBoard>, seats: Seats) -> Self {
         Self {
-            board: ZhandiBoard::default(),
-            tree: GameTree::new(ZhandiBoard::default()),
+            board: tree.board(),
+            tree,
             rect: Rect::from_center_size(Vec2::ZERO, Vec2::new(600.0, 533.0)),
             cells: vec![vec![Entity::PLACEHOLDER; BOARD_DIAMETER]; BOARD_DIAMETER],
             pieces: vec![vec![Entity::PLACEHOLDER; BOARD_DIAMETER]; BOARD_DIAMETER],
             background: Entity::PLACEHOLDER,
+            overlay: Vec::new(),
             updated: false,
-            remote_play,
-        }
-    }
-    pub fn try_move(&mut self, step: ZhandiStep) {
-        if self.tree.try_move(step) {
-            self.updated = false;
-            self.board = self.tree.board();
+            seats,
+            clock: None,
         }
     }
 }
@@ -48,6 +50,21 @@ impl Game for ZhandiGame {
     fn board(&self) -> &Self::B {
         &self.board
     }
+
+    fn seats(&mut self) -> &mut Seats {
+        &mut self.seats
+    }
+
+    fn clock(&mut self) -> &mut Option<Clock> {
+        &mut self.clock
+    }
+
+    fn try_move(&mut self, step: ZhandiStep) {
+        if self.tree.try_move(step) {
+            self.updated = false;
+            self.board = self.tree.board();
+        }
+    }
 }
 
 fn draw(
@@ -97,10 +114,7 @@ fn draw(
             };
             game.pieces[x][y] = piece;
 
-            let clickable = match game.remote_play {
-                Some(remote_player) => remote_player != game.board.get_active_player(),
-                None => true,
-            };
+            let clickable = game.accepts_local_input();
             let cell = commands.spawn((
                 CellCom {
                     shape: Shape::Circle { center, radius: dcell_diameter / 2.0 },
@@ -114,6 +128,29 @@ fn draw(
     }
 }
 
+// 在空点上显示 AI 候选着法的热度，并标出最佳着法
+fn draw_overlay(
+    commands: &mut Commands,
+    game: &mut ZhandiGame,
+    overlay: &BoardOverlay<ZhandiBoard>,
+) {
+    let moves = overlay.moves_for(&game.board);
+    let leftup = Vec2::new(game.rect.center().x - game.rect.size().x * (1.25 / 6.0), game.rect.center().y - game.rect.size().x * (2.165 / 6.0));
+    let cell_diameter = game.rect.size().x * (0.625 / 6.0);
+    let dcell_size = Vec2::new(cell_diameter, cell_diameter) * 0.8;
+    let (xdx, xdy, ydx, ydy) = (-cell_diameter / 2.0, cell_diameter * (3.0_f32.sqrt() / 2.0), cell_diameter, 0.0);
+    let center_of = |x: usize, y: usize| leftup + Vec2::new(x as f32 * xdx + y as f32 * ydx, x as f32 * xdy + y as f32 * ydy);
+    let cell_of = |step: &ZhandiStep| match step {
+        ZhandiStep::Pos(x, y) => Some((*x, *y)),
+    };
+    for ((x, y), heat) in heat_per_cell(moves, cell_of) {
+        game.overlay.push(spawn_heat_cell(commands, center_of(x, y), dcell_size, heat));
+    }
+    if let Some((x, y)) = moves.first().and_then(|(step, _)| cell_of(step)) {
+        game.overlay.push(spawn_best_marker(commands, center_of(x, y), dcell_size));
+    }
+}
+
 fn clear(
     commands: &mut Commands,
     game: &mut ZhandiGame,
@@ -142,46 +179,28 @@ fn update(
     er_remote: &mut EventReader<ReceiveRemoteStep>,
     ew_remote: &mut EventWriter<SendRemoteStep>,
     textures: &ZhandiTextureAssets,
+    overlay: &Res<BoardOverlay<ZhandiBoard>>,
 ) {
-    match game.remote_play {
-        Some(remote_player) => {
-            for event in er_click.read() {
-                if game.board.get_active_player() == remote_player {
-                    break;
-                }
-                for x in 0..BOARD_DIAMETER {
-                    for y in 0..BOARD_DIAMETER {
-                        if game.cells[x][y] == event.cell {
-                            let step = ZhandiStep::Pos(x, y);
-                            if let Some(step_str) = game.board.write_step(step) {
-                                game.try_move(step);
-                                ew_remote.write(SendRemoteStep { step: step_str });
-                            }
-                        }
-                    }
+    for event in er_click.read() {
+        if !game.accepts_local_input() {
+            break;
+        }
+        for x in 0..BOARD_DIAMETER {
+            for y in 0..BOARD_DIAMETER {
+                if game.cells[x][y] == event.cell {
+                    try_move_and_send(game, ZhandiStep::Pos(x, y), ew_remote);
                 }
             }
+        }
+    }
 
-            for event in er_remote.read() {
-                if game.board.get_active_player() == remote_player.flip() {
-                    break;
-                }
-                if let Some(step) = game.board.read_step(event.step.clone()) {
-                    game.try_move(step);
-                }
-            }
-        },
-        None => {
-            for event in er_click.read() {
-                for x in 0..BOARD_DIAMETER {
-                    for y in 0..BOARD_DIAMETER {
-                        if game.cells[x][y] == event.cell {
-                            game.try_move(ZhandiStep::Pos(x, y));
-                        }
-                    }
-                }
-            }
-        },
+    for event in er_remote.read() {
+        if !game.accepts_remote_input() {
+            break;
+        }
+        if let Ok(step) = game.board.read_step(event.step.clone()) {
+            game.try_move(step);
+        }
     }
     
     for event in er_update.read() {
@@ -189,6 +208,11 @@ fn update(
         game.board = event.new_board.clone();
     }
 
+    if !game.updated || overlay.is_changed() {
+        despawn_overlay(commands, &mut game.overlay);
+        draw_overlay(commands, game, overlay);
+    }
+
     if !game.updated {
         clear(commands, game);
         draw(commands, textures, game);
@@ -205,9 +229,10 @@ pub fn zhandi_update(
     mut er_remote: EventReader<ReceiveRemoteStep>,
     mut ew_remote: EventWriter<SendRemoteStep>,
     textures: Res<ZhandiTextureAssets>,
+    overlay: Res<BoardOverlay<ZhandiBoard>>,
 ) {
     for mut game in q_game.iter_mut() {
-        update(&mut commands, &mut game, &mut er_drag, &mut er_click, &mut er_update, &mut er_remote, &mut ew_remote, &textures);
+        update(&mut commands, &mut game, &mut er_drag, &mut er_click, &mut er_update, &mut er_remote, &mut ew_remote, &textures, &overlay);
     }
 }
 
@@ -239,6 +264,9 @@ fn cleanup_game_entities(commands: &mut Commands, game: &ZhandiGame) {
     if game.background != Entity::PLACEHOLDER {
         commands.entity(game.background).despawn();
     }
+    for e in &game.overlay {
+        commands.entity(*e).despawn();
+    }
     
     // 清理所有格子和棋子
     for x in 0..BOARD_DIAMETER {
@@ -31,8 +31,12 @@ pub struct ZhandiBoard {
     pub black_score: usize,
     pub white_score: usize,
     pub fullmove: usize,
+    pub komi: usize, // 后手额外计算的子数
 }
 
+// 默认的贴子数，与默认不同时写在 FEN 的第 4 部分
+pub const DEFAULT_KOMI: usize = 4;
+
 impl Default for ZhandiBoard {
     fn default() -> Self {
         Self { 
@@ -42,6 +46,57 @@ impl Default for ZhandiBoard {
             black_score: 0,
             white_score: 0,
             fullmove: 1,
+            komi: DEFAULT_KOMI,
+        }
+    }
+}
+
+impl ZhandiBoard {
+    pub fn with_komi(mut self, komi: usize) -> Self {
+        self.komi = komi;
+        self
+    }
+
+
+    // 与 try_move 相同，但在这一步不合法时给出原因
+    pub fn checked_move(&self, step: ZhandiStep) -> Result<Self, &'static str> {
+        if self.end {
+            return Err("the game is over")
+        }
+        match step {
+            ZhandiStep::Pos(x, y) => {
+                if !valid_coordinate(x, y) {
+                    return Err("the cell is outside the board")
+                }
+                if self.pieces[x][y].is_some() {
+                    return Err("the cell is occupied")
+                }
+                let mut pieces = self.pieces.clone();
+                pieces[x][y] = Some(self.active_player);
+
+                try_control(&mut pieces, self.active_player.flip());
+                
+                let end = !((0..BOARD_DIAMETER).any(|x| {
+                    (0..BOARD_DIAMETER).any(|y| {
+                        valid_coordinate(x, y) && pieces[x][y].is_none()
+                    })
+                }));
+                let black_score = pieces.iter().flatten().flatten().filter(|&&p| p == ZhandiPiece::Black).count();
+                let white_score = pieces.iter().flatten().flatten().filter(|&&p| p == ZhandiPiece::White).count();
+
+                Ok(ZhandiBoard { 
+                    pieces, 
+                    active_player: self.active_player.flip(), 
+                    end,
+                    black_score, 
+                    white_score, 
+                    fullmove: match self.active_player {
+                        ZhandiPiece::Black => self.fullmove,
+                        ZhandiPiece::White => self.fullmove + 1,
+                    },
+                    komi: self.komi,
+                })
+            },
         }
     }
 }
@@ -84,41 +139,10 @@ fn try_control(pieces: &mut Vec<Vec<Option<ZhandiPiece>>>, player: ZhandiPiece)
 impl Board for ZhandiBoard {
     type S = ZhandiStep;
 
-    fn try_move(&self, step: Self::S) -> Option<Self> where Self: Sized {
-        if self.end {
-            return None 
-        }
-        match step {
-            ZhandiStep::Pos(x, y) => {
-                if !valid_coordinate(x, y) || self.pieces[x][y].is_some() {
-                    return None 
-                }
-                let mut pieces = self.pieces.clone();
-                pieces[x][y] = Some(self.active_player);
+    const NAME: &'static str = "zhandi";
 
-                try_control(&mut pieces, self.active_player.flip());
-                
-                let end = !((0..BOARD_DIAMETER).any(|x| {
-                    (0..BOARD_DIAMETER).any(|y| {
-                        valid_coordinate(x, y) && pieces[x][y].is_none()
-                    })
-                }));
-                let black_score = pieces.iter().flatten().flatten().filter(|&&p| p == ZhandiPiece::Black).count();
-                let white_score = pieces.iter().flatten().flatten().filter(|&&p| p == ZhandiPiece::White).count();
-
-                Some(ZhandiBoard { 
-                    pieces, 
-                    active_player: self.active_player.flip(), 
-                    end,
-                    black_score, 
-                    white_score, 
-                    fullmove: match self.active_player {
-                        ZhandiPiece::Black => self.fullmove,
-                        ZhandiPiece::White => self.fullmove + 1,
-                    },
-                })
-            },
-        }
+    fn try_move(&self, step: Self::S) -> Option<Self> where Self: Sized {
+        self.checked_move(step).ok()
     }
 
     fn all_move(&self) -> Vec<Self::S> {
@@ -141,9 +165,9 @@ impl Board for ZhandiBoard {
     }
 
     fn get_winner(&self) -> Option<PlayerOrder> {
-        if self.black_score > self.white_score + 4 {
+        if self.black_score > self.white_score + self.komi {
             Some(PlayerOrder::First)
-        } else if self.black_score == self.white_score + 4 {
+        } else if self.black_score == self.white_score + self.komi {
             None
         } else {
             Some(PlayerOrder::Second)
@@ -152,9 +176,9 @@ impl Board for ZhandiBoard {
 
     fn game_info(&self) -> &str {
         if self.end {
-            if self.black_score > self.white_score + 4 {
+            if self.black_score > self.white_score + self.komi {
                 "Black Win"
-            } else if self.black_score == self.white_score + 4 {
+            } else if self.black_score == self.white_score + self.komi {
                 "Draw"
             } else {
                 "White Win"
@@ -178,40 +202,26 @@ impl Board for ZhandiBoard {
         }
     }
 
-    fn read_step(&self, s: String) -> Option<Self::S> {
-        if self.end {
-            return None;
-        }
-
+    fn read_step(&self, s: String) -> Result<Self::S, XinqiParseError> {
         let mut chars = s.chars();
-
-        if let Some(first_char) = chars.next() {
-            let first_char = first_char as u8;
-            if first_char >= b'a' && first_char < b'a' + BOARD_DIAMETER as u8 {
-                let x = (first_char - b'a') as usize;
-                let x = BOARD_DIAMETER - 1 - x;
-                let num_str: String = chars.collect();
-                if let Ok(y) = num_str.parse::<usize>() {
-                    if y == 0 { return None } // 由于类型限制必须单独处理
-                    let y = y - 1;
-                    if x < BOARD_RADIUS {
-                        if y < x + BOARD_RADIUS {
-                            return Some(ZhandiStep::Pos(x, y));
-                        } else {
-                            return None;
-                        }
-                    } else {
-                        if x + y < BOARD_DIAMETER + BOARD_RADIUS - 1 {
-                            return Some(ZhandiStep::Pos(x, y + (x + 1 - BOARD_RADIUS)));
-                        } else {
-                            return None;
-                        }
-                    }
-                }
-            }
-        }
-
-        None
+        let x = match chars.next() {
+            Some(c) if c >= 'a' && c < (b'a' + BOARD_DIAMETER as u8) as char => BOARD_DIAMETER - 1 - (c as u8 - b'a') as usize,
+            _ => return Err(invalid_step(&s, "expected a column letter")),
+        };
+        let num_str: String = chars.collect();
+        // 每一列的格数不同
+        let len = if x < BOARD_RADIUS { x + BOARD_RADIUS } else { BOARD_DIAMETER + BOARD_RADIUS - 1 - x };
+        let y = match num_str.parse::<usize>() {
+            Ok(y) if (1..=len).contains(&y) => y - 1,
+            _ => return Err(invalid_step(&s, format!("expected a row number from 1 to {}", len))),
+        };
+        let step = if x < BOARD_RADIUS {
+            ZhandiStep::Pos(x, y)
+        } else {
+            ZhandiStep::Pos(x, y + (x + 1 - BOARD_RADIUS))
+        };
+        self.checked_move(step).map_err(|reason| invalid_step(&s, format!("illegal move, {}", reason)))?;
+        Ok(step)
     }
 
     fn write_step(&self, step: Self::S) -> Option<String> {
@@ -230,29 +240,21 @@ impl Board for ZhandiBoard {
         }
     }
 
-    fn read_fen(s: String) -> Option<Self> where Self: Sized {
-        if !s.starts_with("[zhandi] ") {
-            return None;
-        }
-        
-        let parts: Vec<&str> = s.split_whitespace().collect();
-        // 应该有3个部分: [zhandi], pieces, active_player
-        if parts.len() != 3 {
-            return None;
+    fn read_fen(s: String) -> Result<Self, XinqiParseError> where Self: Sized {
+        let parts = split_with_columns(&s);
+        check_fen_header(&parts, Self::NAME)?;
+        // 应该有3个部分: [zhandi], pieces, active_player，贴子数与默认不同时还有第4个部分 komi
+        if parts.len() != 3 && parts.len() != 4 {
+            return Err(XinqiParseError::new("fields", format!("expected 3 or 4 fields, found {}", parts.len())));
         }
 
         // 解析pieces部分
-        let pieces_str = parts[1];
-        let pieces_rows: Vec<&str> = pieces_str.split('/').collect();
-        if pieces_rows.len() != BOARD_DIAMETER {
-            return None;
-        }
+        let (pieces_column, pieces_str) = parts[1];
+        let pieces_rows = split_rows(pieces_str, pieces_column);
+        check_rows(&pieces_rows, "pieces", pieces_column, BOARD_DIAMETER, BOARD_DIAMETER)?;
         
         let mut pieces: Vec<Vec<Option<ZhandiPiece>>> = vec![vec![None; BOARD_DIAMETER]; BOARD_DIAMETER];
-        for (i, row_str) in pieces_rows.iter().enumerate() {
-            if row_str.len() != BOARD_DIAMETER {
-                return None;
-            }
+        for (i, (row_column, row_str)) in pieces_rows.iter().enumerate() {
             for (j, ch) in row_str.chars().enumerate() {
                 if !valid_coordinate(i, j) {
                     pieces[i][j] = None;
@@ -261,17 +263,24 @@ impl Board for ZhandiBoard {
                         'b' => Some(ZhandiPiece::Black),
                         'w' => Some(ZhandiPiece::White),
                         '-' => None,
-                        _ => return None, // 无效字符
+                        _ => return Err(invalid_char("pieces", ch, row_column + j)),
                     }
                 }
             }
         }
 
         // 解析当前玩家
-        let active_player = match parts[2] {
+        let active_player = match parts[2].1 {
             "b" => ZhandiPiece::Black,
             "w" => ZhandiPiece::White,
-            _ => return None,
+            p => return Err(XinqiParseError::new("active player", format!("expected \"b\" or \"w\", found \"{}\"", p)).at_column(parts[2].0)),
+        };
+
+        let komi = match parts.get(3) {
+            Some(&(column, komi)) => komi.parse::<usize>().map_err(|_| {
+                XinqiParseError::new("komi", format!("expected a number, found \"{}\"", komi)).at_column(column)
+            })?,
+            None => DEFAULT_KOMI,
         };
 
         let end = !((0..BOARD_DIAMETER).any(|x| {
@@ -283,13 +292,14 @@ impl Board for ZhandiBoard {
         let white_score = pieces.iter().flatten().flatten().filter(|&&p| p == ZhandiPiece::White).count();
 
         // 创建并返回游戏状态实例
-        Some(Self {
+        Ok(Self {
             pieces,
             active_player,
             end,
             black_score,
             white_score,
             fullmove: 1,
+            komi,
         })
     }
 
@@ -313,6 +323,52 @@ impl Board for ZhandiBoard {
             ZhandiPiece::White => "w",
         };
 
-        format!("[zhandi] {} {}", pieces, active_player)
+        if self.komi == DEFAULT_KOMI {
+            format!("[zhandi] {} {}", pieces, active_player)
+        } else {
+            format!("[zhandi] {} {} {}", pieces, active_player, self.komi)
+        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 把 FEN 中第 index 个空格分隔的部分换成 value，返回新的 FEN 和这一部分的列号
+    fn replace_field(fen: &str, index: usize, value: &str) -> (String, usize) {
+        let mut fields: Vec<&str> = fen.split(' ').collect();
+        let column = fields[..index].iter().map(|f| f.len() + 1).sum::<usize>() + 1;
+        fields[index] = value;
+        (fields.join(" "), column)
+    }
+
+    #[test]
+    fn parse_errors_name_the_field_and_column() {
+        let fen = ZhandiBoard::default().write_fen();
+        // 棋子部分正中间的一格
+        let index = fen.find(' ').unwrap() + 1 + (BOARD_RADIUS - 1) * (BOARD_DIAMETER + 1) + BOARD_RADIUS - 1;
+        let mut bad = fen.clone();
+        bad.replace_range(index..index + 1, "x");
+        let error = ZhandiBoard::read_fen(bad).err().unwrap();
+        assert_eq!((error.field, error.column), ("pieces", Some(index + 1)));
+        assert!(error.reason.contains("'x'"));
+
+        let (bad, column) = replace_field(&fen, 2, "r");
+        let error = ZhandiBoard::read_fen(bad).err().unwrap();
+        assert_eq!((error.field, error.column), ("active player", Some(column)));
+
+        let (bad, column) = replace_field(&format!("{} 6", fen), 3, "six");
+        let error = ZhandiBoard::read_fen(bad).err().unwrap();
+        assert_eq!((error.field, error.column), ("komi", Some(column)));
+
+        let board = ZhandiBoard::default();
+        let first = board.write_step(board.all_move()[0]).unwrap();
+        let played = board.try_move(board.all_move()[0]).unwrap();
+        for (board, step, reason) in [(&board, "z1", "column letter"), (&board, "a9", "row number"), (&played, first.as_str(), "illegal move")] {
+            let error = board.read_step(step.to_string()).err().unwrap();
+            assert_eq!(error.field, "step");
+            assert!(error.reason.contains(reason), "{}: {}", step, error.reason);
+        }
+    }
+}
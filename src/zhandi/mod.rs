@@ -1,15 +1,22 @@
+#[cfg(feature = "gui")]
 use bevy::prelude::*;
-use crate::{general::UpdateBoard, graphics::XinqiGraphicsPlugin, tree::game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}};
+#[cfg(feature = "gui")]
+use crate::{general::UpdateBoard, graphics::{overlay::BoardOverlay, XinqiGraphicsPlugin}, tree::game_tree_event::{DeleteVariationEvent, MoveToNodeEvent}};
+#[cfg(feature = "gui")]
 use crate::zhandi::{general::*, game::*, draw::*};
 
 pub mod general;
+#[cfg(feature = "gui")]
 pub mod game;
 pub mod ai;
+#[cfg(feature = "gui")]
 mod draw;
 mod utils;
 
+#[cfg(feature = "gui")]
 pub struct ZhandiPlugin;
 
+#[cfg(feature = "gui")]
 impl Plugin for ZhandiPlugin {
     fn build(&self, app: &mut App) {
         if !app.is_plugin_added::<XinqiGraphicsPlugin>() {
@@ -17,12 +24,14 @@ impl Plugin for ZhandiPlugin {
         }
         app.add_event::<EndZhandiGame>();
         app.add_event::<UpdateBoard<ZhandiBoard>>();
+        app.init_resource::<BoardOverlay<ZhandiBoard>>();
         app.add_systems(Startup, zhandi_setup);
         app.add_systems(Update, (handle_end_zhandi_game, zhandi_update).chain());
         app.add_systems(Update, handle_zhandi_tree_events);
     }
 }
 
+#[cfg(feature = "gui")]
 fn handle_zhandi_tree_events(
     mut q_zhandi: Query<&mut ZhandiGame>,
     mut ew_update_board: EventWriter<UpdateBoard<ZhandiBoard>>,